@@ -9,17 +9,24 @@
 #![forbid(unsafe_code)]
 #![warn(clippy::pedantic, missing_debug_implementations, missing_docs)]
 
-use heck::ToSnakeCase;
+use heck::{ToShoutySnakeCase, ToSnakeCase};
 use proc_macro::TokenStream;
 use proc_macro_crate::{FoundCrate, crate_name};
 use proc_macro2::Span;
-use quote::quote;
-use syn::{Attribute, Data, DeriveInput, Error, Ident, Lit, LitStr, Type, parse_macro_input};
+use quote::{format_ident, quote};
+use syn::{
+    Attribute, Data, DeriveInput, Error, ExprPath, Field, Fields, Ident, Index, Lit, LitStr,
+    Member, Meta, Token, Type, parse_macro_input, punctuated::Punctuated,
+};
 
 /// Represents the `minidb` attribute on a struct
 #[derive(Debug, Default)]
 struct MiniDBStructAttributes {
     name: Option<String>,
+    comment: Option<String>,
+    builder: bool,
+    fields: bool,
+    singleton: bool,
 }
 
 impl MiniDBStructAttributes {
@@ -37,9 +44,25 @@ impl MiniDBStructAttributes {
                         } else {
                             return Err(meta.error("Expected string literal for `name` attribute"));
                         }
+                    } else if meta.path.is_ident("comment") {
+                        let value: Lit = meta.value()?.parse()?;
+
+                        if let Lit::Str(s) = value {
+                            struct_attrs.comment = Some(s.value());
+                        } else {
+                            return Err(
+                                meta.error("Expected string literal for `comment` attribute")
+                            );
+                        }
+                    } else if meta.path.is_ident("builder") {
+                        struct_attrs.builder = true;
+                    } else if meta.path.is_ident("fields") {
+                        struct_attrs.fields = true;
+                    } else if meta.path.is_ident("singleton") {
+                        struct_attrs.singleton = true;
                     } else {
                         return Err(meta.error(
-                            "Unknown minidb attribute on struct. Expected one of [`name`]",
+                            "Unknown minidb attribute on struct. Expected one of [`name`, `comment`, `builder`, `fields`, `singleton`]",
                         ));
                     }
 
@@ -52,23 +75,394 @@ impl MiniDBStructAttributes {
     }
 }
 
+// each flag is an independent marker attribute
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Default)]
 struct MiniDBFieldAttributes {
     is_key: bool,
+    is_transient: bool,
+    is_serde_skipped: bool,
+    is_redacted: bool,
+    is_indexed: bool,
+    is_unique: bool,
+    is_fulltext: bool,
+    rehydrate: Option<String>,
+    computed: Option<String>,
+    comment: Option<String>,
 }
 
 impl MiniDBFieldAttributes {
-    fn from_attributes(attrs: &[Attribute]) -> Self {
+    fn from_attributes(attrs: &[Attribute]) -> Result<Self, Error> {
         let mut field_attrs = Self::default();
 
         for attr in attrs {
             if attr.path().is_ident("key") {
                 // #[key]
                 field_attrs.is_key = true;
+            } else if attr.path().is_ident("index") {
+                // #[index]
+                field_attrs.is_indexed = true;
+            } else if attr.path().is_ident("unique") {
+                // #[unique], backed by an index
+                field_attrs.is_indexed = true;
+                field_attrs.is_unique = true;
+            } else if attr.path().is_ident("fulltext") {
+                // #[fulltext]
+                field_attrs.is_fulltext = true;
+            } else if attr.path().is_ident("serde") {
+                // #[serde(skip)] or #[serde(skip_deserializing)]
+                let metas =
+                    attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+                field_attrs.is_serde_skipped |= metas.iter().any(|meta| {
+                    meta.path().is_ident("skip") || meta.path().is_ident("skip_deserializing")
+                });
+            } else if attr.path().is_ident("minidb") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("transient") {
+                        field_attrs.is_transient = true;
+                    } else if meta.path.is_ident("redact") {
+                        field_attrs.is_redacted = true;
+                    } else if meta.path.is_ident("rehydrate") {
+                        let value: Lit = meta.value()?.parse()?;
+
+                        if let Lit::Str(s) = value {
+                            field_attrs.rehydrate = Some(s.value());
+                        } else {
+                            return Err(
+                                meta.error("Expected string literal for `rehydrate` attribute")
+                            );
+                        }
+                    } else if meta.path.is_ident("computed") {
+                        let value: Lit = meta.value()?.parse()?;
+
+                        if let Lit::Str(s) = value {
+                            field_attrs.computed = Some(s.value());
+                        } else {
+                            return Err(
+                                meta.error("Expected string literal for `computed` attribute")
+                            );
+                        }
+                    } else if meta.path.is_ident("comment") {
+                        let value: Lit = meta.value()?.parse()?;
+
+                        if let Lit::Str(s) = value {
+                            field_attrs.comment = Some(s.value());
+                        } else {
+                            return Err(
+                                meta.error("Expected string literal for `comment` attribute")
+                            );
+                        }
+                    } else {
+                        return Err(meta.error(
+                            "Unknown minidb attribute on field. Expected one of [`transient`, `rehydrate`, `computed`, `redact`, `comment`]",
+                        ));
+                    }
+
+                    Ok(())
+                })?;
             }
         }
 
-        field_attrs
+        Ok(field_attrs)
+    }
+
+    /// Validates the `transient` attribute and builds the statement that rehydrates the field, if any
+    fn rehydration(
+        &self,
+        field: &Field,
+        member: &Member,
+    ) -> Result<Option<proc_macro2::TokenStream>, Error> {
+        if self.rehydrate.is_some() && !self.is_transient {
+            return Err(Error::new_spanned(
+                field,
+                "`rehydrate` requires the field to be `transient`",
+            ));
+        }
+
+        if !self.is_transient {
+            return Ok(None);
+        }
+
+        if self.is_key {
+            return Err(Error::new_spanned(
+                field,
+                "The #[key] field can't be `transient`",
+            ));
+        }
+
+        if !self.is_serde_skipped {
+            return Err(Error::new_spanned(
+                field,
+                "`transient` fields must also be marked with #[serde(skip)]",
+            ));
+        }
+
+        let Some(rehydrate) = &self.rehydrate else {
+            return Ok(None);
+        };
+        let Ok(rehydrate) = syn::parse_str::<ExprPath>(rehydrate) else {
+            return Err(Error::new_spanned(
+                field,
+                "`rehydrate` must be a path to a function",
+            ));
+        };
+
+        Ok(Some(quote! {
+            let value = #rehydrate(self);
+            self.#member = value;
+        }))
+    }
+
+    /// Builds the `(field, serialized value)` pair of an `#[index]` field
+    fn index_value(
+        &self,
+        crate_path: &proc_macro2::TokenStream,
+        field: &Field,
+        member: &Member,
+    ) -> Result<Option<proc_macro2::TokenStream>, Error> {
+        if !self.is_indexed {
+            return Ok(None);
+        }
+
+        if self.is_transient {
+            return Err(Error::new_spanned(
+                field,
+                "`transient` fields aren't stored so they can't be indexed",
+            ));
+        }
+
+        let field_name = member_name(member);
+        Ok(Some(quote! {
+            (#field_name, #crate_path::__private::postcard::to_stdvec(&self.#member)?)
+        }))
+    }
+
+    /// Builds the expression returning the text of a `#[fulltext]` field
+    fn fulltext_value(
+        &self,
+        field: &Field,
+        member: &Member,
+    ) -> Result<Option<proc_macro2::TokenStream>, Error> {
+        if !self.is_fulltext {
+            return Ok(None);
+        }
+
+        if self.is_transient {
+            return Err(Error::new_spanned(
+                field,
+                "`transient` fields aren't stored so they can't be searched",
+            ));
+        }
+
+        Ok(Some(
+            quote! { ::std::convert::AsRef::<str>::as_ref(&self.#member) },
+        ))
+    }
+
+    /// Builds the `minidb::Field` constant of a stored named field, for typed queries
+    fn typed_field(
+        &self,
+        struct_name: &Ident,
+        crate_path: &proc_macro2::TokenStream,
+        field: &Field,
+        member: &Member,
+    ) -> Option<proc_macro2::TokenStream> {
+        if !matches!(member, Member::Named(_)) || self.is_transient || self.is_serde_skipped {
+            return None;
+        }
+
+        let field_name = member_name(member);
+        let const_name = format_ident!(
+            "{}",
+            field_name.trim_start_matches("r#").to_shouty_snake_case()
+        );
+        let vis = &field.vis;
+        let ty = &field.ty;
+        let doc = format!("The `{field_name}` field, for typed queries");
+
+        Some(quote! {
+            #[doc = #doc]
+            #vis const #const_name: #crate_path::Field<#struct_name, #ty> = #crate_path::Field::new(#field_name, {
+                fn get(item: &#struct_name) -> &#ty {
+                    &item.#member
+                }
+                get
+            });
+        })
+    }
+
+    /// Validates the `computed` attribute and builds the statement that recalculates the field, if any
+    fn computation(
+        &self,
+        field: &Field,
+        member: &Member,
+    ) -> Result<Option<proc_macro2::TokenStream>, Error> {
+        let Some(computed) = &self.computed else {
+            return Ok(None);
+        };
+
+        if self.is_key {
+            return Err(Error::new_spanned(
+                field,
+                "The #[key] field can't be `computed`",
+            ));
+        }
+
+        if self.is_transient {
+            return Err(Error::new_spanned(
+                field,
+                "`computed` fields are stored, use `rehydrate` for `transient` fields",
+            ));
+        }
+
+        let Ok(computed) = syn::parse_str::<ExprPath>(computed) else {
+            return Err(Error::new_spanned(
+                field,
+                "`computed` must be a path to a function",
+            ));
+        };
+
+        Ok(Some(quote! {
+            let value = #computed(self);
+            self.#member = value;
+        }))
+    }
+}
+
+/// What the derive collected from the fields of a struct
+struct TableFields {
+    key: Member,
+    rehydrations: Vec<proc_macro2::TokenStream>,
+    computations: Vec<proc_macro2::TokenStream>,
+    field_comments: Vec<proc_macro2::TokenStream>,
+    redacted: Vec<String>,
+    redactions: Vec<proc_macro2::TokenStream>,
+    indexes: Vec<String>,
+    unique: Vec<String>,
+    index_values: Vec<proc_macro2::TokenStream>,
+    fulltext: Vec<String>,
+    fulltext_values: Vec<proc_macro2::TokenStream>,
+    debug_fields: Vec<proc_macro2::TokenStream>,
+    field_consts: Vec<proc_macro2::TokenStream>,
+}
+
+impl TableFields {
+    fn from_fields(
+        struct_name: &Ident,
+        crate_path: &proc_macro2::TokenStream,
+        fields: &Fields,
+    ) -> Result<Self, Error> {
+        let mut id_field_member: Option<Member> = None;
+        let mut num_keys_fields = 0;
+        let mut rehydrations = Vec::new();
+        let mut computations = Vec::new();
+        let mut field_comments = Vec::new();
+        let mut redacted = Vec::new();
+        let mut redactions = Vec::new();
+        let mut indexes = Vec::new();
+        let mut unique = Vec::new();
+        let mut index_values = Vec::new();
+        let mut fulltext = Vec::new();
+        let mut fulltext_values = Vec::new();
+        let mut debug_fields = Vec::new();
+        let mut field_consts = Vec::new();
+
+        for (i, field) in fields.iter().enumerate() {
+            let member = match field.ident.as_ref() {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(i)),
+            };
+
+            let ty = &field.ty;
+            let field_attrs = MiniDBFieldAttributes::from_attributes(&field.attrs)?;
+
+            if let Some(rehydration) = field_attrs.rehydration(field, &member)? {
+                rehydrations.push(rehydration);
+            }
+
+            if let Some(computation) = field_attrs.computation(field, &member)? {
+                computations.push(computation);
+            }
+
+            let field_name = member_name(&member);
+            if let Some(comment) = &field_attrs.comment {
+                field_comments.push(quote! { (#field_name, #comment) });
+            }
+
+            if let Some(field_const) =
+                field_attrs.typed_field(struct_name, crate_path, field, &member)
+            {
+                field_consts.push(field_const);
+            }
+
+            if let Some(index_value) = field_attrs.index_value(crate_path, field, &member)? {
+                if field_attrs.is_unique {
+                    unique.push(field_name.clone());
+                }
+                indexes.push(field_name.clone());
+                index_values.push(index_value);
+            }
+
+            if let Some(fulltext_value) = field_attrs.fulltext_value(field, &member)? {
+                fulltext.push(field_name.clone());
+                fulltext_values.push(fulltext_value);
+            }
+
+            let debug_value = if field_attrs.is_redacted {
+                redacted.push(field_name.clone());
+                redactions.push(quote! { self.#member = ::std::default::Default::default(); });
+                quote! { &"[REDACTED]" }
+            } else {
+                quote! { &self.#member }
+            };
+            debug_fields.push(match &member {
+                Member::Named(_) => quote! { .field(#field_name, #debug_value) },
+                Member::Unnamed(_) => quote! { .field(#debug_value) },
+            });
+
+            if field_attrs.is_key {
+                num_keys_fields += 1;
+                id_field_member = Some(member);
+
+                let is_id_type = is_id_type(ty);
+                if !is_id_type {
+                    return Err(Error::new_spanned(
+                        ty,
+                        "The #[key] field must be of type `String`.",
+                    ));
+                }
+            }
+        }
+
+        if num_keys_fields != 1 {
+            return Err(Error::new_spanned(
+                struct_name,
+                "A struct deriving `Table` must have exactly one field marked with #[key].",
+            ));
+        }
+
+        let Some(key) = id_field_member else {
+            return Err(Error::new_spanned(
+                fields,
+                "A struct deriving `Table` must have exactly one field marked with #[key].",
+            ));
+        };
+
+        Ok(Self {
+            key,
+            rehydrations,
+            computations,
+            field_comments,
+            redacted,
+            redactions,
+            indexes,
+            unique,
+            index_values,
+            fulltext,
+            fulltext_values,
+            debug_fields,
+            field_consts,
+        })
     }
 }
 
@@ -79,10 +473,34 @@ impl MiniDBFieldAttributes {
 /// ### Struct
 ///
 /// * `#[minidb(name = "custom_name")]` - Sets a different name for the struct/table. Names get converted to `snake_case`
+/// * `#[minidb(comment = "...")]` - Describes the table, the comment is stored in the database metadata
+/// * `#[minidb(builder)]` - Generates `Struct::builder()` returning a `StructBuilder` with a setter per field, unset
+///   fields (the key included, so an ID is generated on insert) get their `Default` value. Only for non-generic
+///   structs with named fields
+/// * `#[minidb(fields)]` - Generates the typed field constants described below. Only for non-generic structs with named
+///   fields
+/// * `#[minidb(singleton)]` - Makes the table hold only the record stored under `minidb::SINGLETON_ID`, used through
+///   `MiniDB::singleton`
 ///
 /// ### Field
 ///
-/// * `#[key]` - Sets the field as a primary key
+/// * `#[key]` - Sets the field as a primary key, works on named fields and on positional fields of tuple structs
+/// * `#[minidb(transient)]` - Marks the field as runtime-only, it must also be marked `#[serde(skip)]` since derive macros can't add attributes
+/// * `#[minidb(comment = "...")]` - Describes the field, the comment is stored in the database metadata
+/// * `#[minidb(transient, rehydrate = "path::to::fn")]` - Same as above but calls `fn(&Self) -> FieldType` after every read to rebuild the field
+/// * `#[minidb(computed = "path::to::fn")]` - Calls `fn(&Self) -> FieldType` before every write and stores the result, so scans can use it
+/// * `#[index]` - Keeps a secondary index of the field, so `MiniDB::find_by_index` can look records up by its value
+/// * `#[unique]` - Same as `#[index]` but writes fail with `Error::UniqueViolation` if another record has the same value
+/// * `#[fulltext]` - Adds the words of the field to the full-text index searched by `MiniDB::search`, the field must
+///   implement `AsRef<str>`
+/// * `#[minidb(redact)]` - Masks the field in exports and in a generated `Debug` impl, so don't also derive `Debug` on the struct.
+///   Exported records get its `Default` value instead, so the field must implement `Default`
+///
+/// ## Typed fields
+///
+/// With `#[minidb(fields)]` the struct gets a `minidb::Field` constant per stored field, named after it in
+/// `SCREAMING_SNAKE_CASE` (`Person::AGE` for `age`), for `MiniDB::query`. They are opt-in since they share the
+/// namespace of the struct's own associated items
 ///
 /// ## Example
 ///
@@ -97,116 +515,325 @@ impl MiniDBFieldAttributes {
 ///     name: String,
 ///     age: u8,
 /// }
+///
+/// // tuple structs mark the positional key field instead
+/// #[derive(Table)]
+/// struct Tag(#[key] String, String);
 /// ```
-#[proc_macro_derive(Table, attributes(serde, minidb, key))]
+#[proc_macro_derive(Table, attributes(serde, minidb, key, index, unique, fulltext))]
 pub fn table_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+
+    match table_derive_impl(&input) {
+        Ok(out) => out.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn table_derive_impl(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
     let struct_name = &input.ident;
     let struct_generics = &input.generics;
     let (impl_generics, ty_generics, where_clause) = struct_generics.split_for_impl();
 
-    let struct_attrs = match MiniDBStructAttributes::from_attributes(&input.attrs) {
-        Ok(attrs) => attrs,
-        Err(e) => return e.to_compile_error().into(),
-    };
+    let struct_attrs = MiniDBStructAttributes::from_attributes(&input.attrs)?;
     let table_name_str = if let Some(custom_name) = struct_attrs.name {
         custom_name.to_snake_case()
     } else {
         struct_name.to_string().to_snake_case()
     };
     let table_name = Lit::Str(LitStr::new(&table_name_str, Span::call_site()));
-    let Ok(found_crate) = crate_name("minidb") else {
-        return Error::new_spanned(struct_name, "minidb crate not found in dependencies")
-            .to_compile_error()
-            .into();
-    };
-    let crate_path = match found_crate {
-        FoundCrate::Itself => quote!(minidb),
-        FoundCrate::Name(name) => {
-            let ident = Ident::new(&name, Span::call_site());
-            quote!(#ident)
-        }
-    };
-    let fields = match &input.data {
-        Data::Struct(s) => &s.fields,
-        Data::Enum(e) => {
-            return Error::new_spanned(e.enum_token, "Table derive macro only supports structs")
-                .to_compile_error()
-                .into();
-        }
-        Data::Union(u) => {
-            return Error::new_spanned(u.union_token, "Table derive macro only supports structs")
-                .to_compile_error()
-                .into();
-        }
+    let crate_path = minidb_crate_path(struct_name)?;
+    let fields = struct_fields(input)?;
+
+    let TableFields {
+        key: id_field_member,
+        rehydrations,
+        computations,
+        field_comments,
+        redacted,
+        redactions,
+        indexes,
+        unique,
+        index_values,
+        fulltext,
+        fulltext_values,
+        debug_fields,
+        field_consts,
+    } = TableFields::from_fields(struct_name, &crate_path, fields)?;
+
+    let has_computed = !computations.is_empty();
+    let singleton = struct_attrs.singleton;
+
+    let comment = if let Some(comment) = &struct_attrs.comment {
+        quote! { Some(#comment) }
+    } else {
+        quote! { None }
     };
 
-    let mut id_field_ident: Option<Ident> = None;
-    let mut num_keys_fields = 0;
+    let table_model_impl = quote! {
+        impl #impl_generics #crate_path::Table for #struct_name #ty_generics #where_clause {
+            const TABLE: #crate_path::redb::TableDefinition<'_, &'static str, &[u8]> = #crate_path::redb::TableDefinition::new(#table_name);
 
-    for field in fields {
-        let Some(ident) = field.ident.as_ref() else {
-            return Error::new_spanned(field, "Struct field must have a name")
-                .to_compile_error()
-                .into();
-        };
+            const COMMENT: Option<&'static str> = #comment;
 
-        let ty = &field.ty;
-        let field_attrs = MiniDBFieldAttributes::from_attributes(&field.attrs);
+            const FIELD_COMMENTS: &'static [(&'static str, &'static str)] = &[#(#field_comments),*];
+
+            const HAS_COMPUTED: bool = #has_computed;
 
-        if field_attrs.is_key {
-            num_keys_fields += 1;
-            id_field_ident = Some(ident.clone());
+            const REDACTED_FIELDS: &'static [&'static str] = &[#(#redacted),*];
 
-            let is_id_type = is_id_type(ty);
-            if !is_id_type {
-                return Error::new_spanned(ty, "The #[key] field must be of type `String`.")
-                    .to_compile_error()
-                    .into();
+            const INDEXES: &'static [&'static str] = &[#(#indexes),*];
+
+            const UNIQUE: &'static [&'static str] = &[#(#unique),*];
+
+            const FULLTEXT: &'static [&'static str] = &[#(#fulltext),*];
+
+            const SINGLETON: bool = #singleton;
+
+            fn get_id(&self) -> &str {
+                &self.#id_field_member
+            }
+
+            fn set_id(&mut self, id: String) {
+                self.#id_field_member = id;
+            }
+
+            fn rehydrate(&mut self) {
+                #(#rehydrations)*
+            }
+
+            fn compute(&mut self) {
+                #(#computations)*
+            }
+
+            fn redact(&mut self) {
+                #(#redactions)*
+            }
+
+            fn index_values(&self) -> ::std::result::Result<::std::vec::Vec<(&'static str, ::std::vec::Vec<u8>)>, #crate_path::Error> {
+                Ok(::std::vec![#(#index_values),*])
+            }
+
+            fn fulltext_values(&self) -> ::std::vec::Vec<&str> {
+                ::std::vec![#(#fulltext_values),*]
             }
         }
+    };
+
+    let debug_impl = redacted_debug_impl(
+        struct_name,
+        struct_generics,
+        fields,
+        &redacted,
+        &debug_fields,
+    );
+
+    let fields_impl = typed_fields_impl(input, fields, struct_attrs.fields, &field_consts)?;
+
+    let builder_impl = struct_attrs
+        .builder
+        .then(|| record_builder_impl(input, fields))
+        .transpose()?;
+
+    // generic structs can't be submitted to the registry since they have no concrete type
+    let registration = struct_generics
+        .params
+        .is_empty()
+        .then(|| quote! { #crate_path::__register_table!(#struct_name); });
+
+    Ok(quote! {
+        #table_model_impl
+        #debug_impl
+        #fields_impl
+        #builder_impl
+        #registration
+    })
+}
+
+/// Returns the fields of the struct the derive is applied to, failing for enums and unions
+fn struct_fields(input: &DeriveInput) -> Result<&Fields, Error> {
+    match &input.data {
+        Data::Struct(s) => Ok(&s.fields),
+        Data::Enum(e) => Err(Error::new_spanned(
+            e.enum_token,
+            "Table derive macro only supports structs",
+        )),
+        Data::Union(u) => Err(Error::new_spanned(
+            u.union_token,
+            "Table derive macro only supports structs",
+        )),
     }
+}
 
-    if num_keys_fields != 1 {
-        return Error::new_spanned(
+/// Builds the `StructBuilder` generated by `#[minidb(builder)]`
+fn record_builder_impl(
+    input: &DeriveInput,
+    fields: &Fields,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let struct_name = &input.ident;
+    let Fields::Named(named) = fields else {
+        return Err(Error::new_spanned(
             struct_name,
-            "A struct deriving `Table` must have exactly one field marked with #[key].",
-        )
-        .to_compile_error()
-        .into();
-    }
-
-    let Some(id_field_ident) = id_field_ident else {
-        return Error::new_spanned(
-            fields,
-            "A struct deriving `Table` must have exactly one field marked with #[key].",
-        )
-        .to_compile_error()
-        .into();
+            "`builder` needs a struct with named fields",
+        ));
     };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "`builder` doesn't support generic structs",
+        ));
+    }
 
-    let table_model_impl = quote! {
-        impl #impl_generics #crate_path::Table for #struct_name #ty_generics #where_clause {
-            const TABLE: #crate_path::redb::TableDefinition<'_, &'static str, &[u8]> = #crate_path::redb::TableDefinition::new(#table_name);
+    let vis = &input.vis;
+    let builder_name = format_ident!("{struct_name}Builder");
+    let builder_doc =
+        format!("Builds a [`{struct_name}`] one field at a time, see [`{struct_name}::builder`]");
 
-            fn get_id(&self) -> &str {
-                &self.#id_field_ident
+    let mut slots = Vec::new();
+    let mut setters = Vec::new();
+    let mut values = Vec::new();
+    for field in &named.named {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let ty = &field.ty;
+        let doc = format!("Sets the `{ident}` field");
+
+        slots.push(quote! { #ident: ::std::option::Option<#ty> });
+        setters.push(quote! {
+            #[doc = #doc]
+            #[must_use]
+            pub fn #ident<V>(mut self, value: V) -> Self
+            where
+                V: ::std::convert::Into<#ty>,
+            {
+                self.#ident = ::std::option::Option::Some(value.into());
+                self
             }
+        });
+        values.push(quote! { #ident: self.#ident.unwrap_or_default() });
+    }
 
-            fn set_id(&mut self, id: String) {
-                self.#id_field_ident = id;
+    Ok(quote! {
+        #[doc = #builder_doc]
+        #[derive(Default)]
+        #vis struct #builder_name {
+            #(#slots),*
+        }
+
+        #[allow(dead_code)]
+        impl #builder_name {
+            #(#setters)*
+
+            /// Builds the record, unset fields get their `Default` value
+            #[must_use]
+            pub fn build(self) -> #struct_name {
+                #struct_name {
+                    #(#values),*
+                }
+            }
+        }
+
+        #[allow(dead_code)]
+        impl #struct_name {
+            /// Starts building a record, the ID is generated on insert unless it's set
+            #[must_use]
+            pub fn builder() -> #builder_name {
+                #builder_name::default()
             }
         }
+    })
+}
+
+/// Builds the `Debug` impl that masks the redacted fields of a struct
+fn redacted_debug_impl(
+    struct_name: &Ident,
+    struct_generics: &syn::Generics,
+    fields: &Fields,
+    redacted: &[String],
+    debug_fields: &[proc_macro2::TokenStream],
+) -> proc_macro2::TokenStream {
+    // the generated impl would conflict with a derived one, so it's only emitted when something needs masking
+    if redacted.is_empty() {
+        return quote! {};
+    }
+
+    let (impl_generics, ty_generics, where_clause) = struct_generics.split_for_impl();
+    let struct_name_str = struct_name.to_string();
+    let builder = if let Fields::Named(_) = fields {
+        quote! { debug_struct }
+    } else {
+        quote! { debug_tuple }
     };
 
-    let out = quote! {
-        #table_model_impl
+    quote! {
+        impl #impl_generics ::std::fmt::Debug for #struct_name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.#builder(#struct_name_str)
+                    #(#debug_fields)*
+                    .finish()
+            }
+        }
+    }
+}
+
+/// Wraps the `minidb::Field` constants in an inherent impl if the struct is marked with `#[minidb(fields)]`
+fn typed_fields_impl(
+    input: &DeriveInput,
+    fields: &Fields,
+    enabled: bool,
+    field_consts: &[proc_macro2::TokenStream],
+) -> Result<proc_macro2::TokenStream, Error> {
+    if !enabled {
+        return Ok(quote! {});
+    }
+    if !matches!(fields, Fields::Named(_)) {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "`fields` needs a struct with named fields",
+        ));
+    }
+    // the getters are plain functions that can't name the parameters of a generic struct
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "`fields` doesn't support generic structs",
+        ));
+    }
+
+    let struct_name = &input.ident;
+    Ok(quote! {
+        #[allow(dead_code)]
+        impl #struct_name {
+            #(#field_consts)*
+        }
+    })
+}
+
+/// Resolves the path to the `minidb` crate, in case it was renamed
+fn minidb_crate_path(struct_name: &Ident) -> Result<proc_macro2::TokenStream, Error> {
+    let Ok(found_crate) = crate_name("minidb") else {
+        return Err(Error::new_spanned(
+            struct_name,
+            "minidb crate not found in dependencies",
+        ));
     };
 
-    // return Error::new_spanned(struct_name, out)
-    //     .to_compile_error()
-    //     .into();
-    out.into()
+    Ok(match found_crate {
+        FoundCrate::Itself => quote!(minidb),
+        FoundCrate::Name(name) => {
+            let ident = Ident::new(&name, Span::call_site());
+            quote!(#ident)
+        }
+    })
+}
+
+/// Returns the name of a field, or its position for tuple structs
+fn member_name(member: &Member) -> String {
+    match member {
+        Member::Named(ident) => ident.to_string(),
+        Member::Unnamed(index) => index.index.to_string(),
+    }
 }
 
 fn is_id_type(ty: &Type) -> bool {
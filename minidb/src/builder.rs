@@ -2,17 +2,92 @@
 // Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
 // with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::{fmt::Debug, path::PathBuf};
+use std::{collections::HashMap, env, fmt::Debug, fs, path::PathBuf, time::Duration};
 
 use crate::{
-    ArgonKey, Error, META_TABLE, MiniDB, SETTINGS_TABLE, encryption::derive_key_from_password,
-    error::Result, model::Table,
+    ArgonKey, Error, META_KEY_SALT, META_KEY_TABLE_PREFIX, META_TABLE, MiniDB, SETTINGS_TABLE,
+    cache_layer::CacheLayer,
+    encryption::{
+        ArgonParams, derive_key_from_password, derive_key_from_password_with_pepper, derive_subkey,
+        key_cache_id, load_or_create_key_file,
+    },
+    error::Result,
+    format::{self, META_KEY_FORMAT},
+    index::{self, INDEX_KEY_CONTEXT},
+    key_cache::KeyCache,
+    lock::{LockBackend, LockFile, recovery_path, retry_while_locked},
+    model::{Table, TableInfo},
+    quota::{self, Evictor, Quota, QuotaPolicy},
+    record_meta::RECORD_META_TABLE,
+    unique_group::{self, UniqueMember},
 };
 use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
-use redb::{Database, WriteTransaction};
+use redb::{Database, ReadableDatabase, TableHandle, WriteTransaction};
 
 type Initializer = Box<dyn Fn(&WriteTransaction) -> Result<()>>;
 
+/// Looks up the value of an override by its variable name
+type EnvLookup = Box<dyn Fn(&str) -> Option<String>>;
+
+/// Overrides [`MiniDBBuilder::lock_timeout`], in seconds
+const ENV_LOCK_TIMEOUT: &str = "MINIDB_LOCK_TIMEOUT";
+
+/// Overrides [`MiniDBBuilder::lock_backend`], `os` or `pidfile`
+const ENV_LOCK_BACKEND: &str = "MINIDB_LOCK_BACKEND";
+
+/// Overrides [`MiniDBBuilder::max_record_size`], in bytes
+const ENV_MAX_RECORD_SIZE: &str = "MINIDB_MAX_RECORD_SIZE";
+
+/// Overrides [`MiniDBBuilder::strict`], `true` or `false`
+const ENV_STRICT: &str = "MINIDB_STRICT";
+
+/// Overrides [`MiniDBBuilder::record_timestamps`], `true` or `false`
+const ENV_RECORD_TIMESTAMPS: &str = "MINIDB_RECORD_TIMESTAMPS";
+
+/// Reads an override through `lookup`, [`None`] if it isn't set
+fn env_override<T, F>(lookup: &EnvLookup, name: &str, parse: F) -> Result<Option<T>>
+where
+    F: FnOnce(&str) -> Option<T>,
+{
+    let Some(value) = lookup(name) else {
+        return Ok(None);
+    };
+
+    parse(value.trim())
+        .map(Some)
+        .ok_or_else(|| Error::InvalidEnvVar {
+            name: name.to_string(),
+            value,
+        })
+}
+
+/// Returns the redb configuration every database file is opened with, so a relocated file is opened the same way
+pub(crate) fn redb_builder() -> redb::Builder {
+    Database::builder()
+}
+
+/// Opens the table `T` inside the bootstrap transaction so it gets created, and stores its [`TableInfo`]
+pub(crate) fn init_table<T>(txn: &WriteTransaction) -> Result<()>
+where
+    T: Table,
+{
+    txn.open_table(T::TABLE)
+        .map(|_| ())
+        .map_err(|e| Error::TableInitialization {
+            name: T::TABLE.to_string(),
+            source: e,
+        })?;
+
+    let info = TableInfo::of::<T>();
+    let key = format!("{META_KEY_TABLE_PREFIX}{}", info.name);
+    let bytes = postcard::to_stdvec(&info)?;
+    let mut meta = txn.open_table(META_TABLE)?;
+    meta.insert(key.as_str(), bytes.as_slice())?;
+    drop(meta);
+
+    index::mark_built_if_empty::<T>(txn)
+}
+
 /// A builder for a [`MiniDB`]
 ///
 /// ## Example
@@ -29,10 +104,30 @@ type Initializer = Box<dyn Fn(&WriteTransaction) -> Result<()>>;
 ///     .build()
 ///     .unwrap();
 /// ```
+// every bool is an independent option
+#[allow(clippy::struct_excessive_bools)]
 pub struct MiniDBBuilder {
     path: PathBuf,
     initializers: Vec<Initializer>,
+    evictors: HashMap<String, Evictor>,
+    indexes: Vec<String>,
     key_source: Option<KeySource>,
+    argon_params: Option<ArgonParams>,
+    key_cache: Option<Box<dyn KeyCache>>,
+    pepper: Option<Vec<u8>>,
+    #[cfg(feature = "password-strength")]
+    min_password_strength: Option<u8>,
+    bind_records: bool,
+    max_record_size: Option<usize>,
+    strict: bool,
+    quota: Option<Quota>,
+    record_timestamps: bool,
+    sortable_ids: bool,
+    cache_layer: Option<Box<dyn CacheLayer>>,
+    lock_backend: LockBackend,
+    lock_timeout: Option<Duration>,
+    env_overrides: Option<EnvLookup>,
+    unique_groups: Vec<UniqueMember>,
 }
 
 impl Debug for MiniDBBuilder {
@@ -40,6 +135,16 @@ impl Debug for MiniDBBuilder {
         f.debug_struct("MiniDBBuilder")
             .field("path", &self.path)
             .field("key_source", &self.key_source)
+            .field("argon_params", &self.argon_params)
+            .field("bind_records", &self.bind_records)
+            .field("max_record_size", &self.max_record_size)
+            .field("strict", &self.strict)
+            .field("quota", &self.quota)
+            .field("record_timestamps", &self.record_timestamps)
+            .field("sortable_ids", &self.sortable_ids)
+            .field("lock_backend", &self.lock_backend)
+            .field("lock_timeout", &self.lock_timeout)
+            .field("env_overrides", &self.env_overrides.is_some())
             .finish_non_exhaustive()
     }
 }
@@ -70,7 +175,25 @@ impl MiniDBBuilder {
         Self {
             path: path.into(),
             initializers: Vec::new(),
+            evictors: HashMap::new(),
+            indexes: Vec::new(),
             key_source: None,
+            argon_params: None,
+            key_cache: None,
+            pepper: None,
+            #[cfg(feature = "password-strength")]
+            min_password_strength: None,
+            bind_records: false,
+            max_record_size: None,
+            strict: false,
+            quota: None,
+            record_timestamps: false,
+            sortable_ids: false,
+            cache_layer: None,
+            lock_backend: LockBackend::default(),
+            lock_timeout: None,
+            env_overrides: None,
+            unique_groups: Vec::new(),
         }
     }
 
@@ -109,15 +232,43 @@ impl MiniDBBuilder {
     where
         T: Table + 'static,
     {
-        self.initializers.push(Box::new(|txn| {
-            txn.open_table(T::TABLE)
-                .map(|_| ())
-                .map_err(|e| Error::TableInitialization {
-                    name: T::TABLE.to_string(),
-                    source: e,
-                })?;
-            Ok(())
-        }));
+        self.initializers.push(Box::new(init_table::<T>));
+        self.evictors
+            .insert(T::TABLE.name().to_string(), quota::evict::<T>);
+        self.indexes.extend(index::names::<T>());
+        self
+    }
+
+    /// Registers every table model that derives [`Table`](crate::Table) in the binary
+    ///
+    /// Generic structs can't be registered automatically and still need [`MiniDBBuilder::table`].
+    ///
+    /// **Note**: This requires the `registry` feature.
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// use minidb::MiniDB;
+    ///
+    /// // registers `Person`, `Car` and any other derived table
+    /// let db = MiniDB::builder("test.redb")
+    ///     .all_tables()
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "registry")]
+    #[must_use]
+    pub fn all_tables(mut self) -> Self {
+        for registration in inventory::iter::<crate::TableRegistration> {
+            self.initializers.push(Box::new(registration.init));
+            self.evictors
+                .insert((registration.name)(), registration.evict);
+            self.indexes.extend((registration.indexes)());
+        }
         self
     }
 
@@ -162,6 +313,589 @@ impl MiniDBBuilder {
         self
     }
 
+    /// Uses a key file instead of a password, shorthand for [`KeySource::KeyFile`]
+    ///
+    /// The file holds 32 random bytes and is created with owner-only permissions if it doesn't exist and the database is
+    /// new, for a database that was already opened a missing file fails with [`Error::MissingKeyFile`]. Keep it outside the
+    /// database directory, ideally on a different volume or in a secrets mount, so a copy of the database alone can't be decrypted
+    ///
+    /// ## Arguments
+    ///
+    /// * `path` - The path to the key file
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::MiniDB;
+    ///
+    /// let db = MiniDB::builder("/var/lib/app/data.redb")
+    ///     // skipping table registering for convenience
+    ///     .key_file("/run/secrets/minidb.key")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn key_file<P>(self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.key_source(KeySource::KeyFile(path.into()))
+    }
+
+    /// Sets the Argon2 parameters used to derive the key when the key source is [`KeySource::Password`]
+    ///
+    /// The same parameters must be used every time the database is opened. See [`ArgonPresets`](crate::ArgonPresets) for named presets
+    ///
+    /// ## Arguments
+    ///
+    /// * `params` - The [`ArgonParams`] to use, [`ArgonParams::default`] if not set
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::{ArgonParams, ArgonPresets, KeySource, MiniDB};
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .key_source(KeySource::Password("secretpassword".to_string()))
+    ///     .argon_params(ArgonParams::owasp())
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn argon_params(mut self, params: ArgonParams) -> Self {
+        self.argon_params = Some(params);
+        self
+    }
+
+    /// Rejects passwords that are too easy to guess when building the database
+    ///
+    /// The password is scored with [`password_strength`](crate::password_strength) and [`MiniDBBuilder::build`] fails with
+    /// [`Error::WeakPassword`] if the score is lower than `min`. Only used when the key source is a password
+    ///
+    /// **Note**: This requires the `password-strength` feature.
+    ///
+    /// ## Arguments
+    ///
+    /// * `min` - The minimum score, from 0 to 4
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// use minidb::{KeySource, MiniDB};
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .key_source(KeySource::Password("password123".to_string()))
+    ///     .min_password_strength(3)
+    ///     .build(); // fails with `Error::WeakPassword`
+    /// ```
+    #[cfg(feature = "password-strength")]
+    #[must_use]
+    pub fn min_password_strength(mut self, min: u8) -> Self {
+        self.min_password_strength = Some(min);
+        self
+    }
+
+    /// Sets an application pepper that's mixed into the key derived from a password
+    ///
+    /// The pepper is never stored in the database, so a stolen database file can't be brute-forced without also stealing
+    /// the application. Only used when the key source is a password, and the same pepper must be used every time
+    ///
+    /// ## Arguments
+    ///
+    /// * `pepper` - The pepper bytes
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::{KeySource, MiniDB};
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .key_source(KeySource::Password("secretpassword".to_string()))
+    ///     .pepper(std::env::var("APP_PEPPER").unwrap())
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn pepper<P>(mut self, pepper: P) -> Self
+    where
+        P: Into<Vec<u8>>,
+    {
+        self.pepper = Some(pepper.into());
+        self
+    }
+
+    /// Sets where keys derived from a password are cached, so the Argon2 cost is only paid the first time
+    ///
+    /// Only used when the key source is [`KeySource::Password`], see [`KeyCache`] for what gets stored
+    ///
+    /// ## Arguments
+    ///
+    /// * `cache` - The [`KeyCache`] to use
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::{KeySource, MemoryKeyCache, MiniDB};
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .key_source(KeySource::Password("secretpassword".to_string()))
+    ///     .key_cache(MemoryKeyCache::default())
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn key_cache<C>(mut self, cache: C) -> Self
+    where
+        C: KeyCache + 'static,
+    {
+        self.key_cache = Some(Box::new(cache));
+        self
+    }
+
+    /// Sets whether encrypted records are bound to their table and ID
+    ///
+    /// The table name and the record ID are authenticated as associated data, so someone with write access to the file can't
+    /// swap ciphertexts between records or tables without decryption failing. It changes how records are encrypted, so it
+    /// has to be enabled when the database is created and kept on. The setting is stored in the database the first time
+    /// it's built and building it again with the other value fails with [`Error::OptionMismatch`]. Does nothing without
+    /// encryption
+    ///
+    /// ## Arguments
+    ///
+    /// * `bind` - Whether to bind records, `false` by default
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::{KeySource, MiniDB};
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .key_source(KeySource::Password("secretpassword".to_string()))
+    ///     .bind_records(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn bind_records(mut self, bind: bool) -> Self {
+        self.bind_records = bind;
+        self
+    }
+
+    /// Sets the maximum size in bytes of a stored record or setting
+    ///
+    /// Reads refuse to decrypt or deserialize anything bigger, so a corrupted or tampered value can't make the process
+    /// allocate huge buffers, and writes refuse to store it. The size is measured after serialization and encryption
+    ///
+    /// ## Arguments
+    ///
+    /// * `max` - The maximum size in bytes, unlimited by default
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::MiniDB;
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .max_record_size(1024 * 1024)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn max_record_size(mut self, max: usize) -> Self {
+        self.max_record_size = Some(max);
+        self
+    }
+
+    /// Sets whether reads should fail when a stored record has fields the current model doesn't know about
+    ///
+    /// Without strict mode those fields are silently dropped and lost on the next update. Since [postcard] isn't self-describing
+    /// this detects fields removed from (or never added to) the end of the model, which is where schema drift usually happens
+    ///
+    /// ## Arguments
+    ///
+    /// * `strict` - Whether to enable strict mode, `false` by default
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::MiniDB;
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .strict(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets a size budget for the whole database
+    ///
+    /// Every write transaction checks it when committing, so with [`QuotaPolicy::Reject`] a write that would go over the budget
+    /// fails and nothing is committed. With [`QuotaPolicy::Evict`] records are removed from the listed cache tables first
+    ///
+    /// ## Arguments
+    ///
+    /// * `max_bytes` - The maximum amount of bytes the data can use
+    /// * `policy` - What to do when the budget is exceeded
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::{MiniDB, QuotaPolicy};
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .quota(10 * 1024 * 1024, QuotaPolicy::Evict(vec!["thumbnails".to_string()]))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn quota(mut self, max_bytes: u64, policy: QuotaPolicy) -> Self {
+        self.quota = Some(Quota { max_bytes, policy });
+        self
+    }
+
+    /// Sets whether the creation and modification times of records are tracked
+    ///
+    /// The timestamps are kept in a separate table and returned by [`MiniDB::record_meta`], which is useful for sync tools
+    /// and retention logic. It costs an extra write per record write
+    ///
+    /// ## Arguments
+    ///
+    /// * `track` - Whether to track timestamps, `false` by default
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::MiniDB;
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .record_timestamps(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn record_timestamps(mut self, track: bool) -> Self {
+        self.record_timestamps = track;
+        self
+    }
+
+    /// Sets whether generated IDs start with the time they were generated at, so records sort in insertion order
+    ///
+    /// The IDs are a 16 digit hex timestamp followed by a random slug, and [`MiniDB::first`] and [`MiniDB::last`] then
+    /// return the oldest and newest records. IDs generated by the same [`MiniDB`] always increase, across processes
+    /// the order is only as good as the clocks. Records inserted with their own ID sort by that ID as usual.
+    ///
+    /// The setting is stored in the database the first time it's built, random and time-ordered IDs don't sort together
+    /// so building it again with the other value fails with [`Error::OptionMismatch`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `sortable` - Whether to generate time-ordered IDs, `false` by default
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::MiniDB;
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .sortable_ids(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn sortable_ids(mut self, sortable: bool) -> Self {
+        self.sortable_ids = sortable;
+        self
+    }
+
+    /// Adds an indexed field to a uniqueness constraint shared by several tables, writes fail with
+    /// [`Error::UniqueViolation`] if a record of any table in the group already has the same value
+    ///
+    /// The field must be marked with `#[index]` or `#[unique]`, `#[unique]` is still needed for duplicates within the
+    /// table to be caught before the first write. The records written while the field wasn't in the group, which includes
+    /// any opening of the database without this call, are claimed when the database is built, which fails if two of them
+    /// already share a value
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model, also registered with [`MiniDBBuilder::table`]
+    /// * `group` - The name of the constraint, shared by its fields
+    /// * `field` - The name of the field
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// // usernames are unique across admins and customers
+    /// let db = MiniDB::builder("test.redb")
+    ///     .table::<Admin>()
+    ///     .table::<Customer>()
+    ///     .unique_across::<Admin>("usernames", "username")
+    ///     .unique_across::<Customer>("usernames", "username")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn unique_across<T>(mut self, group: &str, field: &str) -> Self
+    where
+        T: Table,
+    {
+        self.unique_groups
+            .push(UniqueMember::new::<T>(group, field));
+        self
+    }
+
+    /// Sets an external cache consulted before reads and updated after writes
+    ///
+    /// ## Arguments
+    ///
+    /// * `cache` - The [`CacheLayer`] to use
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .cache_layer(RedisCache::connect("redis://localhost")?)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn cache_layer<C>(mut self, cache: C) -> Self
+    where
+        C: CacheLayer + 'static,
+    {
+        self.cache_layer = Some(Box::new(cache));
+        self
+    }
+
+    /// Sets how the database file is protected from being opened by more than one process
+    ///
+    /// Use [`LockBackend::PidFile`] when the database lives on a network filesystem or is shared with programs that don't
+    /// respect advisory locks, see [`LockBackend`] for the platform differences
+    ///
+    /// ## Arguments
+    ///
+    /// * `backend` - The [`LockBackend`] to use, [`LockBackend::Os`] by default
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::{LockBackend, MiniDB};
+    ///
+    /// let db = MiniDB::builder("/mnt/nfs/test.redb")
+    ///     // skipping table registering for convenience
+    ///     .lock_backend(LockBackend::PidFile)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn lock_backend(mut self, backend: LockBackend) -> Self {
+        self.lock_backend = backend;
+        self
+    }
+
+    /// Sets how long [`MiniDBBuilder::build`] waits for another owner to release the database before giving up
+    ///
+    /// Without a timeout the build fails right away if the database is already open, either through redb's OS lock
+    /// or through the lock file of [`LockBackend::PidFile`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `timeout` - How long to keep retrying
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    ///
+    /// use minidb::MiniDB;
+    ///
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .lock_timeout(Duration::from_secs(5))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Lets environment variables override some options when [`MiniDBBuilder::build`] is called, so they can be tuned
+    /// per deployment without changing the code
+    ///
+    /// The variables win over the builder methods no matter the order they're called in, and unset variables leave
+    /// the options alone:
+    ///
+    /// * `MINIDB_LOCK_TIMEOUT` - [`MiniDBBuilder::lock_timeout`] in seconds, like `2.5`
+    /// * `MINIDB_LOCK_BACKEND` - [`MiniDBBuilder::lock_backend`], `os` or `pidfile`
+    /// * `MINIDB_MAX_RECORD_SIZE` - [`MiniDBBuilder::max_record_size`] in bytes
+    /// * `MINIDB_STRICT` - [`MiniDBBuilder::strict`], `true` or `false`
+    /// * `MINIDB_RECORD_TIMESTAMPS` - [`MiniDBBuilder::record_timestamps`], `true` or `false`
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::MiniDB;
+    ///
+    /// // MINIDB_LOCK_TIMEOUT=30 ./app
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .with_env_overrides()
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn with_env_overrides(self) -> Self {
+        self.with_overrides_from(|name| env::var(name).ok())
+    }
+
+    /// Like [`MiniDBBuilder::with_env_overrides`] but the variables are looked up with `lookup` instead of read from
+    /// the environment, for configuration kept somewhere else or for tests
+    ///
+    /// ## Arguments
+    ///
+    /// * `lookup` - Returns the value of a variable by its name, [`None`] if it isn't set
+    ///
+    /// ## Returns
+    ///
+    /// A new [`MiniDBBuilder`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use minidb::MiniDB;
+    /// use std::collections::HashMap;
+    ///
+    /// let vars = HashMap::from([("MINIDB_LOCK_TIMEOUT".to_string(), "30".to_string())]);
+    /// let db = MiniDB::builder("test.redb")
+    ///     // skipping table registering for convenience
+    ///     .with_overrides_from(move |name| vars.get(name).cloned())
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn with_overrides_from<F>(mut self, lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + 'static,
+    {
+        self.env_overrides = Some(Box::new(lookup));
+        self
+    }
+
+    /// Applies the options set through environment variables, see [`MiniDBBuilder::with_env_overrides`]
+    fn apply_env_overrides(&mut self, lookup: &EnvLookup) -> Result<()> {
+        let parse_bool = |value: &str| match value.to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        };
+
+        if let Some(timeout) = env_override(lookup, ENV_LOCK_TIMEOUT, |value| {
+            Duration::try_from_secs_f64(value.parse().ok()?).ok()
+        })? {
+            self.lock_timeout = Some(timeout);
+        }
+        if let Some(backend) = env_override(lookup, ENV_LOCK_BACKEND, |value| {
+            match value.to_ascii_lowercase().as_str() {
+                "os" => Some(LockBackend::Os),
+                "pidfile" => Some(LockBackend::PidFile),
+                _ => None,
+            }
+        })? {
+            self.lock_backend = backend;
+        }
+        if let Some(max) = env_override(lookup, ENV_MAX_RECORD_SIZE, |value| value.parse().ok())? {
+            self.max_record_size = Some(max);
+        }
+        if let Some(strict) = env_override(lookup, ENV_STRICT, parse_bool)? {
+            self.strict = strict;
+        }
+        if let Some(track) = env_override(lookup, ENV_RECORD_TIMESTAMPS, parse_bool)? {
+            self.record_timestamps = track;
+        }
+
+        Ok(())
+    }
+
     /// Builds the [`MiniDB`] from the builder
     ///
     /// ## Returns
@@ -170,7 +904,7 @@ impl MiniDBBuilder {
     ///
     /// ## Errors
     ///
-    /// Returns an error if the database file already exists, if the bootstrap transaction fails, or if the key derivation fails
+    /// Returns an error if the database file already exists, if an environment override is invalid, if the lock file is held by another process, if the recovery check fails, if the bootstrap transaction fails, or if the key derivation fails
     ///
     /// ## Example
     ///
@@ -184,9 +918,98 @@ impl MiniDBBuilder {
     ///     .build()
     ///     .unwrap();
     /// ```
-    pub fn build(self) -> Result<MiniDB> {
-        let db = Database::builder().create(&self.path)?;
+    pub fn build(mut self) -> Result<MiniDB> {
+        if let Some(lookup) = self.env_overrides.take() {
+            self.apply_env_overrides(&lookup)?;
+        }
+        if let Some(member) = self
+            .unique_groups
+            .iter()
+            .find(|member| !member.indexes.contains(&member.field.as_str()))
+        {
+            return Err(Error::UnknownIndex {
+                table: member.table.clone(),
+                field: member.field.clone(),
+            });
+        }
+
+        let lock = match self.lock_backend {
+            LockBackend::Os => None,
+            LockBackend::PidFile => Some(retry_while_locked(self.lock_timeout, || {
+                LockFile::acquire(&self.path)
+            })?),
+        };
+        let mut db =
+            retry_while_locked(self.lock_timeout, || Ok(redb_builder().create(&self.path)?))?;
+
+        // a write panicked the last time the database was open
+        let marker = recovery_path(&self.path);
+        if marker.exists() {
+            db.check_integrity()?;
+            fs::remove_file(&marker)?;
+        }
+
+        // a key file can only be created for a database that was never opened, otherwise its data needs the lost key
+        let is_new = match db.begin_read()?.open_table(META_TABLE) {
+            Ok(meta) => meta.get(META_KEY_FORMAT)?.is_none() && meta.get(META_KEY_SALT)?.is_none(),
+            Err(redb::TableError::TableDoesNotExist(_)) => true,
+            Err(e) => return Err(e.into()),
+        };
+
+        self.bootstrap(&db)?;
+
+        let mut store = MiniDB::new(db);
+        store.lock = lock;
+        store.path = Some(self.path.clone());
+        store.set_strict(self.strict);
+        store.set_bind_records(self.bind_records);
+        store.set_max_record_size(self.max_record_size);
+        store.set_quota(self.quota.take());
+        store.evictors = std::mem::take(&mut self.evictors);
+        store.set_record_timestamps(self.record_timestamps);
+        store.set_sortable_ids(self.sortable_ids);
+        store.set_cache_layer(self.cache_layer.take());
+
+        if let Some(source) = self.key_source.take() {
+            let key = match source {
+                KeySource::Password(pass) => self.password_key(&store, &pass)?,
+
+                #[cfg(feature = "secrecy")]
+                KeySource::SecretPassword(pass) => {
+                    self.password_key(&store, secrecy::ExposeSecret::expose_secret(&pass))?
+                }
+
+                KeySource::PreDerived(key) => key,
+
+                KeySource::ExternalKeyProvider(provider_fn) => provider_fn(),
+
+                KeySource::KeyFile(path) => {
+                    let key = load_or_create_key_file(&path, is_new)?;
+                    store.key_file = Some(path);
+                    key
+                }
+            };
 
+            store.codec.index_key = Some(derive_subkey(&key, INDEX_KEY_CONTEXT));
+            store.set_cipher(XChaCha20Poly1305::new(&key.into()));
+        }
+
+        // the records have to be readable to be claimed, so this waits for the cipher
+        if !self.unique_groups.is_empty() {
+            store.unique_groups = std::mem::take(&mut self.unique_groups);
+            store.transaction(|txn| {
+                for member in &txn.db.unique_groups {
+                    (member.backfill)(txn, member)?;
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(store)
+    }
+
+    /// Creates the internal tables, checks the stored format and options and registers the tables, all in one transaction
+    fn bootstrap(&self, db: &Database) -> Result<()> {
         let txn = db.begin_write()?;
         {
             let _ = txn
@@ -202,30 +1025,58 @@ impl MiniDBBuilder {
                     source: e,
                 })?;
         }
-        for init in self.initializers {
+        if self.record_timestamps {
+            let _ = txn
+                .open_table(RECORD_META_TABLE)
+                .map_err(|e| Error::TableInitialization {
+                    name: RECORD_META_TABLE.to_string(),
+                    source: e,
+                })?;
+        }
+        format::upgrade(&txn)?;
+        format::pin_option(&txn, "sortable_ids", self.sortable_ids)?;
+        format::pin_option(&txn, "bind_records", self.bind_records)?;
+        for init in &self.initializers {
             init(&txn)?;
         }
+        index::forget_unregistered(&txn, &self.indexes)?;
+        unique_group::forget_unregistered(&txn, &self.unique_groups)?;
         txn.commit()?;
+        Ok(())
+    }
 
-        let mut store = MiniDB::new(db);
-
-        if let Some(source) = self.key_source {
-            let key = match source {
-                KeySource::Password(pass) => {
-                    let salt = store.get_salt()?;
-
-                    derive_key_from_password(&pass, Some(salt), None)?
-                }
+    /// Derives the key from a password, going through the key cache if there's one
+    fn password_key(&self, store: &MiniDB, password: &str) -> Result<ArgonKey> {
+        #[cfg(feature = "password-strength")]
+        if let Some(min) = self.min_password_strength {
+            let strength = crate::password_strength(password);
+            if strength < min {
+                return Err(Error::WeakPassword {
+                    score: strength,
+                    min,
+                });
+            }
+        }
 
-                KeySource::PreDerived(key) => key,
+        let salt = store.get_salt()?;
+        let params = self.argon_params.clone().unwrap_or_default();
+        let cache = self.key_cache.as_deref();
+        let pepper = self.pepper.as_deref().unwrap_or_default();
+        let cache_id = key_cache_id(&salt, &params, password, pepper);
 
-                KeySource::ExternalKeyProvider(provider_fn) => provider_fn(),
-            };
-
-            store.set_cipher(XChaCha20Poly1305::new(&key.into()));
+        if let Some(key) = cache.and_then(|c| c.get(&cache_id)) {
+            return Ok(key);
         }
 
-        Ok(store)
+        let key = if let Some(pepper) = &self.pepper {
+            derive_key_from_password_with_pepper(password, Some(salt), params, pepper)?
+        } else {
+            derive_key_from_password(password, Some(salt), params)?
+        };
+        if let Some(cache) = cache {
+            cache.put(&cache_id, &key);
+        }
+        Ok(key)
     }
 }
 
@@ -236,26 +1087,40 @@ impl MiniDBBuilder {
 /// * `KeySource::Password(String)` - The key source is a password
 /// * `KeySource::PreDerived(ArgonKey)` - The key source is a pre-derived key (`[u8; 32]`)
 /// * `KeySource::ExternalKeyProvider(Box<dyn Fn() -> ArgonKey>)` - The key source is a function that returns a key (`[u8; 32]`)
+/// * `KeySource::KeyFile(PathBuf)` - The key source is a file with a random key (`[u8; 32]`), created if it doesn't exist and the database is new
+/// * `KeySource::SecretPassword(SecretString)` - The key source is a password wrapped in [`secrecy::SecretString`], which is zeroized when dropped. Requires the `secrecy` feature
 pub enum KeySource {
     /// The key source is a password
     Password(String),
 
+    /// The key source is a password wrapped in [`secrecy::SecretString`], which is zeroized when dropped
+    #[cfg(feature = "secrecy")]
+    SecretPassword(secrecy::SecretString),
+
     /// The key source is a pre-derived key (`[u8; 32]`)
     PreDerived(ArgonKey),
 
     /// The key source is a function that returns a key (`[u8; 32]`)
     ExternalKeyProvider(Box<dyn Fn() -> ArgonKey>),
+
+    /// The key source is a file with a random key (`[u8; 32]`), created if it doesn't exist and the database is new
+    KeyFile(PathBuf),
 }
 
 impl Debug for KeySource {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             KeySource::Password(_) => f.debug_tuple("Password").field(&"********").finish(),
+            #[cfg(feature = "secrecy")]
+            KeySource::SecretPassword(_) => {
+                f.debug_tuple("SecretPassword").field(&"********").finish()
+            }
             KeySource::PreDerived(key) => f.debug_tuple("PreDerived").field(key).finish(),
             KeySource::ExternalKeyProvider(_) => f
                 .debug_tuple("ExternalKeyProvider")
                 .field(&"<closure>")
                 .finish(),
+            KeySource::KeyFile(path) => f.debug_tuple("KeyFile").field(path).finish(),
         }
     }
 }
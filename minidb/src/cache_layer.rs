@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+/// An external cache (Redis, moka, etc) consulted before reads and updated after writes
+///
+/// Entries are identified by the database, the table name and the ID of a record and hold its stored bytes, encrypted
+/// if the database is, so the cache never sees plain data. The database is identified by the canonical path of its
+/// file, or a random namespace if it has none, so one cache can be shared by several databases. Only committed writes
+/// and removals reach the cache, reads never fill it since a read could put back bytes that a concurrent write already
+/// replaced
+///
+/// ## Example
+///
+/// ```rust,ignore
+/// struct Moka(moka::sync::Cache<(String, String, String), Vec<u8>>);
+///
+/// impl CacheLayer for Moka {
+///     fn get(&self, namespace: &str, table: &str, id: &str) -> Option<Vec<u8>> {
+///         self.0.get(&(namespace.to_string(), table.to_string(), id.to_string()))
+///     }
+///
+///     fn put(&self, namespace: &str, table: &str, id: &str, bytes: &[u8]) {
+///         self.0.insert((namespace.to_string(), table.to_string(), id.to_string()), bytes.to_vec());
+///     }
+///
+///     fn invalidate(&self, namespace: &str, table: &str, id: &str) {
+///         self.0.invalidate(&(namespace.to_string(), table.to_string(), id.to_string()));
+///     }
+/// }
+/// ```
+pub trait CacheLayer: Send + Sync {
+    /// Returns the cached bytes of a record, if any
+    ///
+    /// ## Arguments
+    ///
+    /// * `namespace` - Identifies the database
+    /// * `table` - The name of the table
+    /// * `id` - The ID of the record
+    fn get(&self, namespace: &str, table: &str, id: &str) -> Option<Vec<u8>>;
+
+    /// Stores the bytes of a record that was written
+    ///
+    /// ## Arguments
+    ///
+    /// * `namespace` - Identifies the database
+    /// * `table` - The name of the table
+    /// * `id` - The ID of the record
+    /// * `bytes` - The stored bytes of the record
+    fn put(&self, namespace: &str, table: &str, id: &str, bytes: &[u8]);
+
+    /// Drops a record that was removed
+    ///
+    /// ## Arguments
+    ///
+    /// * `namespace` - Identifies the database
+    /// * `table` - The name of the table
+    /// * `id` - The ID of the record
+    fn invalidate(&self, namespace: &str, table: &str, id: &str);
+}
+
+impl<C> CacheLayer for std::sync::Arc<C>
+where
+    C: CacheLayer + ?Sized,
+{
+    fn get(&self, namespace: &str, table: &str, id: &str) -> Option<Vec<u8>> {
+        (**self).get(namespace, table, id)
+    }
+
+    fn put(&self, namespace: &str, table: &str, id: &str, bytes: &[u8]) {
+        (**self).put(namespace, table, id, bytes);
+    }
+
+    fn invalidate(&self, namespace: &str, table: &str, id: &str) {
+        (**self).invalidate(namespace, table, id);
+    }
+}
+
+/// Returns the namespace of a database in the [`CacheLayer`], the canonical path of its file or a random one if it
+/// has none
+pub(crate) fn namespace(path: Option<&Path>) -> String {
+    if let Some(path) = path.and_then(|path| fs::canonicalize(path).ok()) {
+        return path.display().to_string();
+    }
+
+    let mut random = [0u8; 16];
+    OsRng.fill_bytes(&mut random);
+    random.iter().fold(String::new(), |mut namespace, byte| {
+        let _ = write!(namespace, "{byte:02x}");
+        namespace
+    })
+}
+
+/// A change to apply to the [`CacheLayer`] once a transaction commits, `None` removes the record
+pub(crate) type CacheUpdate = (String, String, Option<Vec<u8>>);
@@ -0,0 +1,168 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    ArgonKey,
+    encryption::{
+        decrypt_bytes, decrypt_bytes_with_aad, derive_subkey, encrypt_bytes, encrypt_bytes_with_aad,
+    },
+    error::{Error, Result},
+    model::Table,
+};
+use chacha20poly1305::XChaCha20Poly1305;
+use redb::TableHandle;
+use serde::{Deserialize, Serialize};
+
+/// Returns a copy of a record with its computed fields recalculated, [`None`] if the model has none
+///
+/// Models don't have to be [`Clone`] so the copy goes through postcard, and [`Table::compute`] runs on the copy since
+/// updates only get a shared reference
+pub(crate) fn computed<T>(item: &T) -> Result<Option<T>>
+where
+    T: Table,
+{
+    if !T::HAS_COMPUTED {
+        return Ok(None);
+    }
+
+    let mut copy: T = postcard::from_bytes(&postcard::to_stdvec(item)?)?;
+    copy.compute();
+    Ok(Some(copy))
+}
+
+/// Turns values into the bytes that get stored and back, handling the optional encryption
+#[derive(Clone, Default)]
+pub(crate) struct Codec {
+    pub(crate) cipher: Option<XChaCha20Poly1305>,
+    pub(crate) strict: bool,
+    pub(crate) bind_records: bool,
+    pub(crate) max_record_size: Option<usize>,
+    pub(crate) index_key: Option<ArgonKey>,
+}
+
+impl Codec {
+    /// Creates a new [`Codec`] with an optional cipher
+    pub(crate) fn new(cipher: Option<XChaCha20Poly1305>) -> Self {
+        Self {
+            cipher,
+            ..Default::default()
+        }
+    }
+
+    /// Serializes and, if there's a cipher, encrypts a value
+    pub(crate) fn encode<T>(&self, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize + ?Sized,
+    {
+        let bytes = postcard::to_stdvec(value)?;
+
+        let bytes = if let Some(cipher) = &self.cipher {
+            encrypt_bytes(cipher, &bytes)?
+        } else {
+            bytes
+        };
+        self.check_size(bytes.len())?;
+        Ok(bytes)
+    }
+
+    /// Makes sure stored bytes aren't over the size limit, if there's one
+    fn check_size(&self, size: usize) -> Result<()> {
+        match self.max_record_size {
+            Some(max) if size > max => Err(Error::RecordTooLarge { size, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decrypts, if there's a cipher, and deserializes a value
+    pub(crate) fn decode<T>(&self, bytes: &[u8]) -> Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.check_size(bytes.len())?;
+
+        if let Some(cipher) = &self.cipher {
+            let decrypted = decrypt_bytes(cipher, bytes)?;
+            self.deserialize(&decrypted)
+        } else {
+            self.deserialize(bytes)
+        }
+    }
+
+    /// Returns the associated data a record of `T` stored under `key` is encrypted with
+    ///
+    /// Binding the ciphertext to its table and key means moving it to another table or key makes decryption fail
+    fn record_aad<T>(&self, key: &str) -> Vec<u8>
+    where
+        T: Table,
+    {
+        if !self.bind_records {
+            return Vec::new();
+        }
+
+        let table = T::TABLE;
+        let mut aad = Vec::with_capacity(table.name().len() + key.len() + 1);
+        aad.extend_from_slice(table.name().as_bytes());
+        aad.push(0);
+        aad.extend_from_slice(key.as_bytes());
+        aad
+    }
+
+    /// Encodes a table record as is, its computed fields have to be recalculated already, see [`computed`]
+    pub(crate) fn encode_record<T>(&self, item: &T) -> Result<Vec<u8>>
+    where
+        T: Table,
+    {
+        let bytes = postcard::to_stdvec(item)?;
+
+        let bytes = if let Some(cipher) = &self.cipher {
+            encrypt_bytes_with_aad(cipher, &bytes, &self.record_aad::<T>(item.get_id()))?
+        } else {
+            bytes
+        };
+        self.check_size(bytes.len())?;
+        Ok(bytes)
+    }
+
+    /// Returns what an indexed value is stored as, keyed so encrypted databases don't leak it
+    pub(crate) fn index_token(&self, value: &[u8]) -> Vec<u8> {
+        match &self.index_key {
+            Some(key) => derive_subkey(key, value).to_vec(),
+            None => value.to_vec(),
+        }
+    }
+
+    /// Deserializes a value, in strict mode any leftover bytes mean the record has fields the model doesn't know about
+    fn deserialize<T>(&self, bytes: &[u8]) -> Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        if !self.strict {
+            return Ok(postcard::from_bytes(bytes)?);
+        }
+
+        let (value, rest) = postcard::take_from_bytes(bytes)?;
+        if !rest.is_empty() {
+            return Err(Error::UnknownFields(rest.len()));
+        }
+
+        Ok(value)
+    }
+
+    /// Decodes a table record stored under `key` and runs its [`Table::rehydrate`] hook
+    pub(crate) fn decode_record<T>(&self, key: &str, bytes: &[u8]) -> Result<T>
+    where
+        T: Table,
+    {
+        self.check_size(bytes.len())?;
+
+        let mut item: T = if let Some(cipher) = &self.cipher {
+            let decrypted = decrypt_bytes_with_aad(cipher, bytes, &self.record_aad::<T>(key))?;
+            self.deserialize(&decrypted)?
+        } else {
+            self.deserialize(bytes)?
+        };
+        item.rehydrate();
+        Ok(item)
+    }
+}
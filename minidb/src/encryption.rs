@@ -2,15 +2,79 @@
 // Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
 // with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use std::{
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
 use crate::{ArgonKey, Error, error::Result};
+pub use argon2::Params as ArgonParams;
 use argon2::{
-    Algorithm, Argon2, Params as ArgonParams, PasswordHasher, Version,
+    Algorithm, Argon2, PasswordHasher, Version,
     password_hash::{SaltString, rand_core::RngCore},
 };
 use chacha20poly1305::{
     XChaCha20Poly1305, XNonce,
-    aead::{Aead, OsRng},
+    aead::{Aead, OsRng, Payload},
 };
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Named presets for the Argon2 parameters used to derive the key from a password
+///
+/// Costs are in KiB of memory, iterations and lanes. Pick a preset once, the database has to be opened with the same
+/// parameters every time or the derived key won't match
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use minidb::{ArgonParams, ArgonPresets, KeySource, MiniDB};
+///
+/// let db = MiniDB::builder("test.redb")
+///     // skipping table registering for convenience
+///     .key_source(KeySource::Password("secretpassword".to_string()))
+///     .argon_params(ArgonParams::bitwarden())
+///     .build()
+///     .unwrap();
+/// ```
+pub trait ArgonPresets {
+    /// The OWASP recommendation: 19 MiB, 2 iterations, 1 lane. Same as [`ArgonParams::default`]
+    fn owasp() -> Self;
+
+    /// The Bitwarden defaults: 64 MiB, 3 iterations, 4 lanes
+    fn bitwarden() -> Self;
+
+    /// The cheapest parameters argon2 accepts: 8 KiB, 1 iteration, 1 lane. Only meant for tests
+    fn fast_insecure_for_tests() -> Self;
+}
+
+// validated at compile time so the presets can't fail
+const fn const_params(m_cost: u32, t_cost: u32, p_cost: u32) -> ArgonParams {
+    match ArgonParams::new(m_cost, t_cost, p_cost, None) {
+        Ok(params) => params,
+        Err(_) => panic!("invalid argon2 preset"),
+    }
+}
+
+const OWASP: ArgonParams = const_params(19 * 1024, 2, 1);
+const BITWARDEN: ArgonParams = const_params(64 * 1024, 3, 4);
+const FAST_INSECURE: ArgonParams = const_params(ArgonParams::MIN_M_COST, 1, 1);
+
+impl ArgonPresets for ArgonParams {
+    fn owasp() -> Self {
+        OWASP
+    }
+
+    fn bitwarden() -> Self {
+        BITWARDEN
+    }
+
+    fn fast_insecure_for_tests() -> Self {
+        FAST_INSECURE
+    }
+}
 
 /// Decrypt bytes using a cipher and ciphertext
 ///
@@ -30,10 +94,15 @@ pub fn decrypt_bytes<C>(cipher: &XChaCha20Poly1305, ciphertext: C) -> Result<Vec
 where
     C: AsRef<[u8]>,
 {
-    decrypt_bytes_impl(cipher, ciphertext.as_ref())
+    decrypt_bytes_with_aad(cipher, ciphertext.as_ref(), &[])
 }
 
-fn decrypt_bytes_impl(cipher: &XChaCha20Poly1305, ciphertext: &[u8]) -> Result<Vec<u8>> {
+/// Decrypts bytes that were encrypted with associated data, which must match
+pub(crate) fn decrypt_bytes_with_aad(
+    cipher: &XChaCha20Poly1305,
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
     if ciphertext.len() < 24 {
         return Err(Error::CipherTextTooShort(ciphertext.len()));
     }
@@ -41,7 +110,13 @@ fn decrypt_bytes_impl(cipher: &XChaCha20Poly1305, ciphertext: &[u8]) -> Result<V
     let (nonce_bytes, ciphertext) = ciphertext.split_at(24);
     let nonce_array: [u8; 24] = nonce_bytes.try_into().unwrap();
     let nonce = XNonce::from(nonce_array);
-    let plaintext = cipher.decrypt(&nonce, ciphertext.as_ref())?;
+    let plaintext = cipher.decrypt(
+        &nonce,
+        Payload {
+            msg: ciphertext,
+            aad,
+        },
+    )?;
 
     Ok(plaintext)
 }
@@ -71,13 +146,47 @@ where
     Salt: Into<Option<String>>,
     Params: Into<Option<ArgonParams>>,
 {
-    derive_key_from_password_impl(password.as_ref(), salt.into(), params.into())
+    derive_key_from_password_impl(password.as_ref(), salt.into(), params.into(), &[])
+}
+
+/// Derive a key from a password and salt, mixing in an application pepper
+///
+/// The pepper is passed to Argon2 as its secret input, so unlike the salt it's never stored in the database.
+/// Keep it in the application binary, an environment variable or a secrets manager
+///
+/// ## Arguments
+///
+/// * `password` - The password to derive the key from
+/// * `salt` - The salt to use for the key derivation
+/// * `params` - The parameters to use for the key derivation
+/// * `pepper` - The application pepper, up to 4 GiB
+///
+/// ## Returns
+///
+/// A `Result` containing the derived key
+///
+/// ## Errors
+///
+/// Returns an error if the key derivation fails or if the pepper is too long
+pub fn derive_key_from_password_with_pepper<Pass, Salt, Params>(
+    password: Pass,
+    salt: Salt,
+    params: Params,
+    pepper: &[u8],
+) -> Result<ArgonKey>
+where
+    Pass: AsRef<str>,
+    Salt: Into<Option<String>>,
+    Params: Into<Option<ArgonParams>>,
+{
+    derive_key_from_password_impl(password.as_ref(), salt.into(), params.into(), pepper)
 }
 
 fn derive_key_from_password_impl(
     password: &str,
     salt: Option<String>,
     params: Option<ArgonParams>,
+    pepper: &[u8],
 ) -> Result<ArgonKey> {
     let salt_string = salt.unwrap_or_else(|| {
         let salt_str = SaltString::generate(&mut OsRng);
@@ -86,7 +195,12 @@ fn derive_key_from_password_impl(
     let salt = SaltString::from_b64(&salt_string)?;
 
     let params = params.unwrap_or_default();
-    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let argon2 = if pepper.is_empty() {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    } else {
+        Argon2::new_with_secret(pepper, Algorithm::Argon2id, Version::V0x13, params)
+            .map_err(argon2::password_hash::Error::from)?
+    };
     let bytes = argon2
         .hash_password(password.as_bytes(), &salt)?
         .hash
@@ -101,6 +215,146 @@ fn derive_key_from_password_impl(
     Ok(key)
 }
 
+/// Derives an independent subkey from a master key with HKDF-SHA256
+///
+/// Useful to give each tenant or namespace its own encryption key while only storing one master key.
+/// The same master key and context always derive the same subkey, different contexts derive unrelated subkeys
+///
+/// ## Arguments
+///
+/// * `master` - The master key
+/// * `context` - What the subkey is for, e.g. the tenant ID
+///
+/// ## Returns
+///
+/// The derived subkey
+///
+/// ## Panics
+///
+/// Never, 32 bytes is always a valid HKDF-SHA256 output length
+///
+/// ## Example
+///
+/// ```rust
+/// use minidb::{KeySource, derive_subkey};
+///
+/// let master = [1u8; 32];
+/// let tenant_key = derive_subkey(&master, b"tenant:acme");
+/// let source = KeySource::PreDerived(tenant_key);
+/// ```
+#[must_use]
+pub fn derive_subkey<C>(master: &ArgonKey, context: C) -> ArgonKey
+where
+    C: AsRef<[u8]>,
+{
+    let hkdf = Hkdf::<Sha256>::new(None, master);
+    let mut key = [0u8; 32];
+    hkdf.expand(context.as_ref(), &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Returns the [`KeyCache`](crate::KeyCache) entry of a key derived from a password
+///
+/// The entry ends with a keyed hash of the password and the pepper, so a cached key is only returned for the password
+/// and pepper it was derived from
+///
+/// ## Arguments
+///
+/// * `salt` - The salt of the database, also the key of the hash
+/// * `params` - The parameters used for the key derivation
+/// * `password` - The password the key was derived from
+/// * `pepper` - The application pepper, empty if there's none
+pub(crate) fn key_cache_id(
+    salt: &str,
+    params: &ArgonParams,
+    password: &str,
+    pepper: &[u8],
+) -> String {
+    // the length prefix keeps the boundary between the password and the pepper unambiguous
+    let mut secret = (password.len() as u64).to_le_bytes().to_vec();
+    secret.extend_from_slice(password.as_bytes());
+    secret.extend_from_slice(pepper);
+
+    let hkdf = Hkdf::<Sha256>::new(Some(salt.as_bytes()), &secret);
+    let context = format!(
+        "minidb:key-cache${}${}${}",
+        params.m_cost(),
+        params.t_cost(),
+        params.p_cost()
+    );
+    let mut digest = [0u8; 32];
+    hkdf.expand(context.as_bytes(), &mut digest)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+    digest.iter().fold(format!("{salt}$"), |mut id, byte| {
+        let _ = write!(id, "{byte:02x}");
+        id
+    })
+}
+
+/// Reads a key from a key file, or creates the file with a random key if it doesn't exist and the database is new
+///
+/// ## Arguments
+///
+/// * `path` - The path to the key file
+/// * `create` - Whether a missing file can be created, only for databases that don't have any data yet
+///
+/// ## Returns
+///
+/// A `Result` containing the key
+///
+/// ## Errors
+///
+/// Returns [`Error::MissingKeyFile`] if the file doesn't exist and can't be created, or an error if the file can't be
+/// read or created, or if it doesn't contain exactly 32 bytes
+pub(crate) fn load_or_create_key_file(path: &Path, create: bool) -> Result<ArgonKey> {
+    match fs::read(path) {
+        Ok(bytes) => bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::KeyLengthMismatch(bytes.len())),
+        Err(e) if e.kind() == ErrorKind::NotFound && !create => {
+            Err(Error::MissingKeyFile(path.to_path_buf()))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let mut key = [0u8; 32];
+            OsRng.fill_bytes(&mut key);
+
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+            let mut file = options.open(path)?;
+            file.write_all(&key)?;
+            file.sync_all()?;
+            Ok(key)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Overwrites a key file with zeros and deletes it
+///
+/// ## Arguments
+///
+/// * `path` - The path to the key file
+///
+/// ## Errors
+///
+/// Returns an error if the file can't be overwritten or deleted
+pub(crate) fn destroy_key_file(path: &Path) -> Result<()> {
+    let len = fs::metadata(path)?.len();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; usize::try_from(len).unwrap_or(32)])?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path)?;
+    Ok(())
+}
+
 /// Encrypt bytes using a cipher
 ///
 /// ## Arguments
@@ -119,16 +373,27 @@ pub fn encrypt_bytes<P>(cipher: &XChaCha20Poly1305, plaintext: P) -> Result<Vec<
 where
     P: AsRef<[u8]>,
 {
-    encrypt_bytes_impl(cipher, plaintext.as_ref())
+    encrypt_bytes_with_aad(cipher, plaintext.as_ref(), &[])
 }
 
-fn encrypt_bytes_impl(cipher: &XChaCha20Poly1305, plaintext: &[u8]) -> Result<Vec<u8>> {
+/// Encrypts bytes and authenticates associated data that isn't stored with them
+pub(crate) fn encrypt_bytes_with_aad(
+    cipher: &XChaCha20Poly1305,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>> {
     let mut rng = OsRng;
     let mut nonce_bytes = [0u8; 24];
     rng.fill_bytes(&mut nonce_bytes);
     let nonce = XNonce::from(nonce_bytes);
 
-    let ciphertext = cipher.encrypt(&nonce, plaintext.as_ref())?;
+    let ciphertext = cipher.encrypt(
+        &nonce,
+        Payload {
+            msg: plaintext,
+            aad,
+        },
+    )?;
     let mut result = Vec::with_capacity(nonce_bytes.len() + ciphertext.len());
     result.extend_from_slice(&nonce_bytes);
     result.extend_from_slice(&ciphertext);
@@ -188,6 +453,65 @@ mod tests {
         assert_eq!(*count, N);
     }
 
+    #[test]
+    fn test_argon_presets() {
+        assert_eq!(ArgonParams::owasp(), ArgonParams::default());
+        assert_eq!(ArgonParams::bitwarden().m_cost(), 64 * 1024);
+
+        let salt = SaltString::generate(&mut OsRng);
+        let fast = time_function!(derive_key_from_password(
+            "abcdef123",
+            salt.to_string(),
+            ArgonParams::fast_insecure_for_tests()
+        ))
+        .unwrap();
+        let owasp = time_function!(derive_key_from_password(
+            "abcdef123",
+            salt.to_string(),
+            ArgonParams::owasp()
+        ))
+        .unwrap();
+        assert_ne!(fast, owasp);
+    }
+
+    #[test]
+    fn test_key_differs_with_pepper() {
+        let password = "abcdef123";
+        let salt = SaltString::generate(&mut OsRng);
+        let params = ArgonParams::fast_insecure_for_tests();
+
+        let plain = time_function!(derive_key_from_password(
+            password,
+            salt.to_string(),
+            params.clone()
+        ))
+        .unwrap();
+        let peppered = time_function!(derive_key_from_password_with_pepper(
+            password,
+            salt.to_string(),
+            params.clone(),
+            b"pepper"
+        ))
+        .unwrap();
+        let peppered_again =
+            derive_key_from_password_with_pepper(password, salt.to_string(), params, b"pepper")
+                .unwrap();
+
+        assert_ne!(plain, peppered);
+        assert_eq!(peppered, peppered_again);
+    }
+
+    #[test]
+    fn test_derive_subkey() {
+        let master = [7u8; 32];
+        let a = time_function!(derive_subkey(&master, b"tenant:a"));
+        let b = time_function!(derive_subkey(&master, b"tenant:b"));
+
+        assert_eq!(a, derive_subkey(&master, b"tenant:a"));
+        assert_ne!(a, b);
+        assert_ne!(a, master);
+    }
+
     #[test]
     fn test_encryption_and_decryption() {
         let key = [1u8; 32];
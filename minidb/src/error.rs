@@ -31,6 +31,25 @@ pub enum Error {
     #[error("hashing error: {0}")]
     Hashing(argon2::password_hash::Error),
 
+    /// An environment variable read by [`MiniDBBuilder::with_env_overrides`](crate::MiniDBBuilder::with_env_overrides)
+    /// has a value that can't be parsed
+    #[error("invalid value `{value}` for environment variable `{name}`")]
+    InvalidEnvVar {
+        /// The name of the variable
+        name: String,
+
+        /// The value of the variable
+        value: String,
+    },
+
+    /// The bytes aren't a record exported from a compatible table
+    #[error("invalid exported record: {0}")]
+    InvalidExport(String),
+
+    /// Something happened while doing file operations
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
     /// Something happened while serializing to JSON
     #[error("JSON error: {0}")]
     JSON(#[from] serde_json::Error),
@@ -39,10 +58,113 @@ pub enum Error {
     #[error("derived key length mismatch: expected 32 bytes, got {0}")]
     KeyLengthMismatch(usize),
 
+    /// The database is locked by another process through its lock file
+    #[error("database is locked by {}: {}", .pid.map_or_else(|| "an unknown process".to_string(), |pid| format!("process {pid}")), .path.display())]
+    Locked {
+        /// The path to the lock file
+        path: std::path::PathBuf,
+
+        /// The PID written in the lock file, if it could be read
+        pid: Option<u32>,
+    },
+
     /// Missing hash output
     #[error("missing hash output")]
     MissingHashOutput,
 
+    /// The key file of a database that already has data doesn't exist, a new key wouldn't decrypt it
+    #[error("key file not found for an existing database: {}", .0.display())]
+    MissingKeyFile(std::path::PathBuf),
+
+    /// The path of the database file is unknown because it wasn't opened with [`MiniDB::builder`](crate::MiniDB::builder)
+    #[error("the database path is unknown")]
+    MissingPath,
+
+    /// An operation that needs encryption was called on a database opened without a key
+    #[error("the database is not encrypted")]
+    NotEncrypted,
+
+    /// A record of a [`Table::SINGLETON`](crate::Table::SINGLETON) table was written under another ID than
+    /// [`SINGLETON_ID`](crate::SINGLETON_ID)
+    #[error("table `{table}` only holds the singleton record, not `{id}`")]
+    NotSingleton {
+        /// The name of the table
+        table: String,
+
+        /// The ID the record was written under
+        id: String,
+    },
+
+    /// The field isn't marked with `#[unique]`, so more than one record can have the value
+    #[error("field `{field}` of table `{table}` isn't unique")]
+    NotUnique {
+        /// The name of the table
+        table: String,
+
+        /// The name of the field
+        field: String,
+    },
+
+    /// An option that changes how records are written doesn't match the value the database was created with
+    #[error("option `{option}` must stay {stored} for this database")]
+    OptionMismatch {
+        /// The name of the [`MiniDBBuilder`](crate::MiniDBBuilder) option
+        option: String,
+
+        /// The value stored in the database
+        stored: bool,
+    },
+
+    /// The database moved with [`MiniDB::relocate`](crate::MiniDB::relocate) but the original file couldn't be removed
+    #[error("database moved but the original file `{}` couldn't be removed: {source}", .path.display())]
+    OriginalNotRemoved {
+        /// The path to the original file
+        path: std::path::PathBuf,
+
+        /// Why it couldn't be removed
+        source: std::io::Error,
+    },
+
+    /// The data doesn't fit the database quota
+    #[error("quota exceeded: {used} bytes used, {max} allowed")]
+    QuotaExceeded {
+        /// The bytes the data would use
+        used: u64,
+
+        /// The maximum bytes allowed
+        max: u64,
+    },
+
+    /// A record with the same ID already exists in the table
+    #[error("record `{id}` already exists in table `{table}`")]
+    RecordExists {
+        /// The name of the table
+        table: String,
+
+        /// The ID of the record
+        id: String,
+    },
+
+    /// The record was pinned with [`MiniDB::pin`](crate::MiniDB::pin) and can't be removed
+    #[error("record `{id}` of table `{table}` is pinned")]
+    RecordPinned {
+        /// The name of the table
+        table: String,
+
+        /// The ID of the record
+        id: String,
+    },
+
+    /// The stored bytes of a record are over the size limit
+    #[error("record is {size} bytes, the limit is {max}")]
+    RecordTooLarge {
+        /// The size of the record in bytes
+        size: usize,
+
+        /// The maximum size allowed
+        max: usize,
+    },
+
     /// Something happened while serializing or deserializing
     #[error("serialization error: {0}")]
     Serialization(#[from] postcard::Error),
@@ -55,6 +177,10 @@ pub enum Error {
     #[error("table error: {0}")]
     Table(#[from] redb::TableError),
 
+    /// The table was frozen with [`MiniDB::freeze`](crate::MiniDB::freeze) and can't be written to
+    #[error("table `{0}` is frozen")]
+    TableFrozen(String),
+
     /// Something happened while initializing a table but not using it
     #[error("failed to initialize table `{name}`: {source}")]
     TableInitialization {
@@ -69,6 +195,48 @@ pub enum Error {
     /// Something happened while doing transaction operations
     #[error("transaction error: {0}")]
     Transaction(#[from] redb::TransactionError),
+
+    /// The database was written in a format this version can't read, usually by a newer version of MiniDB
+    #[error("unsupported database format: version {}, codec {}", .0.version, .0.codec)]
+    UnsupportedFormat(crate::FormatInfo),
+
+    /// Another record already has the same value in a `#[unique]` field, or in a field of the same
+    /// [`MiniDBBuilder::unique_across`](crate::MiniDBBuilder::unique_across) group
+    #[error("record `{id}` of table `{table}` already has the same `{field}`")]
+    UniqueViolation {
+        /// The name of the table
+        table: String,
+
+        /// The name of the field
+        field: String,
+
+        /// The ID of the record that has the value
+        id: String,
+    },
+
+    /// The stored record has data the current model doesn't know about, only returned in strict mode
+    #[error("stored record has {0} bytes of unknown fields")]
+    UnknownFields(usize),
+
+    /// The field has no secondary index, mark it with `#[index]`
+    #[error("field `{field}` of table `{table}` isn't indexed")]
+    UnknownIndex {
+        /// The name of the table
+        table: String,
+
+        /// The name of the field
+        field: String,
+    },
+
+    /// The password is too easy to guess
+    #[error("password is too weak: scored {score} out of 4, at least {min} is required")]
+    WeakPassword {
+        /// The estimated score of the password
+        score: u8,
+
+        /// The minimum score required
+        min: u8,
+    },
 }
 
 impl From<argon2::password_hash::Error> for Error {
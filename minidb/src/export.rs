@@ -0,0 +1,185 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt::Debug;
+
+use crate::{
+    Table,
+    error::{Error, Result},
+};
+use redb::TableHandle;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The magic bytes at the start of every exported record
+const EXPORT_MAGIC: [u8; 4] = *b"MNDR";
+
+/// The current version of the export format
+const EXPORT_VERSION: u32 = 1;
+
+/// A single record exported by [`MiniDB::export_record`](crate::MiniDB::export_record)
+///
+/// The data is always plain postcard so the blob can be imported into a database with a different key
+#[derive(Serialize, Deserialize)]
+struct RecordExport {
+    magic: [u8; 4],
+    version: u32,
+    table: String,
+    fingerprint: [u8; 32],
+    data: Vec<u8>,
+}
+
+/// What [`MiniDB::import_record_with`](crate::MiniDB::import_record_with) does when a record with the same ID already
+/// exists
+pub enum ImportPolicy<T> {
+    /// Keep the existing record and drop the imported one
+    Skip,
+
+    /// Replace the existing record, like [`MiniDB::import_record`](crate::MiniDB::import_record)
+    Overwrite,
+
+    /// Fail with [`Error::RecordExists`]
+    Fail,
+
+    /// Store the record returned by the function, called with the existing and the imported record. The ID of the
+    /// merged record is reset to the imported one
+    Merge(Box<dyn FnOnce(T, T) -> T>),
+}
+
+impl<T> ImportPolicy<T> {
+    /// Creates an [`ImportPolicy::Merge`] from a closure, which can capture its environment
+    ///
+    /// ## Arguments
+    ///
+    /// * `merge` - Takes the existing and the imported record and returns the one to store
+    #[must_use]
+    pub fn merge<F>(merge: F) -> Self
+    where
+        F: FnOnce(T, T) -> T + 'static,
+    {
+        Self::Merge(Box::new(merge))
+    }
+}
+
+impl<T> Debug for ImportPolicy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Skip => f.write_str("Skip"),
+            Self::Overwrite => f.write_str("Overwrite"),
+            Self::Fail => f.write_str("Fail"),
+            Self::Merge(_) => f.debug_tuple("Merge").finish_non_exhaustive(),
+        }
+    }
+}
+
+/// What redacted fields are replaced with
+const REDACTED: &str = "[REDACTED]";
+
+/// Serializes a record to JSON with its [`Table::REDACTED_FIELDS`] masked
+pub(crate) fn to_redacted_json<T>(item: &T) -> Result<serde_json::Value>
+where
+    T: Table,
+{
+    let mut value = serde_json::to_value(item)?;
+
+    for field in T::REDACTED_FIELDS {
+        let slot = match &mut value {
+            serde_json::Value::Object(fields) => fields.get_mut(*field),
+            // tuple structs are arrays, their fields are named by position
+            serde_json::Value::Array(values) => field
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| values.get_mut(index)),
+            _ => None,
+        };
+
+        if let Some(slot) = slot {
+            *slot = serde_json::Value::String(REDACTED.to_string());
+        }
+    }
+
+    Ok(value)
+}
+
+/// Hashes the table name and the field names of a record, so an import into a different model is rejected
+fn fingerprint<T>(item: &T) -> Result<[u8; 32]>
+where
+    T: Table,
+{
+    let mut hasher = Sha256::new();
+    hasher.update(T::TABLE.name().as_bytes());
+
+    if let serde_json::Value::Object(fields) = serde_json::to_value(item)? {
+        for field in fields.keys() {
+            hasher.update([0]);
+            hasher.update(field.as_bytes());
+        }
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Encodes a record as a self-contained blob, with its [`Table::REDACTED_FIELDS`] reset by [`Table::redact`]
+pub(crate) fn encode<T>(item: &T) -> Result<Vec<u8>>
+where
+    T: Table,
+{
+    // models don't have to be `Clone`, so the copy goes through postcard
+    let redacted: T;
+    let item = if T::REDACTED_FIELDS.is_empty() {
+        item
+    } else {
+        redacted = {
+            let mut copy: T = postcard::from_bytes(&postcard::to_stdvec(item)?)?;
+            copy.redact();
+            copy
+        };
+        &redacted
+    };
+
+    let export = RecordExport {
+        magic: EXPORT_MAGIC,
+        version: EXPORT_VERSION,
+        table: T::TABLE.name().to_string(),
+        fingerprint: fingerprint(item)?,
+        data: postcard::to_stdvec(item)?,
+    };
+
+    Ok(postcard::to_stdvec(&export)?)
+}
+
+/// Decodes a blob created by [`encode`], checking it belongs to the table model `T`
+pub(crate) fn decode<T>(bytes: &[u8]) -> Result<T>
+where
+    T: Table,
+{
+    let export: RecordExport = postcard::from_bytes(bytes)?;
+    if export.magic != EXPORT_MAGIC {
+        return Err(Error::InvalidExport("not an exported record".to_string()));
+    }
+    if export.version > EXPORT_VERSION {
+        return Err(Error::InvalidExport(format!(
+            "unsupported version {}",
+            export.version
+        )));
+    }
+    if export.table != T::TABLE.name() {
+        return Err(Error::InvalidExport(format!(
+            "record belongs to table `{}`, not `{}`",
+            export.table,
+            T::TABLE.name()
+        )));
+    }
+
+    let mut item: T = postcard::from_bytes(&export.data)?;
+    if fingerprint(&item)? != export.fingerprint {
+        return Err(Error::InvalidExport(format!(
+            "record doesn't match the schema of table `{}`",
+            export.table
+        )));
+    }
+
+    item.rehydrate();
+    Ok(item)
+}
@@ -0,0 +1,111 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    META_TABLE,
+    error::{Error, Result},
+};
+use redb::{ReadableTable, WriteTransaction};
+use serde::{Deserialize, Serialize};
+
+pub(crate) const META_KEY_FORMAT: &str = "format";
+
+/// The prefix of the meta keys holding the options that change how records are written
+const META_KEY_OPTION_PREFIX: &str = "option:";
+const MAGIC: [u8; 4] = *b"MNDB";
+const CODEC: &str = "postcard";
+
+/// The current version of the database format
+const FORMAT_VERSION: u32 = 1;
+
+/// Describes how a database is stored, kept in the metadata so newer versions know how to read or migrate it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatInfo {
+    /// Identifies the database as a MiniDB database, always `MNDB`
+    pub magic: [u8; 4],
+
+    /// The version of the format
+    pub version: u32,
+
+    /// The serialization format of the records
+    pub codec: String,
+}
+
+impl FormatInfo {
+    /// Returns the format written by this version of MiniDB
+    pub(crate) fn current() -> Self {
+        Self {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            codec: CODEC.to_string(),
+        }
+    }
+}
+
+/// Reads the stored format
+fn read_format(txn: &WriteTransaction) -> Result<Option<FormatInfo>> {
+    let meta = txn.open_table(META_TABLE)?;
+    let value = meta.get(META_KEY_FORMAT)?;
+
+    if let Some(bytes) = value {
+        Ok(Some(postcard::from_bytes(bytes.value())?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Stores an option that changes how records are written, or fails if it was stored with another value
+///
+/// Databases opened before the option was stored take the value they're opened with next
+pub(crate) fn pin_option(txn: &WriteTransaction, name: &str, value: bool) -> Result<()> {
+    let key = format!("{META_KEY_OPTION_PREFIX}{name}");
+    let mut meta = txn.open_table(META_TABLE)?;
+    let stored: Option<bool> = meta
+        .get(key.as_str())?
+        .map(|bytes| postcard::from_bytes(bytes.value()))
+        .transpose()?;
+
+    match stored {
+        Some(stored) if stored != value => Err(Error::OptionMismatch {
+            option: name.to_string(),
+            stored,
+        }),
+        Some(_) => Ok(()),
+        None => {
+            meta.insert(key.as_str(), postcard::to_stdvec(&value)?.as_slice())?;
+            Ok(())
+        }
+    }
+}
+
+/// Checks the stored format and migrates databases written by older versions, called when building the database
+pub(crate) fn upgrade(txn: &WriteTransaction) -> Result<()> {
+    let version = match read_format(txn)? {
+        Some(info) if info.magic != MAGIC || info.codec != CODEC => {
+            return Err(Error::UnsupportedFormat(info));
+        }
+        Some(info) => info.version,
+
+        // databases created before the format was stored
+        None => 0,
+    };
+
+    if version > FORMAT_VERSION {
+        return Err(Error::UnsupportedFormat(FormatInfo {
+            version,
+            ..FormatInfo::current()
+        }));
+    }
+    if version == FORMAT_VERSION {
+        return Ok(());
+    }
+
+    // version 0 has the same layout as 1, it just didn't store its format.
+    // Future migrations go here, one step per version
+
+    let bytes = postcard::to_stdvec(&FormatInfo::current())?;
+    let mut meta = txn.open_table(META_TABLE)?;
+    meta.insert(META_KEY_FORMAT, bytes.as_slice())?;
+    Ok(())
+}
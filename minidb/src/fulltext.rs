@@ -0,0 +1,154 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{Transaction, codec::Codec, error::Result, model::Table};
+use redb::{
+    MultimapTableDefinition, ReadTransaction, ReadableTable, TableHandle, WriteTransaction,
+};
+
+/// The prefix of the full-text index tables
+const FULLTEXT_PREFIX: &str = "fulltext:";
+
+/// How many times each word appears in a record
+pub(crate) type Terms = BTreeMap<String, u32>;
+
+/// A record matched by [`MiniDB::search`](crate::MiniDB::search)
+#[derive(Debug, Clone)]
+pub struct SearchHit<T> {
+    /// The record
+    pub item: T,
+
+    /// How well the record matches the query, higher is better. Only comparable between hits of the same search
+    pub score: f64,
+}
+
+/// Returns the definition of the full-text index of a table, the words are mapped to `(id, occurrences)` pairs
+fn definition(name: &str) -> MultimapTableDefinition<'_, &'static [u8], (&'static str, u32)> {
+    MultimapTableDefinition::new(name)
+}
+
+/// Splits text into lowercase words, anything that isn't a letter or a digit separates them
+pub(crate) fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Returns the words of the [`Table::FULLTEXT`] fields of a record, its computed fields are taken as they are
+pub(crate) fn terms_of<T>(item: &T) -> Terms
+where
+    T: Table,
+{
+    let mut terms = Terms::new();
+    for text in item.fulltext_values() {
+        for word in tokenize(text) {
+            *terms.entry(word).or_default() += 1;
+        }
+    }
+
+    terms
+}
+
+/// Adds the words of a record to the full-text index of its table
+pub(crate) fn add(
+    txn: &WriteTransaction,
+    codec: &Codec,
+    table: &str,
+    id: &str,
+    terms: &Terms,
+) -> Result<()> {
+    let name = format!("{FULLTEXT_PREFIX}{table}");
+    let mut index = txn.open_multimap_table(definition(&name))?;
+    for (word, count) in terms {
+        index.insert(codec.index_token(word.as_bytes()).as_slice(), (id, *count))?;
+    }
+
+    Ok(())
+}
+
+/// Removes the words of a record from the full-text index of its table
+pub(crate) fn remove(
+    txn: &WriteTransaction,
+    codec: &Codec,
+    table: &str,
+    id: &str,
+    terms: &Terms,
+) -> Result<()> {
+    let name = format!("{FULLTEXT_PREFIX}{table}");
+    let mut index = txn.open_multimap_table(definition(&name))?;
+    for (word, count) in terms {
+        index.remove(codec.index_token(word.as_bytes()).as_slice(), (id, *count))?;
+    }
+
+    Ok(())
+}
+
+/// Drops the full-text index of a table and indexes every record again, returns how many records were indexed
+pub(crate) fn rebuild<T>(txn: &Transaction) -> Result<usize>
+where
+    T: Table,
+{
+    let name = T::TABLE.name();
+    txn.txn
+        .delete_multimap_table(definition(&format!("{FULLTEXT_PREFIX}{name}")))?;
+
+    let mut pending = Vec::new();
+    {
+        let table = txn.txn.open_table(T::TABLE)?;
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let item: T = txn.db.codec.decode_record(key.value(), value.value())?;
+            pending.push((key.value().to_string(), terms_of(&item)));
+        }
+    }
+
+    for (id, terms) in &pending {
+        add(&txn.txn, &txn.db.codec, name, id, terms)?;
+    }
+
+    Ok(pending.len())
+}
+
+/// Scores the records of a table that contain any word of the query with TF-IDF, best matches first and ties in ID
+/// order
+///
+/// `total` is the number of records in the table, words found in fewer records weigh more
+pub(crate) fn rank(
+    txn: &ReadTransaction,
+    codec: &Codec,
+    table: &str,
+    total: u64,
+    query: &str,
+) -> Result<Vec<(String, f64)>> {
+    let name = format!("{FULLTEXT_PREFIX}{table}");
+    let index = match txn.open_multimap_table(definition(&name)) {
+        Ok(index) => index,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    for word in tokenize(query).collect::<BTreeSet<_>>() {
+        let postings = index.get(codec.index_token(word.as_bytes()).as_slice())?;
+        let found_in = postings.len();
+        if found_in == 0 {
+            continue;
+        }
+
+        // tables with more than 2^52 records aren't a concern
+        #[allow(clippy::cast_precision_loss)]
+        let rarity = (1.0 + total as f64 / found_in as f64).ln();
+        for posting in postings {
+            let posting = posting?;
+            let (id, count) = posting.value();
+            *scores.entry(id.to_string()).or_default() += (1.0 + f64::from(count).ln()) * rarity;
+        }
+    }
+
+    let mut ranked: Vec<_> = scores.into_iter().collect();
+    ranked.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then_with(|| a_id.cmp(b_id)));
+    Ok(ranked)
+}
@@ -0,0 +1,374 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::ops::Bound;
+
+use crate::{
+    META_TABLE, Transaction,
+    codec::Codec,
+    error::{Error, Result},
+    model::Table,
+    record_meta,
+};
+use redb::{
+    MultimapTableDefinition, MultimapTableHandle, ReadTransaction, ReadableMultimapTable,
+    ReadableTable, ReadableTableMetadata, TableHandle, WriteTransaction,
+};
+use serde::{Deserialize, Serialize};
+
+/// The prefix of the index tables and of their markers in the meta table
+const INDEX_PREFIX: &str = "index:";
+
+/// The prefix of the meta keys holding the last record indexed by a build that didn't finish yet
+const INDEX_PROGRESS_PREFIX: &str = "index_progress:";
+
+/// The prefix of the meta keys holding the [`FieldStats`] of the indexed fields
+pub(crate) const STATS_PREFIX: &str = "stats:";
+
+/// How many records [`MiniDB::find_by_index`](crate::MiniDB::find_by_index) indexes per transaction when it has to
+/// build an index first
+pub(crate) const DEFAULT_BUILD_CHUNK: usize = 1000;
+
+/// The context used to derive the key that hides indexed values in encrypted databases
+pub(crate) const INDEX_KEY_CONTEXT: &[u8] = b"minidb:index";
+
+/// The `(field, value)` pairs of an item's indexed fields
+pub(crate) type IndexValues = Vec<(&'static str, Vec<u8>)>;
+
+/// The value distribution of an indexed field, refreshed by [`MiniDB::compact`](crate::MiniDB::compact) and returned
+/// by [`MiniDB::field_stats`](crate::MiniDB::field_stats)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldStats {
+    /// The name of the table
+    pub table: String,
+
+    /// The name of the field
+    pub field: String,
+
+    /// How many records are indexed
+    pub entries: u64,
+
+    /// How many distinct values the field has
+    pub cardinality: u64,
+
+    /// The first value of the index as raw postcard bytes, [`None`] if the index is empty or the database is encrypted
+    ///
+    /// The index is ordered by the serialized bytes, not by the values, so this is the smallest value only for `bool`,
+    /// `u8` and strings of the same length. Decode it with postcard to look at it, but don't take it for the minimum
+    /// of a number field
+    pub min_bytes: Option<Vec<u8>>,
+
+    /// The last value of the index as raw postcard bytes, see [`FieldStats::min_bytes`]
+    pub max_bytes: Option<Vec<u8>>,
+
+    /// When the stats were refreshed, in milliseconds since the Unix epoch
+    pub refreshed_at: u64,
+}
+
+/// Returns the name of the index table of a field, also used as its marker in the meta table
+fn index_name(table: &str, field: &str) -> String {
+    format!("{INDEX_PREFIX}{table}:{field}")
+}
+
+/// Returns the names of the index tables of a table model
+pub(crate) fn names<T>() -> Vec<String>
+where
+    T: Table,
+{
+    T::INDEXES
+        .iter()
+        .map(|field| index_name(T::TABLE.name(), field))
+        .collect()
+}
+
+/// Returns the values of the indexed fields of a record, its computed fields are taken as they are
+pub(crate) fn values_of<T>(item: &T) -> Result<IndexValues>
+where
+    T: Table,
+{
+    if T::INDEXES.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    item.index_values()
+}
+
+/// Adds the entries of a record to the index tables
+pub(crate) fn add(
+    txn: &WriteTransaction,
+    codec: &Codec,
+    table: &str,
+    id: &str,
+    values: &IndexValues,
+) -> Result<()> {
+    for (field, value) in values {
+        let name = index_name(table, field);
+        let mut index =
+            txn.open_multimap_table(MultimapTableDefinition::<&[u8], &str>::new(&name))?;
+        index.insert(codec.index_token(value).as_slice(), id)?;
+    }
+
+    Ok(())
+}
+
+/// Removes the entries of a record from the index tables
+pub(crate) fn remove(
+    txn: &WriteTransaction,
+    codec: &Codec,
+    table: &str,
+    id: &str,
+    values: &IndexValues,
+) -> Result<()> {
+    for (field, value) in values {
+        let name = index_name(table, field);
+        let mut index =
+            txn.open_multimap_table(MultimapTableDefinition::<&[u8], &str>::new(&name))?;
+        index.remove(codec.index_token(value).as_slice(), id)?;
+    }
+
+    Ok(())
+}
+
+/// Makes sure no other record has the same value in any of the [`Table::UNIQUE`] fields of an item
+///
+/// The index is used once it's built, which is right away for tables registered while empty. Before that the table is
+/// scanned so records written before the field was marked unique are checked too
+pub(crate) fn check_unique<T, R>(
+    txn: &WriteTransaction,
+    codec: &Codec,
+    table: &R,
+    id: &str,
+    values: &IndexValues,
+) -> Result<()>
+where
+    T: Table,
+    R: ReadableTable<&'static str, &'static [u8]>,
+{
+    let name = T::TABLE.name();
+    let meta = txn.open_table(META_TABLE)?;
+
+    for (field, value) in values.iter().filter(|(field, _)| T::UNIQUE.contains(field)) {
+        let violation = |other: String| Error::UniqueViolation {
+            table: name.to_string(),
+            field: (*field).to_string(),
+            id: other,
+        };
+
+        let index_table = index_name(name, field);
+        if meta.get(index_table.as_str())?.is_some() {
+            let index =
+                txn.open_multimap_table(MultimapTableDefinition::<&[u8], &str>::new(&index_table))?;
+            for other in index.get(codec.index_token(value).as_slice())? {
+                let other = other?;
+                if other.value() != id {
+                    return Err(violation(other.value().to_string()));
+                }
+            }
+            continue;
+        }
+
+        for entry in table.iter()? {
+            let (key, bytes) = entry?;
+            if key.value() == id {
+                continue;
+            }
+
+            let other: T = codec.decode_record(key.value(), bytes.value())?;
+            if values_of(&other)?
+                .iter()
+                .any(|(other_field, other_value)| other_field == field && other_value == value)
+            {
+                return Err(violation(key.value().to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Marks the indexes of a table as built while it's empty, called when the table is registered
+///
+/// There are no records written before the indexes to catch up on, and every write from then on keeps them up to date,
+/// so the first writes don't have to scan the table to check [`Table::UNIQUE`] fields
+pub(crate) fn mark_built_if_empty<T>(txn: &WriteTransaction) -> Result<()>
+where
+    T: Table,
+{
+    if T::INDEXES.is_empty() || !txn.open_table(T::TABLE)?.is_empty()? {
+        return Ok(());
+    }
+
+    let name = T::TABLE.name();
+    let mut meta = txn.open_table(META_TABLE)?;
+    for field in T::INDEXES {
+        meta.remove(format!("{INDEX_PROGRESS_PREFIX}{name}:{field}").as_str())?;
+        meta.insert(index_name(name, field).as_str(), [].as_slice())?;
+    }
+
+    Ok(())
+}
+
+/// Checks if the index of a field was built for the records written before it existed
+pub(crate) fn is_built(txn: &ReadTransaction, table: &str, field: &str) -> Result<bool> {
+    let meta = txn.open_table(META_TABLE)?;
+    Ok(meta.get(index_name(table, field).as_str())?.is_some())
+}
+
+/// Indexes the next chunk of records for one field, resuming after the last chunk, and marks the index as built
+/// once the whole table was scanned
+///
+/// Writes made between chunks keep the index up to date themselves, so records written behind the cursor don't need
+/// to be caught up and the ones ahead of it are indexed again harmlessly
+///
+/// Returns whether the index is built
+pub(crate) fn build_chunk<T>(txn: &Transaction, field: &str, chunk_size: usize) -> Result<bool>
+where
+    T: Table,
+{
+    let name = T::TABLE.name();
+    let progress_key = format!("{INDEX_PROGRESS_PREFIX}{name}:{field}");
+
+    let mut meta = txn.txn.open_table(META_TABLE)?;
+    if meta.get(index_name(name, field).as_str())?.is_some() {
+        return Ok(true);
+    }
+    let cursor: Option<String> = meta
+        .get(progress_key.as_str())?
+        .map(|bytes| postcard::from_bytes(bytes.value()))
+        .transpose()?;
+
+    let mut pending = Vec::new();
+    let mut done = true;
+    {
+        let table = txn.txn.open_table(T::TABLE)?;
+        let start = cursor.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        for entry in table.range::<&str>((start, Bound::Unbounded))? {
+            if pending.len() == chunk_size {
+                done = false;
+                break;
+            }
+
+            let (key, value) = entry?;
+            let item: T = txn.db.codec.decode_record(key.value(), value.value())?;
+            let values: IndexValues = values_of(&item)?
+                .into_iter()
+                .filter(|(indexed, _)| *indexed == field)
+                .collect();
+            pending.push((key.value().to_string(), values));
+        }
+    }
+
+    for (id, values) in &pending {
+        add(&txn.txn, &txn.db.codec, name, id, values)?;
+    }
+
+    if done {
+        meta.remove(progress_key.as_str())?;
+        meta.insert(index_name(name, field).as_str(), [].as_slice())?;
+    } else if let Some((last, _)) = pending.last() {
+        meta.insert(progress_key.as_str(), postcard::to_stdvec(last)?.as_slice())?;
+    }
+
+    Ok(done)
+}
+
+/// Recalculates the [`FieldStats`] of every index table, dropping the stats of indexes that no longer exist
+pub(crate) fn refresh_stats(txn: &WriteTransaction, codec: &Codec) -> Result<()> {
+    let mut meta = txn.open_table(META_TABLE)?;
+    meta.retain_in::<&str, _>(STATS_PREFIX.., |key, _| !key.starts_with(STATS_PREFIX))?;
+
+    let refreshed_at = record_meta::now_millis();
+    // values are hidden behind a keyed hash in encrypted databases so their order means nothing
+    let hidden = codec.index_key.is_some();
+
+    for handle in txn.list_multimap_tables()? {
+        let Some((table, field)) = handle
+            .name()
+            .strip_prefix(INDEX_PREFIX)
+            .and_then(|name| name.rsplit_once(':'))
+        else {
+            continue;
+        };
+
+        let index =
+            txn.open_multimap_table(MultimapTableDefinition::<&[u8], &str>::new(handle.name()))?;
+        let mut stats = FieldStats {
+            table: table.to_string(),
+            field: field.to_string(),
+            entries: 0,
+            cardinality: 0,
+            min_bytes: None,
+            max_bytes: None,
+            refreshed_at,
+        };
+        for entry in index.iter()? {
+            let (value, ids) = entry?;
+            stats.entries += ids.len();
+            stats.cardinality += 1;
+
+            if !hidden {
+                stats
+                    .min_bytes
+                    .get_or_insert_with(|| value.value().to_vec());
+                stats.max_bytes = Some(value.value().to_vec());
+            }
+        }
+
+        meta.insert(
+            format!("{STATS_PREFIX}{table}:{field}").as_str(),
+            postcard::to_stdvec(&stats)?.as_slice(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Drops the index tables, build markers and build progress of every index that isn't registered now
+///
+/// Records written while an index isn't registered aren't added to it, so it has to be built again from scratch the
+/// next time it is
+pub(crate) fn forget_unregistered(txn: &WriteTransaction, registered: &[String]) -> Result<()> {
+    let is_registered = |name: &str| registered.iter().any(|index| index == name);
+
+    let mut meta = txn.open_table(META_TABLE)?;
+    meta.retain_in::<&str, _>(INDEX_PREFIX.., |key, _| {
+        !key.starts_with(INDEX_PREFIX) || is_registered(key)
+    })?;
+    meta.retain_in::<&str, _>(INDEX_PROGRESS_PREFIX.., |key, _| {
+        key.strip_prefix(INDEX_PROGRESS_PREFIX)
+            .is_none_or(|index| is_registered(&format!("{INDEX_PREFIX}{index}")))
+    })?;
+    drop(meta);
+
+    for handle in txn.list_multimap_tables()? {
+        if handle.name().starts_with(INDEX_PREFIX) && !is_registered(handle.name()) {
+            txn.delete_multimap_table(handle)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the IDs of the records whose indexed field has the given serialized value, in ID order
+pub(crate) fn lookup(
+    txn: &ReadTransaction,
+    codec: &Codec,
+    table: &str,
+    field: &str,
+    value: &[u8],
+) -> Result<Vec<String>> {
+    let name = index_name(table, field);
+    let index = match txn.open_multimap_table(MultimapTableDefinition::<&[u8], &str>::new(&name)) {
+        Ok(index) => index,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut ids = Vec::new();
+    for id in index.get(codec.index_token(value).as_slice())? {
+        ids.push(id?.value().to_string());
+    }
+
+    Ok(ids)
+}
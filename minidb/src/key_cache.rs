@@ -0,0 +1,102 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::ArgonKey;
+
+/// Storage for keys derived from passwords, so reopening a database doesn't pay the Argon2 cost every time
+///
+/// Entries are identified by the salt of the database followed by a keyed hash of the password, the pepper and the
+/// Argon2 parameters, so a cached key is only used for the password and pepper it was derived from. Anything that can
+/// read the cache can decrypt the database, store it somewhere at least as protected as the database itself (e.g. an
+/// OS keyring)
+pub trait KeyCache {
+    /// Returns the cached key for an entry, if any
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The identifier of the entry
+    fn get(&self, id: &str) -> Option<ArgonKey>;
+
+    /// Stores a derived key
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The identifier of the entry
+    /// * `key` - The derived key
+    fn put(&self, id: &str, key: &ArgonKey);
+}
+
+/// A [`KeyCache`] that keeps the keys in memory, useful when the same process opens a database more than once
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use minidb::{KeySource, MemoryKeyCache, MiniDB};
+///
+/// let cache = Arc::new(MemoryKeyCache::default());
+/// let db = MiniDB::builder("test.redb")
+///     // skipping table registering for convenience
+///     .key_source(KeySource::Password("secretpassword".to_string()))
+///     .key_cache(cache.clone())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Default)]
+pub struct MemoryKeyCache {
+    keys: Mutex<HashMap<String, ArgonKey>>,
+}
+
+impl Debug for MemoryKeyCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryKeyCache").finish_non_exhaustive()
+    }
+}
+
+impl MemoryKeyCache {
+    /// Removes every cached key
+    pub fn clear(&self) {
+        self.keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+impl KeyCache for MemoryKeyCache {
+    fn get(&self, id: &str) -> Option<ArgonKey> {
+        self.keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .copied()
+    }
+
+    fn put(&self, id: &str, key: &ArgonKey) {
+        self.keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id.to_string(), *key);
+    }
+}
+
+impl<C> KeyCache for std::sync::Arc<C>
+where
+    C: KeyCache + ?Sized,
+{
+    fn get(&self, id: &str) -> Option<ArgonKey> {
+        (**self).get(id)
+    }
+
+    fn put(&self, id: &str, key: &ArgonKey) {
+        (**self).put(id, key);
+    }
+}
@@ -0,0 +1,120 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    error::Result,
+    record_meta::{from_millis, meta_key, now_millis},
+};
+use redb::{ReadTransaction, ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+
+/// Holds the active leases, keyed like [`RECORD_META_TABLE`](crate::record_meta::RECORD_META_TABLE)
+pub(crate) const LEASE_TABLE: TableDefinition<&'static str, &[u8]> = TableDefinition::new("leases");
+
+/// A claim on a record, returned by [`MiniDB::claim`](crate::MiniDB::claim)
+///
+/// Other claims on the same record fail until the lease expires or is given back with
+/// [`MiniDB::release`](crate::MiniDB::release)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    table: String,
+    id: String,
+    token: String,
+    expires: SystemTime,
+}
+
+impl Lease {
+    /// Returns the ID of the claimed record
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns when the lease expires
+    #[must_use]
+    pub fn expires(&self) -> SystemTime {
+        self.expires
+    }
+}
+
+/// The stored lease, the token tells the holder apart from a later claim on the same record
+#[derive(Serialize, Deserialize)]
+struct StoredLease {
+    token: String,
+    expires: u64,
+}
+
+/// Claims a record if it isn't claimed already or the previous lease expired
+pub(crate) fn try_claim(
+    txn: &WriteTransaction,
+    table: &str,
+    id: &str,
+    ttl: Duration,
+) -> Result<Option<Lease>> {
+    let mut leases = txn.open_table(LEASE_TABLE)?;
+    let key = meta_key(table, id);
+    let now = now_millis();
+
+    let current: Option<StoredLease> = leases
+        .get(key.as_str())?
+        .map(|bytes| postcard::from_bytes(bytes.value()))
+        .transpose()?;
+    if current.is_some_and(|lease| lease.expires > now) {
+        return Ok(None);
+    }
+
+    let stored = StoredLease {
+        token: cuid2::slug(),
+        expires: now.saturating_add(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX)),
+    };
+    leases.insert(key.as_str(), postcard::to_stdvec(&stored)?.as_slice())?;
+
+    Ok(Some(Lease {
+        table: table.to_string(),
+        id: id.to_string(),
+        token: stored.token,
+        expires: from_millis(stored.expires),
+    }))
+}
+
+/// Removes a lease if it's still held by `lease`
+pub(crate) fn release(txn: &WriteTransaction, lease: &Lease) -> Result<bool> {
+    let mut leases = txn.open_table(LEASE_TABLE)?;
+    let key = meta_key(&lease.table, &lease.id);
+
+    let current: Option<StoredLease> = leases
+        .get(key.as_str())?
+        .map(|bytes| postcard::from_bytes(bytes.value()))
+        .transpose()?;
+    if current.is_none_or(|current| current.token != lease.token) {
+        return Ok(false);
+    }
+
+    leases.remove(key.as_str())?;
+    Ok(true)
+}
+
+/// Drops the lease of a removed record whoever holds it, so a record later written under the same ID starts unclaimed
+pub(crate) fn forget(txn: &WriteTransaction, table: &str, id: &str) -> Result<()> {
+    txn.open_table(LEASE_TABLE)?
+        .remove(meta_key(table, id).as_str())?;
+    Ok(())
+}
+
+/// Checks if a record has a lease that didn't expire yet
+pub(crate) fn is_claimed(txn: &ReadTransaction, table: &str, id: &str) -> Result<bool> {
+    let leases = match txn.open_table(LEASE_TABLE) {
+        Ok(leases) => leases,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    let Some(bytes) = leases.get(meta_key(table, id).as_str())? else {
+        return Ok(false);
+    };
+    let stored: StoredLease = postcard::from_bytes(bytes.value())?;
+    Ok(stored.expires > now_millis())
+}
@@ -18,6 +18,10 @@
 //! * Type-safe operations (mostly)
 //! * Optional encryption using [XChaCha20Poly1305]
 //! * Includes derive macros (e.g., `#[derive(Table)]`) for easy table definition
+//! * Optional compile-time table registry (`registry` feature) so every derived table can be registered at once
+//! * Optional password strength check (`password-strength` feature) to reject trivially weak encryption passwords
+//! * Optional latency profiling (`profiling` feature) that breaks a write and a read down into steps on the actual hardware
+//! * Optional [secrecy](https://crates.io/crates/secrecy) support (`secrecy` feature) so passwords don't have to live in plain `String`s
 //! * Re-exports [serde] for convenience
 //! * Re-exports [redb] and some direct/less-opinionated methods for advanced usage
 //! * "Relational" (requires manual management of foreign keys)
@@ -94,32 +98,109 @@
 //! Licensed under the Mozilla Public License 2.0 ([MPL-2.0](https://www.mozilla.org/en-US/MPL/2.0/)).
 
 mod builder;
+mod cache_layer;
+mod codec;
 mod encryption;
 mod error;
+mod export;
+mod format;
+mod fulltext;
+mod index;
+mod key_cache;
+mod lease;
+mod lock;
 mod model;
+mod outbox;
+mod pin;
+#[cfg(feature = "profiling")]
+mod profile;
+mod query;
+mod quota;
+mod record_meta;
+#[cfg(feature = "registry")]
+mod registry;
+mod singleton;
+mod snapshot;
+#[cfg(feature = "password-strength")]
+mod strength;
+mod sync;
 mod testing;
 mod transaction;
-
+mod unique_group;
+
+#[cfg(feature = "profiling")]
+pub use crate::profile::BenchProfile;
+#[cfg(feature = "registry")]
+pub use crate::registry::TableRegistration;
+#[cfg(feature = "password-strength")]
+pub use crate::strength::password_strength;
 pub use crate::{
     builder::{KeySource, MiniDBBuilder},
+    cache_layer::CacheLayer,
+    encryption::{ArgonParams, ArgonPresets, derive_subkey},
     error::Error,
-    model::{Table, TableIterator},
+    export::ImportPolicy,
+    format::FormatInfo,
+    fulltext::SearchHit,
+    index::FieldStats,
+    key_cache::{KeyCache, MemoryKeyCache},
+    lease::Lease,
+    lock::{LockBackend, LockHolder},
+    model::{Batches, Page, ScanReport, Table, TableInfo, TableIterator},
+    outbox::{Outbox, OutboxEvent},
+    query::{Aggregate, AggregateValue, Field, Filter, GroupBy, Query},
+    quota::{Quota, QuotaPolicy},
+    record_meta::RecordMeta,
+    singleton::{SINGLETON_ID, Singleton},
+    snapshot::Snapshot,
+    sync::{SyncConflict, SyncReport, SyncResolution},
     transaction::Transaction,
 };
 #[cfg(feature = "macros")]
 pub use minidb_macros::Table;
 pub use redb;
+#[cfg(feature = "secrecy")]
+pub use secrecy;
 pub use serde;
 
-use std::{fmt::Debug, path::PathBuf};
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "registry")]
+    pub use inventory;
+    pub use postcard;
+}
+
+/// Registers a table model in the global registry, does nothing without the `registry` feature
+#[cfg(not(feature = "registry"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_table {
+    ($ty:ty) => {};
+}
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    io::{ErrorKind, Read, Write},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use crate::{
-    encryption::{decrypt_bytes, encrypt_bytes},
+    codec::Codec,
+    encryption::destroy_key_file,
     error::Result,
+    lock::{LockFile, recovery_path},
 };
 use argon2::password_hash::{SaltString, rand_core::OsRng};
 use chacha20poly1305::XChaCha20Poly1305;
-use redb::{Database, ReadableDatabase, ReadableTable, ReadableTableMetadata, TableDefinition};
+use redb::{
+    Database, ReadOnlyTable, ReadableDatabase, ReadableTable, ReadableTableMetadata,
+    TableDefinition, TableHandle,
+};
 use serde::{Deserialize, Serialize};
 
 pub(crate) const META_TABLE: TableDefinition<&'static str, &[u8]> = TableDefinition::new("meta");
@@ -127,21 +208,56 @@ pub(crate) const SETTINGS_TABLE: TableDefinition<&'static str, &[u8]> =
     TableDefinition::new("settings");
 
 const META_KEY_SALT: &str = "salt";
+const META_KEY_EXTRA_PREFIX: &str = "extra:";
+pub(crate) const META_KEY_TABLE_PREFIX: &str = "table:";
+pub(crate) const META_KEY_FROZEN_PREFIX: &str = "frozen:";
 
 pub(crate) type ArgonKey = [u8; 32];
 
+/// Flushes a copied file and the directory entry pointing to it, so the original can be removed safely
+fn sync_copy(path: &Path) -> std::io::Result<()> {
+    fs::File::open(path)?.sync_all()?;
+
+    // directories can't be opened as files on Windows, where the metadata is written with the file
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        fs::File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
 /// A MiniDB
 ///
 /// This is a wrapper around [`redb::Database`], but also stores the [`XChaCha20Poly1305`] instance to handle the optional encryption
 pub struct MiniDB {
     db: Database,
-    cipher: Option<XChaCha20Poly1305>,
+    codec: Codec,
+    quota: Option<Quota>,
+    record_timestamps: bool,
+    sortable_ids: bool,
+    last_id_time: AtomicU64,
+    id_retries: AtomicU64,
+    cache: Option<Box<dyn CacheLayer>>,
+    cache_namespace: String,
+    path: Option<PathBuf>,
+    key_file: Option<PathBuf>,
+    unique_groups: Vec<unique_group::UniqueMember>,
+    evictors: HashMap<String, quota::Evictor>,
+    // declared after `db` so the file is closed before the lock is released
+    lock: Option<LockFile>,
 }
 
 impl Debug for MiniDB {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MiniDB")
             .field("db", &self.db)
+            .field("quota", &self.quota)
             .finish_non_exhaustive()
     }
 }
@@ -172,6 +288,74 @@ impl MiniDB {
         MiniDBBuilder::new(path)
     }
 
+    /// Retrieves who holds the lock file created by [`LockBackend::PidFile`] for a database, without opening it
+    ///
+    /// Meant for operators recovering from a hung or crashed process, together with [`MiniDB::force_unlock`]. The OS
+    /// lock taken by redb can't be inspected and is released by the OS when the process exits
+    ///
+    /// ## Arguments
+    ///
+    /// * `path` - The path to the database file
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(holder))` if the lock file exists
+    /// * `Ok(None)` if nobody holds the lock
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the lock file can't be read
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// if let Some(holder) = MiniDB::lock_holder("test.redb").unwrap() {
+    ///     println!("locked by {:?} since {:?}, stale: {}", holder.pid, holder.since, holder.stale);
+    /// }
+    /// ```
+    pub fn lock_holder<P>(path: P) -> Result<Option<LockHolder>>
+    where
+        P: AsRef<Path>,
+    {
+        lock::holder(path.as_ref())
+    }
+
+    /// Removes the lock file created by [`LockBackend::PidFile`] for a database, so it can be opened again after its
+    /// owner hung or crashed
+    ///
+    /// The PID read from [`MiniDB::lock_holder`] has to be passed back as confirmation, the lock is left alone if it
+    /// changed hands since. Make sure the process is really gone first, two processes writing the same database at
+    /// once will corrupt it
+    ///
+    /// ## Arguments
+    ///
+    /// * `path` - The path to the database file
+    /// * `pid` - The [`LockHolder::pid`] the lock is expected to be held by
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(true)` if the lock file was removed
+    /// * `Ok(false)` if there's no lock file or it's held by someone else
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the lock file can't be read or removed
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// if let Some(holder) = MiniDB::lock_holder("test.redb").unwrap() {
+    ///     // after checking that the process is gone
+    ///     MiniDB::force_unlock("test.redb", holder.pid).unwrap();
+    /// }
+    /// ```
+    pub fn force_unlock<P>(path: P, pid: Option<u32>) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        lock::force_unlock(path.as_ref(), pid)
+    }
+
     /// Creates a new [`MiniDB`] from a [`redb::Database`]
     ///
     /// If you don't need advanced features then I recommend [`MiniDB::builder`], you can pass the tables to it, the path, and whether or not to use encryption.
@@ -193,7 +377,22 @@ impl MiniDB {
     /// ```
     #[must_use]
     pub fn new(db: Database) -> Self {
-        Self { db, cipher: None }
+        Self {
+            db,
+            codec: Codec::default(),
+            quota: None,
+            record_timestamps: false,
+            sortable_ids: false,
+            last_id_time: AtomicU64::new(0),
+            id_retries: AtomicU64::new(0),
+            cache: None,
+            cache_namespace: String::new(),
+            path: None,
+            key_file: None,
+            unique_groups: Vec::new(),
+            evictors: HashMap::new(),
+            lock: None,
+        }
     }
 
     /// Sets the [`XChaCha20Poly1305`] instance to use, this implies encryption if [Some]
@@ -204,13 +403,113 @@ impl MiniDB {
     ///
     /// * `cipher` - The [`XChaCha20Poly1305`] instance to use
     pub fn set_cipher(&mut self, cipher: XChaCha20Poly1305) {
-        self.cipher = Some(cipher);
+        self.codec.cipher = Some(cipher);
+    }
+
+    /// Sets whether reads should fail when a stored record has fields the current model doesn't know about
+    ///
+    /// I recommend using [`MiniDB::builder`] and setting it with [`MiniDBBuilder::strict`] instead
+    ///
+    /// ## Arguments
+    ///
+    /// * `strict` - Whether to enable strict mode
+    pub fn set_strict(&mut self, strict: bool) {
+        self.codec.strict = strict;
+    }
+
+    /// Sets whether encrypted records are bound to their table and ID
+    ///
+    /// I recommend using [`MiniDB::builder`] and setting it with [`MiniDBBuilder::bind_records`] instead
+    ///
+    /// ## Arguments
+    ///
+    /// * `bind` - Whether to bind records
+    pub fn set_bind_records(&mut self, bind: bool) {
+        self.codec.bind_records = bind;
+    }
+
+    /// Sets the maximum size in bytes of a stored record or setting, [`None`] removes the limit
+    ///
+    /// I recommend using [`MiniDB::builder`] and setting it with [`MiniDBBuilder::max_record_size`] instead
+    ///
+    /// ## Arguments
+    ///
+    /// * `max` - The maximum size in bytes
+    pub fn set_max_record_size(&mut self, max: Option<usize>) {
+        self.codec.max_record_size = max;
+    }
+
+    /// Sets whether the creation and modification times of records are tracked
+    ///
+    /// I recommend using [`MiniDB::builder`] and setting it with [`MiniDBBuilder::record_timestamps`] instead, since
+    /// it also creates the table that holds them
+    ///
+    /// ## Arguments
+    ///
+    /// * `track` - Whether to track timestamps
+    pub fn set_record_timestamps(&mut self, track: bool) {
+        self.record_timestamps = track;
+    }
+
+    /// Sets whether generated IDs sort in insertion order
+    ///
+    /// I recommend using [`MiniDB::builder`] and setting it with [`MiniDBBuilder::sortable_ids`] instead
+    ///
+    /// ## Arguments
+    ///
+    /// * `sortable` - Whether to generate time-ordered IDs
+    pub fn set_sortable_ids(&mut self, sortable: bool) {
+        self.sortable_ids = sortable;
+    }
+
+    /// Generates a new ID, time-ordered if [`MiniDBBuilder::sortable_ids`] is enabled
+    pub(crate) fn new_id(&self) -> String {
+        if !self.sortable_ids {
+            return cuid2::slug();
+        }
+
+        // milliseconds with room for 1000 IDs each, bumped past the last ID so IDs generated in the same millisecond
+        // still increase. Past 1000 they run ahead of the clock until it catches up
+        let now = record_meta::now_millis().saturating_mul(1000);
+        let last = self
+            .last_id_time
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+                Some(now.max(last + 1))
+            })
+            .unwrap_or_default();
+        format!("{:016x}{}", now.max(last + 1), cuid2::slug())
+    }
+
+    /// Sets the external cache consulted before reads and updated after writes, [`None`] removes it
+    ///
+    /// ## Arguments
+    ///
+    /// * `cache` - The [`CacheLayer`] to use
+    pub fn set_cache_layer(&mut self, cache: Option<Box<dyn CacheLayer>>) {
+        if cache.is_some() && self.cache_namespace.is_empty() {
+            self.cache_namespace = cache_layer::namespace(self.path.as_deref());
+        }
+        self.cache = cache;
+    }
+
+    /// Sets the size budget for the database, [`None`] removes it
+    ///
+    /// I recommend using [`MiniDB::builder`] and setting it with [`MiniDBBuilder::quota`] instead
+    ///
+    /// ## Arguments
+    ///
+    /// * `quota` - The [`Quota`] to enforce
+    pub fn set_quota(&mut self, quota: Option<Quota>) {
+        self.quota = quota;
     }
 
     // EMD OF BUILDERS
 
     /// Retrieves all items from a table
     ///
+    /// Items are always sorted by ID in byte order, the same on every platform, since tables are B-trees keyed by ID.
+    /// Every other scan ([`MiniDB::for_each`], [`MiniDB::view_all`], [`MiniDB::find`], etc) follows the same order
+    ///
     /// ## Arguments
     ///
     /// * `T` - The table model
@@ -242,14 +541,9 @@ impl MiniDB {
 
         let mut results = Vec::new();
         for item in table.iter()? {
-            let (_key, value) = item?;
+            let (key, value) = item?;
 
-            let decoded: T = if let Some(cipher) = &self.cipher {
-                let decrypted = decrypt_bytes(cipher, value.value())?;
-                postcard::from_bytes(&decrypted)?
-            } else {
-                postcard::from_bytes(value.value())?
-            };
+            let decoded: T = self.codec.decode_record(key.value(), value.value())?;
 
             results.push(decoded);
         }
@@ -257,122 +551,179 @@ impl MiniDB {
         Ok(results)
     }
 
-    /// Force a check of the integrity of the database file, and repair it if possible.
-    ///
-    /// Note: Calling this function is unnecessary during normal operation. redb will automatically
-    /// detect and recover from crashes, power loss, and other unclean shutdowns. This function is
-    /// quite slow and should only be used when you suspect the database file may have been modified
-    /// externally to redb, or that a redb bug may have left the database in a corrupted state.
+    /// Retrieves all items from a table like [`MiniDB::all`], decrypting and deserializing them across several threads
     ///
-    /// ## Returns
-    ///
-    /// `Ok(true)` if the database passed integrity checks, `Ok(false)` if it failed but was repaired,
-    /// and `Err(Corrupted)` if the check failed and the file could not be repaired
+    /// The table is split into one range of IDs per thread, all read from the same read transaction, and each thread
+    /// decodes its range straight from the table. Decryption is the expensive part of a scan, so this mostly pays off on
+    /// encrypted databases with many records
     ///
-    /// ## Errors
+    /// ## Arguments
     ///
-    /// Returns an error if the check fails and the file could not be repaired
-    pub fn check_integrity(&mut self) -> Result<bool> {
-        Ok(self.db.check_integrity()?)
-    }
-
-    /// Compacts the database file
+    /// * `T` - The table model
+    /// * `threads` - How many threads to decode with, at least one is used and never more than the available
+    ///   parallelism or the number of records
     ///
     /// ## Returns
     ///
-    /// `Ok(true)` if compacting was performed, and `Ok(false)` if no further compacting was possible
+    /// A [`Result`] containing the vector of all items in the table `T`, in ID order
     ///
     /// ## Errors
     ///
-    /// Returns an error if the compacting fails
-    pub fn compact(&mut self) -> Result<bool> {
-        Ok(self.db.compact()?)
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any record fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let threads = std::thread::available_parallelism().map_or(1, usize::from);
+    /// let people = db.all_parallel::<Person>(threads).unwrap();
+    /// ```
+    pub fn all_parallel<T>(&self, threads: usize) -> Result<Vec<T>>
+    where
+        T: Table + Send,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+        let len = usize::try_from(table.len()?).unwrap_or(usize::MAX);
+        let available = std::thread::available_parallelism().map_or(1, usize::from);
+        let threads = threads.min(available).min(len).max(1);
+
+        // only the first ID of every range is copied, the records are decoded where they're stored
+        let chunk_size = len.div_ceil(threads).max(1);
+        let mut starts = Vec::with_capacity(threads);
+        for item in table.iter()?.step_by(chunk_size) {
+            let (key, _) = item?;
+            starts.push(key.value().to_string());
+        }
+
+        let codec = &self.codec;
+        let table = &table;
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = starts
+                .iter()
+                .enumerate()
+                .map(|(i, start)| {
+                    let end = starts.get(i + 1);
+                    scope.spawn(move || {
+                        let range = match end {
+                            Some(end) => table.range::<&str>(start.as_str()..end.as_str())?,
+                            None => table.range::<&str>(start.as_str()..)?,
+                        };
+                        range
+                            .map(|item| {
+                                let (key, value) = item?;
+                                codec.decode_record::<T>(key.value(), value.value())
+                            })
+                            .collect::<Result<Vec<T>>>()
+                    })
+                })
+                .collect();
+
+            let mut results = Vec::with_capacity(len);
+            for worker in workers {
+                let decoded = worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+                results.extend(decoded);
+            }
+            Ok(results)
+        })
     }
 
-    /// Creates the table if it doesn't exist
+    /// Retrieves all items from a table sorted by a key, instead of by ID
     ///
-    /// Recommended to use [`MiniDBBuilder::table`] instead.
+    /// The sort is stable, items with the same key stay in ID order
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
+    /// * `key` - Extracts the key to sort by from an item
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the vector of all items in the table `T`, sorted by `key`
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table creation fails
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// db.create_table::<Person>().unwrap();
+    /// let by_age = db.all_sorted::<Person, _, _>(|person| person.age).unwrap();
     /// ```
-    pub fn create_table<T>(&self) -> Result<()>
+    pub fn all_sorted<T, K, F>(&self, key: F) -> Result<Vec<T>>
     where
         T: Table,
+        K: Ord,
+        F: FnMut(&T) -> K,
     {
-        self.create_table_impl(T::TABLE)
+        let mut results: Vec<T> = self.all()?;
+        results.sort_by_key(key);
+        Ok(results)
     }
 
-    /// Creates the table if it doesn't exist
+    /// Retrieves all items from a table, skipping the records that can't be decoded instead of failing
     ///
-    /// Use [`MiniDB::create_table`] instead
-    pub(crate) fn create_table_impl<K, V>(&self, table: TableDefinition<K, V>) -> Result<()>
-    where
-        K: redb::Key,
-        V: redb::Value,
-    {
-        let txn = self.db.begin_write()?;
-        {
-            let _ = txn
-                .open_table(table)
-                .map_err(|e| Error::TableInitialization {
-                    name: table.to_string(),
-                    source: e,
-                })?;
-        }
-        txn.commit()?;
-        Ok(())
-    }
-
-    /// Exports a table as a JSON string
+    /// Useful to recover what's still readable from a table with a few corrupted or incompatible records
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
-    /// * `pretty` - Whether to pretty print the JSON
     ///
     /// ## Returns
     ///
-    /// * `String` - The JSON string
+    /// A [`ScanReport`] with the decoded items and the ID and error of every skipped record
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    /// Returns an error if the table is not found, if the table is not initialized, or if the storage fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// let json = db.export_table::<Person>(true).unwrap();
+    /// let report = db.all_lenient::<Person>().unwrap();
+    /// for (id, error) in &report.corrupted {
+    ///     eprintln!("skipped {id}: {error}");
+    /// }
     /// ```
-    pub fn export_table<T>(&self, pretty: bool) -> Result<String>
+    pub fn all_lenient<T>(&self) -> Result<ScanReport<T>>
     where
         T: Table,
     {
-        let all_items: Vec<T> = self.all()?;
-        let json = if pretty {
-            serde_json::to_string_pretty(&all_items)
-        } else {
-            serde_json::to_string(&all_items)
-        }?;
-        Ok(json)
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        let mut report = ScanReport {
+            items: Vec::new(),
+            corrupted: Vec::new(),
+        };
+        for item in table.iter()? {
+            let (key, value) = item?;
+
+            match self.codec.decode_record(key.value(), value.value()) {
+                Ok(decoded) => report.items.push(decoded),
+                Err(e) => report.corrupted.push((key.value().to_string(), e)),
+            }
+        }
+
+        Ok(report)
     }
 
-    /// Iterates over all items in a table and applies a function to each item
+    /// Retrieves a page of items after a cursor
+    ///
+    /// The cursor is the ID of the last item of the previous page, so pages stay stable when items are inserted or
+    /// removed elsewhere in the table
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
-    /// * `f` - The function to apply to each item
+    /// * `cursor` - The [`Page::next_cursor`] of the previous page, [`None`] for the first page
+    /// * `limit` - The maximum number of items in the page
+    ///
+    /// ## Returns
+    ///
+    /// A [`Page`] with up to `limit` items and the cursor of the next page
     ///
     /// ## Errors
     ///
@@ -381,449 +732,2419 @@ impl MiniDB {
     /// ## Example
     ///
     /// ```rust,ignore
-    /// db.for_each::<Person>(|person| {
-    ///     println!("Person name: {}", person.name);
-    /// }).unwrap();
+    /// let mut cursor = None;
+    /// loop {
+    ///     let page = db.page::<Person>(cursor.as_deref(), 50).unwrap();
+    ///     // render page.items
+    ///     cursor = page.next_cursor;
+    ///     if cursor.is_none() {
+    ///         break;
+    ///     }
+    /// }
     /// ```
-    pub fn for_each<T, F>(&self, mut f: F) -> Result<()>
+    pub fn page<T>(&self, cursor: Option<&str>, limit: usize) -> Result<Page<T>>
     where
         T: Table,
-        F: FnMut(&T),
     {
         let txn = self.db.begin_read()?;
         let table = txn.open_table(T::TABLE)?;
 
-        for item in table.iter()? {
-            let (_, value) = item?;
-
-            let data: T = if let Some(cipher) = &self.cipher {
-                let decrypted = decrypt_bytes(cipher, value.value())?;
-                postcard::from_bytes(&decrypted)?
-            } else {
-                postcard::from_bytes(value.value())?
-            };
-
-            f(&data);
-        }
-
-        Ok(())
+        let range = match cursor {
+            Some(cursor) => table.range::<&str>((Bound::Excluded(cursor), Bound::Unbounded))?,
+            None => table.iter()?,
+        };
+        self.collect_page(range, limit)
     }
 
-    /// Inserts an item into a table
+    /// Retrieves a page of items after skipping `offset` items
+    ///
+    /// Skipped items aren't decoded, but they still have to be walked, so [`MiniDB::page`] is cheaper for deep pages
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
-    /// * `item` - The item to insert
+    /// * `offset` - How many items to skip
+    /// * `limit` - The maximum number of items in the page
+    ///
+    /// ## Returns
+    ///
+    /// A [`Page`] with up to `limit` items and the cursor of the next page
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// let mut person = Person {
-    ///     id: String::new(), // ID must be new so it can be generated automatically
-    ///     name: "John Doe".to_string(),
-    ///     age: 31,
-    /// };
-    ///
-    /// db.insert(&mut person).unwrap();
+    /// let third_page = db.page_at::<Person>(100, 50).unwrap();
     /// ```
-    pub fn insert<T>(&self, item: &mut T) -> Result<()>
+    pub fn page_at<T>(&self, offset: usize, limit: usize) -> Result<Page<T>>
     where
         T: Table,
     {
-        if item.get_id().trim().is_empty() {
-            let id = cuid2::slug();
-            item.set_id(id);
-        }
-
-        let txn = self.db.begin_write()?;
-        {
-            let mut table = txn.open_table(T::TABLE)?;
-            let bytes = postcard::to_stdvec(item)?;
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
 
-            let to_write: Vec<u8> = if let Some(cipher) = &self.cipher {
-                encrypt_bytes(cipher, &bytes)?
-            } else {
-                bytes
-            };
+        let mut range = table.iter()?;
+        for item in range.by_ref().take(offset) {
+            item?;
+        }
+        self.collect_page(range, limit)
+    }
 
-            table.insert(item.get_id(), to_write.as_slice())?;
+    /// Decodes up to `limit` items from a range, peeking one more to know if there's a next page
+    fn collect_page<T>(
+        &self,
+        mut range: redb::Range<'_, &'static str, &'static [u8]>,
+        limit: usize,
+    ) -> Result<Page<T>>
+    where
+        T: Table,
+    {
+        let mut items = Vec::new();
+        for item in range.by_ref().take(limit) {
+            let (key, value) = item?;
+            items.push(self.codec.decode_record::<T>(key.value(), value.value())?);
         }
-        txn.commit()?;
-        Ok(())
+
+        let next_cursor = match (range.next().transpose()?, items.last()) {
+            (Some(_), Some(last)) => Some(last.get_id().to_string()),
+            _ => None,
+        };
+        Ok(Page { items, next_cursor })
     }
 
-    /// Inserts multiple items into a table
+    /// Starts a typed query on a table, filtered with the [`Field`] constants generated by `#[minidb(fields)]`
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
-    /// * `items` - The items to insert
-    ///
-    /// ## Errors
-    ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// let mut people = vec![
-    ///     Person {
-    ///         id: String::new(),
-    ///         name: "John Doe".to_string(),
-    ///         age: 31,
-    ///     },
-    ///
-    ///     // ...
-    /// ];
-    ///
-    /// db.insert_many(&mut people).unwrap();
+    /// let adults = db
+    ///     .query::<Person>()
+    ///     .filter(Person::AGE.gt(30))
+    ///     .limit(10)
+    ///     .run()
+    ///     .unwrap();
     /// ```
-    pub fn insert_many<T>(&self, items: &mut [T]) -> Result<()>
+    #[must_use]
+    pub fn query<T>(&self) -> Query<'_, T>
     where
         T: Table,
     {
-        let txn = self.db.begin_write()?;
-        {
-            let mut table = txn.open_table(T::TABLE)?;
-            for item in items {
-                if item.get_id().trim().is_empty() {
-                    let id = cuid2::slug();
-                    item.set_id(id);
-                }
+        Query::new(self)
+    }
+
+    /// Opens a table that holds at most one record, for config-like data, see [`Singleton`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model, with [`Table::SINGLETON`] set or this fails to compile
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let settings = db.singleton::<Settings>();
+    /// let mut current = settings.get_or_default().unwrap();
+    /// current.theme = "dark".to_string();
+    /// settings.set(&mut current).unwrap();
+    /// ```
+    #[must_use]
+    pub fn singleton<T>(&self) -> Singleton<'_, T>
+    where
+        T: Table,
+    {
+        const { assert!(T::SINGLETON, "the table model isn't a singleton") };
+        Singleton::new(self)
+    }
+
+    /// Computes the count, sum and extrema of a value taken from every item of a table, without collecting the items
+    ///
+    /// Use [`Query::aggregate`] to aggregate only the items that match a filter
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `f` - Returns the value to aggregate from an item
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the [`Aggregate`] of the values
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any record fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let prices = db.aggregate::<MenuItem, _, _>(|item| item.price).unwrap();
+    /// println!("total {}, most expensive {:?}", prices.sum(), prices.max());
+    /// ```
+    pub fn aggregate<T, V, F>(&self, f: F) -> Result<Aggregate<V>>
+    where
+        T: Table,
+        V: AggregateValue,
+        F: FnMut(&T) -> V,
+    {
+        self.query::<T>().aggregate(f)
+    }
+
+    /// Retrieves the items of a table that match a predicate
+    ///
+    /// The whole table is scanned inside a single read transaction, records are decoded one at a time and only the
+    /// matching ones are kept
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `predicate` - Returns `true` for the items to keep
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the vector of matching items, in ID order
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any record fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let adults = db.find::<Person, _>(|person| person.age > 30).unwrap();
+    /// ```
+    pub fn find<T, F>(&self, mut predicate: F) -> Result<Vec<T>>
+    where
+        T: Table,
+        F: FnMut(&T) -> bool,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
 
-                let bytes = postcard::to_stdvec(&item)?;
-                let to_write: Vec<u8> = if let Some(cipher) = &self.cipher {
-                    encrypt_bytes(cipher, &bytes)?
-                } else {
-                    bytes
-                };
+        let mut results = Vec::new();
+        for item in table.iter()? {
+            let (key, value) = item?;
 
-                table.insert(item.get_id(), to_write.as_slice())?;
+            let decoded: T = self.codec.decode_record(key.value(), value.value())?;
+            if predicate(&decoded) {
+                results.push(decoded);
             }
         }
-        txn.commit()?;
+
+        Ok(results)
+    }
+
+    /// Retrieves the items of a table whose indexed field equals a value
+    ///
+    /// Only the records in the index are decoded. The first lookup on a field indexes the records written before the
+    /// `#[index]` attribute was added unless [`MiniDB::build_index`] already did, after that every write keeps the index
+    /// up to date
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `field` - The name of a field marked with `#[index]`
+    /// * `value` - The value to look for, of the same type as the field
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the vector of matching items, in ID order
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::UnknownIndex`] if the field isn't indexed, or an error if the table is not found or if the
+    /// decryption/deserialization of any record fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let in_paris = db.find_by_index::<Person, _>("city", "Paris").unwrap();
+    /// ```
+    pub fn find_by_index<T, V>(&self, field: &str, value: &V) -> Result<Vec<T>>
+    where
+        T: Table,
+        V: Serialize + ?Sized,
+    {
+        let name = T::TABLE.name();
+        if !index::is_built(&self.db.begin_read()?, name, field)? {
+            self.build_index::<T>(field, index::DEFAULT_BUILD_CHUNK, Duration::ZERO)?;
+        }
+
+        let txn = self.db.begin_read()?;
+        let value = postcard::to_stdvec(value)?;
+        let ids = index::lookup(&txn, &self.codec, name, field, &value)?;
+
+        let table = txn.open_table(T::TABLE)?;
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            let Some(item) = self.read_record(&table, &id)? else {
+                continue;
+            };
+            // a damaged record is dropped without taking its entries out, so an entry can point at an ID that holds
+            // another value now
+            if index::values_of(&item)?
+                .iter()
+                .any(|(other_field, other_value)| *other_field == field && *other_value == value)
+            {
+                results.push(item);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Retrieves the item whose unique field equals a value, straight from the index of the field
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `field` - The [`Field`] constant of a field marked with `#[unique]`, generated by `#[minidb(fields)]`
+    /// * `value` - The value to look for
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(item))` if an item has the value
+    /// * `Ok(None)` if no item has it
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::NotUnique`] if the field isn't unique, or an error if the table is not found or if the
+    /// decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let person = db.get_by(Person::EMAIL, &"a@b.c".to_string()).unwrap();
+    /// ```
+    pub fn get_by<T, V>(&self, field: Field<T, V>, value: &V) -> Result<Option<T>>
+    where
+        T: Table,
+        V: Serialize,
+    {
+        if !T::UNIQUE.contains(&field.name()) {
+            return Err(Error::NotUnique {
+                table: T::TABLE.name().to_string(),
+                field: field.name().to_string(),
+            });
+        }
+
+        Ok(self
+            .find_by_index::<T, V>(field.name(), value)?
+            .into_iter()
+            .next())
+    }
+
+    /// Indexes the records written before the `#[index]` attribute was added to a field, a chunk at a time
+    ///
+    /// Each chunk is indexed in its own transaction, so writers only wait for one chunk instead of the whole table. Writes
+    /// made in between keep the index up to date themselves, and the index is only used once the last chunk commits. A
+    /// build that's interrupted resumes after the last committed chunk. Run it on another thread to keep it off the
+    /// caller, otherwise the first [`MiniDB::find_by_index`] on the field builds it without pauses
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `field` - The name of a field marked with `#[index]`
+    /// * `chunk_size` - How many records to index per transaction, at least 1
+    /// * `pause` - How long to sleep between chunks, to leave room for other writers
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::UnknownIndex`] if the field isn't indexed, or an error if the table is not found, if the
+    /// decryption/deserialization of any record fails or if a commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let db = Arc::new(db);
+    /// let builder = Arc::clone(&db);
+    /// std::thread::spawn(move || {
+    ///     builder.build_index::<Person>("city", 500, Duration::from_millis(10)).unwrap();
+    /// });
+    /// ```
+    pub fn build_index<T>(&self, field: &str, chunk_size: usize, pause: Duration) -> Result<()>
+    where
+        T: Table,
+    {
+        if !T::INDEXES.contains(&field) {
+            return Err(Error::UnknownIndex {
+                table: T::TABLE.name().to_string(),
+                field: field.to_string(),
+            });
+        }
+
+        while !self.transaction(|txn| index::build_chunk::<T>(txn, field, chunk_size.max(1)))? {
+            if !pause.is_zero() {
+                std::thread::sleep(pause);
+            }
+        }
+
         Ok(())
     }
 
-    /// Checks if a table is empty
+    /// Retrieves the value distribution of every indexed field as of the last [`MiniDB::compact`]
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the [`FieldStats`] of every index, sorted by table and field, empty if the database was
+    /// never compacted
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the meta table couldn't be opened or if the deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.compact().unwrap();
+    /// for stats in db.field_stats().unwrap() {
+    ///     println!("{}.{}: {} distinct values", stats.table, stats.field, stats.cardinality);
+    /// }
+    /// ```
+    pub fn field_stats(&self) -> Result<Vec<FieldStats>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(META_TABLE)?;
+
+        let mut results = Vec::new();
+        for item in table.range(index::STATS_PREFIX..)? {
+            let (key, value) = item?;
+            if !key.value().starts_with(index::STATS_PREFIX) {
+                break;
+            }
+
+            results.push(postcard::from_bytes(value.value())?);
+        }
+
+        Ok(results)
+    }
+
+    /// Searches the `#[fulltext]` fields of a table for the words of a query
+    ///
+    /// Words are split on anything that isn't a letter or a digit and compared case-insensitively. A record matches if
+    /// it has any of the words, hits are ranked with TF-IDF so records with more of the words, and with rarer words,
+    /// come first. Every write keeps the index up to date, records written before the attribute was added are only
+    /// found after [`MiniDB::rebuild_fulltext`]
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
+    /// * `query` - The words to look for
     ///
     /// ## Returns
     ///
-    /// * `Ok(true)` if the table is empty
-    /// * `Ok(false)` if the table is not empty
+    /// A [`Result`] containing the [`SearchHit`]s, best match first
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found or couldn't be opened
+    /// Returns an error if the table is not found or if the decryption/deserialization of any matched record fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// let is_empty = db.is_empty::<Person>().unwrap();
-    /// println!("Is the table empty? {}", is_empty);
+    /// for hit in db.search::<Person>("john doe").unwrap() {
+    ///     println!("{} ({:.2})", hit.item.name, hit.score);
+    /// }
     /// ```
-    pub fn is_empty<T>(&self) -> Result<bool>
+    pub fn search<T>(&self, query: &str) -> Result<Vec<SearchHit<T>>>
     where
         T: Table,
     {
         let txn = self.db.begin_read()?;
         let table = txn.open_table(T::TABLE)?;
-        Ok(table.is_empty()?)
+        let ranked = fulltext::rank(&txn, &self.codec, T::TABLE.name(), table.len()?, query)?;
+
+        let words: Vec<_> = fulltext::tokenize(query).collect();
+        let mut hits = Vec::with_capacity(ranked.len());
+        for (id, score) in ranked {
+            let Some(item) = self.read_record(&table, &id)? else {
+                continue;
+            };
+            // postings left by a damaged record that was replaced can rank it for words it no longer has
+            let terms = fulltext::terms_of(&item);
+            if words.iter().any(|word| terms.contains_key(word)) {
+                hits.push(SearchHit { item, score });
+            }
+        }
+
+        Ok(hits)
     }
 
-    /// Retrieves an item from a table
+    /// Indexes the `#[fulltext]` fields of every record from scratch, for records written before the attribute was
+    /// added or fields that stopped being searched
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing how many records were indexed
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the decryption/deserialization of any record fails or if the commit
+    /// fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.rebuild_fulltext::<Person>().unwrap();
+    /// ```
+    pub fn rebuild_fulltext<T>(&self) -> Result<usize>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| fulltext::rebuild::<T>(txn))
+    }
+
+    /// Measures where the time of a write and a read goes on this hardware and with this configuration, to guide tuning
+    /// options like the [`CacheLayer`] or the durability
+    ///
+    /// Copies of `sample` are written one transaction at a time to a scratch table, read back and dropped with the table
+    /// at the end, even on errors. The real table, its indexes and the quota are left alone, so unique fields don't get in
+    /// the way and nothing gets evicted. Each step is timed on its own, see [`BenchProfile`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `sample` - A representative record, its ID is ignored
+    /// * `iterations` - How many copies to write and read
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the average time of each step
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the encryption/serialization fails or if a commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let profile = db.bench_profile(&person, 100).unwrap();
+    /// println!("commit takes {:?} on average", profile.commit);
+    /// ```
+    #[cfg(feature = "profiling")]
+    pub fn bench_profile<T>(&self, sample: &T, iterations: usize) -> Result<BenchProfile>
+    where
+        T: Table,
+    {
+        profile::run(self, sample, iterations)
+    }
+
+    /// Force a check of the integrity of the database file, and repair it if possible.
+    ///
+    /// Note: Calling this function is unnecessary during normal operation. redb will automatically
+    /// detect and recover from crashes, power loss, and other unclean shutdowns. This function is
+    /// quite slow and should only be used when you suspect the database file may have been modified
+    /// externally to redb, or that a redb bug may have left the database in a corrupted state.
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(true)` if the database passed integrity checks, `Ok(false)` if it failed but was repaired,
+    /// and `Err(Corrupted)` if the check failed and the file could not be repaired
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the check fails and the file could not be repaired
+    pub fn check_integrity(&mut self) -> Result<bool> {
+        Ok(self.db.check_integrity()?)
+    }
+
+    /// Compacts the database file, refreshing the [`FieldStats`] of the indexed fields first
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(true)` if compacting was performed, and `Ok(false)` if no further compacting was possible
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the compacting fails
+    pub fn compact(&mut self) -> Result<bool> {
+        self.enforce_quota()?;
+        self.transaction(|txn| index::refresh_stats(&txn.txn, &self.codec))?;
+        Ok(self.db.compact()?)
+    }
+
+    /// Moves the database file to another path while it stays open, for migrating storage at runtime
+    ///
+    /// The file is copied rather than renamed so it can cross filesystems, then the copy is synced to disk, opened with the
+    /// same configuration and its integrity checked before the database switches to it. If anything fails up to that point
+    /// the copy is removed and the database keeps using the original file. The lock file of [`LockBackend::PidFile`] and
+    /// the marker left by a panicked write move along with it
+    ///
+    /// ## Arguments
+    ///
+    /// * `new_path` - Where to move the database file, it must not exist
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::MissingPath`] if the database wasn't opened with [`MiniDB::builder`], or an error if `new_path`
+    /// already exists, if the new lock file can't be created, or if copying, syncing or checking the copy fails. Returns
+    /// [`Error::OriginalNotRemoved`] if the database moved but the original file couldn't be removed afterwards
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.relocate("/mnt/bigger-disk/app.redb").unwrap();
+    /// ```
+    pub fn relocate<P>(&mut self, new_path: P) -> Result<()>
+    where
+        P: Into<PathBuf>,
+    {
+        let new_path = new_path.into();
+        let old_path = self.path.clone().ok_or(Error::MissingPath)?;
+        if new_path.exists() {
+            return Err(std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("`{}` already exists", new_path.display()),
+            )
+            .into());
+        }
+
+        // dropped on failure, which removes the new lock file again
+        let lock = self
+            .lock
+            .as_ref()
+            .map(|_| LockFile::acquire(&new_path))
+            .transpose()?;
+        let copied = fs::copy(&old_path, &new_path)
+            .and_then(|_| sync_copy(&new_path))
+            .map_err(Error::from)
+            .and_then(|()| {
+                let mut db = builder::redb_builder().open(&new_path)?;
+                db.check_integrity()?;
+                Ok(db)
+            });
+        let db = match copied {
+            Ok(db) => db,
+            Err(e) => {
+                let _ = fs::remove_file(&new_path);
+                return Err(e);
+            }
+        };
+
+        // the original file and lock file are released once they're replaced
+        self.db = db;
+        if lock.is_some() {
+            self.lock = lock;
+        }
+        // the copy was already checked, so losing the marker only skips a redundant check on the next open
+        let old_marker = recovery_path(&old_path);
+        if old_marker.exists() && fs::copy(&old_marker, recovery_path(&new_path)).is_ok() {
+            let _ = fs::remove_file(&old_marker);
+        }
+        self.path = Some(new_path);
+        if self.cache.is_some() {
+            self.cache_namespace = cache_layer::namespace(self.path.as_deref());
+        }
+
+        fs::remove_file(&old_path).map_err(|source| Error::OriginalNotRemoved {
+            path: old_path,
+            source,
+        })
+    }
+
+    /// Makes the encrypted data unreadable by destroying what the key is derived from
+    ///
+    /// The salt used with [`KeySource::Password`] is removed from the metadata, so the same password derives a different key
+    /// from then on, and a key file set with [`MiniDBBuilder::key_file`] is overwritten with zeros and deleted. The database
+    /// is compacted afterwards so the pages holding the old salt are released. This is much faster than deleting every record,
+    /// but keys given through [`KeySource::PreDerived`] or [`KeySource::ExternalKeyProvider`] have to be destroyed by the caller,
+    /// as well as any copy in a [`KeyCache`]
+    ///
+    /// Nothing is wiped from the disk: the ciphertext stays in the file, and redb doesn't zero the pages it frees, so the
+    /// old salt may remain in them too. Overwriting the key file may also not reach the old blocks on SSDs or
+    /// copy-on-write filesystems. The records stay unreadable only as long as the key can't be recovered, someone
+    /// holding the password and a copy of the old salt can still decrypt them
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::NotEncrypted`] if the database was opened without a key, or an error if the metadata can't be
+    /// updated, if compacting fails, or if the key file can't be destroyed
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.crypto_erase().unwrap();
+    /// ```
+    pub fn crypto_erase(mut self) -> Result<()> {
+        if self.codec.cipher.is_none() {
+            return Err(Error::NotEncrypted);
+        }
+
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(META_TABLE)?;
+            table.remove(META_KEY_SALT)?;
+        }
+        txn.commit()?;
+
+        self.codec.cipher = None;
+        self.codec.index_key = None;
+        self.db.compact()?;
+
+        if let Some(path) = self.key_file.take() {
+            destroy_key_file(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the database against its [`Quota`], evicting records if the policy allows it
+    ///
+    /// Writes already check the quota when they commit, this is meant for maintenance like after lowering the budget.
+    /// It's also called by [`MiniDB::compact`]
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::QuotaExceeded`] if the data doesn't fit the budget even after evicting, or an error if the transaction fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.enforce_quota().unwrap();
+    /// ```
+    pub fn enforce_quota(&self) -> Result<()> {
+        if self.quota.is_none() {
+            return Ok(());
+        }
+
+        let txn = self.db.begin_write()?;
+        let transaction = Transaction::new(txn, self);
+
+        transaction.enforce_quota()?;
+        transaction.commit()
+    }
+
+    /// Creates the table if it doesn't exist
+    ///
+    /// Recommended to use [`MiniDBBuilder::table`] instead.
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table creation fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.create_table::<Person>().unwrap();
+    /// ```
+    pub fn create_table<T>(&self) -> Result<()>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_write()?;
+        builder::init_table::<T>(&txn)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Exports a table as a JSON string
+    ///
+    /// Fields in [`Table::REDACTED_FIELDS`] are replaced with `"[REDACTED]"` whatever their type, so the JSON is for
+    /// reading and won't deserialize back into the model
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `pretty` - Whether to pretty print the JSON
+    ///
+    /// ## Returns
+    ///
+    /// * `String` - The JSON string
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let json = db.export_table::<Person>(true).unwrap();
+    /// ```
+    pub fn export_table<T>(&self, pretty: bool) -> Result<String>
+    where
+        T: Table,
+    {
+        let all_items: Vec<T> = self.all()?;
+        if !T::REDACTED_FIELDS.is_empty() {
+            let redacted = all_items
+                .iter()
+                .map(export::to_redacted_json)
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(if pretty {
+                serde_json::to_string_pretty(&redacted)
+            } else {
+                serde_json::to_string(&redacted)
+            }?);
+        }
+
+        let json = if pretty {
+            serde_json::to_string_pretty(&all_items)
+        } else {
+            serde_json::to_string(&all_items)
+        }?;
+        Ok(json)
+    }
+
+    /// Starts a consistent export of several tables for analytics tools, see [`Snapshot`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let count = db
+    ///     .snapshot()
+    ///     .table::<Person>()
+    ///     .table::<Order>()
+    ///     .write_ndjson(std::io::stdout())
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot::new(self)
+    }
+
+    /// Exports a single record as a portable, self-contained blob
+    ///
+    /// The blob is decrypted and carries the table name and a fingerprint of the fields, so it can be imported into
+    /// any database with the same model using [`MiniDB::import_record`]. Fields in [`Table::REDACTED_FIELDS`] are
+    /// reset by [`Table::redact`] so the blob still imports, without their values
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the record
+    /// * `writer` - Where to write the blob
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(true)` if the record was exported
+    /// * `Ok(false)` if the record doesn't exist
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the decryption/serialization fails, or if writing fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let mut file = std::fs::File::create("person.mndr").unwrap();
+    /// db.export_record::<Person>(&id, &mut file).unwrap();
+    /// ```
+    pub fn export_record<T, W>(&self, id: &str, mut writer: W) -> Result<bool>
+    where
+        T: Table,
+        W: Write,
+    {
+        let Some(item) = self.get::<T>(id)? else {
+            return Ok(false);
+        };
+
+        writer.write_all(&export::encode(&item)?)?;
+        Ok(true)
+    }
+
+    /// Imports a record exported with [`MiniDB::export_record`], keeping its ID
+    ///
+    /// An existing record with the same ID is replaced
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `reader` - Where to read the blob from
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(item)` with the imported record
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::InvalidExport`] if the blob wasn't exported from the same table model, or an error if reading,
+    /// the deserialization or the write fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let file = std::fs::File::open("person.mndr").unwrap();
+    /// let person: Person = db.import_record(file).unwrap();
+    /// ```
+    pub fn import_record<T, R>(&self, mut reader: R) -> Result<T>
+    where
+        T: Table,
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let item: T = export::decode(&bytes)?;
+        self.update(&item)?;
+        Ok(item)
+    }
+
+    /// Imports a record exported with [`MiniDB::export_record`], keeping its ID and resolving a clash with an existing
+    /// record through `policy`
+    ///
+    /// The existing record is read and the imported one written in the same transaction
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `reader` - Where to read the blob from
+    /// * `policy` - What to do if a record with the same ID already exists
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(item))` with the stored record, merged if [`ImportPolicy::Merge`] was used
+    /// * `Ok(None)` if the record already existed and [`ImportPolicy::Skip`] was used
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::RecordExists`] if the record already existed and [`ImportPolicy::Fail`] was used,
+    /// [`Error::InvalidExport`] if the blob wasn't exported from the same table model, or an error if reading, the
+    /// decryption/deserialization or the write fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let file = std::fs::File::open("person.mndr").unwrap();
+    /// let person = db
+    ///     .import_record_with::<Person, _>(
+    ///         file,
+    ///         ImportPolicy::merge(|existing: Person, imported: Person| Person {
+    ///             visits: existing.visits + imported.visits,
+    ///             ..imported
+    ///         }),
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn import_record_with<T, R>(
+        &self,
+        mut reader: R,
+        policy: ImportPolicy<T>,
+    ) -> Result<Option<T>>
+    where
+        T: Table,
+        R: Read,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let item: T = export::decode(&bytes)?;
+
+        self.transaction(|txn| {
+            let id = item.get_id().to_string();
+            let table = txn.txn.open_table(T::TABLE)?;
+
+            let item = match (table.get(id.as_str())?, policy) {
+                (None, _) | (Some(_), ImportPolicy::Overwrite) => item,
+                (Some(_), ImportPolicy::Skip) => return Ok(None),
+                (Some(_), ImportPolicy::Fail) => {
+                    return Err(Error::RecordExists {
+                        table: T::TABLE.name().to_string(),
+                        id,
+                    });
+                }
+                // only a merge needs the existing record decoded
+                (Some(bytes), ImportPolicy::Merge(merge)) => {
+                    let existing: T = self.codec.decode_record(&id, bytes.value())?;
+                    let mut merged = merge(existing, item);
+                    merged.set_id(id);
+                    merged
+                }
+            };
+            drop(table);
+
+            txn.update(&item)?;
+            Ok(Some(item))
+        })
+    }
+
+    /// Iterates over all items in a table and applies a function to each item, in ID order
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `f` - The function to apply to each item
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.for_each::<Person>(|person| {
+    ///     println!("Person name: {}", person.name);
+    /// }).unwrap();
+    /// ```
+    pub fn for_each<T, F>(&self, mut f: F) -> Result<()>
+    where
+        T: Table,
+        F: FnMut(&T),
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        for item in table.iter()? {
+            let (key, value) = item?;
+
+            let data: T = self.codec.decode_record(key.value(), value.value())?;
+
+            f(&data);
+        }
+
+        Ok(())
+    }
+
+    /// Inserts an item into a table
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `item` - The item to insert
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let mut person = Person {
+    ///     id: String::new(), // ID must be new so it can be generated automatically
+    ///     name: "John Doe".to_string(),
+    ///     age: 31,
+    /// };
+    ///
+    /// db.insert(&mut person).unwrap();
+    /// ```
+    pub fn insert<T>(&self, item: &mut T) -> Result<()>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| txn.insert(item))
+    }
+
+    /// Inserts an item into a table under an ID chosen by the caller, instead of a generated one
+    ///
+    /// Useful to keep natural or external keys, for example when migrating from another database
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `item` - The item to insert, its ID is replaced with `id`
+    /// * `id` - The ID to store the item under
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::EmptyID`] if the ID is empty, [`Error::RecordExists`] if it's already taken, or an error if the
+    /// table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.insert_with_id(&mut person, "employee-1042").unwrap();
+    /// ```
+    pub fn insert_with_id<T>(&self, item: &mut T, id: &str) -> Result<()>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| txn.insert_with_id(item, id))
+    }
+
+    /// Retrieves an item by ID, or inserts the one built by `make` under that ID if it doesn't exist
+    ///
+    /// The check and the insert happen in the same write transaction, so concurrent callers can't both insert
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the item
+    /// * `make` - Builds the item to insert, its ID is replaced with `id`
+    ///
+    /// ## Returns
+    ///
+    /// * `T` - The existing item or the inserted one
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::EmptyID`] if the ID is empty, or an error if the table is not found, if the table is not
+    /// initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let profile = db.get_or_insert_with::<Profile, _>(&user_id, Profile::default).unwrap();
+    /// ```
+    pub fn get_or_insert_with<T, F>(&self, id: &str, make: F) -> Result<T>
+    where
+        T: Table,
+        F: FnOnce() -> T,
+    {
+        self.transaction(|txn| txn.get_or_insert_with(id, make))
+    }
+
+    /// Inserts multiple items into a table
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `items` - The items to insert
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let mut people = vec![
+    ///     Person {
+    ///         id: String::new(),
+    ///         name: "John Doe".to_string(),
+    ///         age: 31,
+    ///     },
+    ///
+    ///     // ...
+    /// ];
+    ///
+    /// db.insert_many(&mut people).unwrap();
+    /// ```
+    pub fn insert_many<T>(&self, items: &mut [T]) -> Result<()>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| txn.insert_many(items))
+    }
+
+    /// Checks if a table is empty
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(true)` if the table is empty
+    /// * `Ok(false)` if the table is not empty
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or couldn't be opened
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let is_empty = db.is_empty::<Person>().unwrap();
+    /// println!("Is the table empty? {}", is_empty);
+    /// ```
+    pub fn is_empty<T>(&self) -> Result<bool>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+        Ok(table.is_empty()?)
+    }
+
+    /// Counts the items in a table without decoding them
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// * `usize` - The number of items in the table
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or couldn't be opened
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let people = db.count::<Person>().unwrap();
+    /// ```
+    pub fn count<T>(&self) -> Result<usize>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+        Ok(usize::try_from(table.len()?).unwrap_or(usize::MAX))
+    }
+
+    /// Retrieves the IDs of every item in a table, in order, without decoding the items
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// * `Vec<String>` - The IDs of the items
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or couldn't be opened
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// for id in db.ids::<Person>().unwrap() {
+    ///     let person: Person = db.get(&id).unwrap().unwrap();
+    /// }
+    /// ```
+    pub fn ids<T>(&self) -> Result<Vec<String>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        let mut ids = Vec::new();
+        for item in table.iter()? {
+            let (key, _) = item?;
+            ids.push(key.value().to_string());
+        }
+
+        Ok(ids)
+    }
+
+    /// Counts the items of a table that match a predicate
+    ///
+    /// Unlike [`MiniDB::count`] every record has to be decoded
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `predicate` - Returns `true` for the items to count
+    ///
+    /// ## Returns
+    ///
+    /// * `usize` - The number of matching items
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let adults = db.count_where::<Person, _>(|person| person.age >= 18).unwrap();
+    /// ```
+    pub fn count_where<T, F>(&self, mut predicate: F) -> Result<usize>
+    where
+        T: Table,
+        F: FnMut(&T) -> bool,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        let mut count = 0;
+        for item in table.iter()? {
+            let (key, value) = item?;
+
+            let decoded: T = self.codec.decode_record(key.value(), value.value())?;
+            if predicate(&decoded) {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Retrieves an item from a table
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The id of the item to retrieve
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(item))` if the item was found
+    /// * `Ok(None)` if the item was not found
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// // retrieves an item from the table `Person`
+    /// let person = db.get::<Person>("person_id").unwrap().unwrap();
+    /// println!("Person name: {}", person.name);
+    ///
+    /// // or
+    ///
+    /// let person: Option<Person> = db.get("person_id").unwrap();
+    /// if let Some(person) = person {
+    ///     println!("Person name: {}", person.name);
+    /// }
+    /// ```
+    pub fn get<T>(&self, id: &str) -> Result<Option<T>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        self.read_record(&table, id)
+    }
+
+    /// Retrieves only some fields of an item, moving them out so the rest of the record is dropped right away
+    ///
+    /// Records are stored with postcard, which isn't self-describing, so the whole record is still decoded but large
+    /// fields that aren't selected are never cloned or returned
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The id of the item to retrieve
+    /// * `project` - Takes the item and returns the selected fields
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(fields))` if the item was found
+    /// * `Ok(None)` if the item was not found
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let (name, age) = db
+    ///     .select::<Person, _, _>("person_id", |person| (person.name, person.age))
+    ///     .unwrap()
+    ///     .unwrap();
+    /// ```
+    pub fn select<T, P, F>(&self, id: &str, project: F) -> Result<Option<P>>
+    where
+        T: Table,
+        F: FnOnce(T) -> P,
+    {
+        Ok(self.get::<T>(id)?.map(project))
+    }
+
+    /// Reads an item from an open table, going through the [`CacheLayer`] if there's one
+    fn read_record<T>(
+        &self,
+        table: &ReadOnlyTable<&'static str, &'static [u8]>,
+        id: &str,
+    ) -> Result<Option<T>>
+    where
+        T: Table,
+    {
+        if let Some(cache) = &self.cache
+            && let Some(bytes) = cache.get(&self.cache_namespace, T::TABLE.name(), id)
+        {
+            return Ok(Some(self.codec.decode_record(id, &bytes)?));
+        }
+
+        let Some(bytes) = table.get(id)? else {
+            return Ok(None);
+        };
+        let item: T = self.codec.decode_record(id, bytes.value())?;
+
+        Ok(Some(item))
+    }
+
+    /// Retrieves multiple items from a table by their IDs
+    ///
+    /// All items are read inside a single read transaction, so they come from the same snapshot
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `ids` - The IDs of the items
+    ///
+    /// ## Returns
+    ///
+    /// * `Vec<Option<T>>` - The items in the same order as `ids`, [`None`] for the ones that don't exist
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let people = db.get_many::<Person>(&["person_id1", "person_id2", ...]).unwrap();
+    ///
+    /// // or
+    ///
+    /// let people: Vec<Option<Person>> = db.get_many(&["person_id1", "person_id2", ...]).unwrap();
+    /// ```
+    pub fn get_many<T>(&self, ids: &[&str]) -> Result<Vec<Option<T>>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            results.push(self.read_record(&table, id)?);
+        }
+
+        Ok(results)
+    }
+
+    /// Retrieves the item with the lowest ID in a table, the oldest one if [`MiniDBBuilder::sortable_ids`] is enabled and
+    /// no IDs were chosen by the caller
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(item))` with the first item
+    /// * `Ok(None)` if the table is empty
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let oldest = db.first::<Person>().unwrap();
+    /// ```
+    pub fn first<T>(&self) -> Result<Option<T>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        table
+            .first()?
+            .map(|(key, value)| self.codec.decode_record(key.value(), value.value()))
+            .transpose()
+    }
+
+    /// Retrieves the item with the highest ID in a table, the newest one if [`MiniDBBuilder::sortable_ids`] is enabled
+    /// and no IDs were chosen by the caller
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(item))` with the last item
+    /// * `Ok(None)` if the table is empty
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let latest = db.last::<Person>().unwrap();
+    /// ```
+    pub fn last<T>(&self) -> Result<Option<T>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        table
+            .last()?
+            .map(|(key, value)| self.codec.decode_record(key.value(), value.value()))
+            .transpose()
+    }
+
+    /// Checks which of several records exist, without decoding them
+    ///
+    /// All IDs are looked up inside a single read transaction, which is much cheaper than calling [`MiniDB::get`] for
+    /// each one when validating a large batch of references
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `ids` - The IDs of the records
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing whether each record exists, in the same order as `ids`
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or if the table is not initialized
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let exists = db.exists_many::<Person>(&[&order.customer_id, &order.seller_id]).unwrap();
+    /// assert!(exists.iter().all(|&exists| exists));
+    /// ```
+    pub fn exists_many<T>(&self, ids: &[&str]) -> Result<Vec<bool>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            results.push(table.get(*id)?.is_some());
+        }
+
+        Ok(results)
+    }
+
+    /// Retrieves information about a stored record without decoding it
+    ///
+    /// The size is always available, the timestamps only if [`MiniDBBuilder::record_timestamps`] was enabled when the
+    /// record was written
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the record
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(meta))` with the [`RecordMeta`] of the record
+    /// * `Ok(None)` if the record doesn't exist
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or if the timestamps can't be read
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let meta = db.record_meta::<Person>("person_id").unwrap().unwrap();
+    /// println!("last modified at {:?}", meta.modified);
+    /// ```
+    pub fn record_meta<T>(&self, id: &str) -> Result<Option<RecordMeta>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let size = {
+            let table = txn.open_table(T::TABLE)?;
+            let Some(bytes) = table.get(id)? else {
+                return Ok(None);
+            };
+            bytes.value().len()
+        };
+
+        Ok(Some(record_meta::read(&txn, T::TABLE.name(), id, size)?))
+    }
+
+    /// Claims a record for `ttl`, so other workers skip it until the lease expires or is released
+    ///
+    /// Leases are stored in the database, so they hold across processes that open it one after another and across
+    /// threads sharing the same [`MiniDB`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the record
+    /// * `ttl` - How long the lease lasts
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(lease))` with the new [`Lease`]
+    /// * `Ok(None)` if the record doesn't exist or is already claimed
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or if the commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// if let Some(lease) = db.claim::<Job>(&id, Duration::from_secs(30)).unwrap() {
+    ///     // process the job
+    ///     db.release(&lease).unwrap();
+    /// }
+    /// ```
+    pub fn claim<T>(&self, id: &str, ttl: Duration) -> Result<Option<Lease>>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| {
+            if txn.txn.open_table(T::TABLE)?.get(id)?.is_none() {
+                return Ok(None);
+            }
+
+            lease::try_claim(&txn.txn, T::TABLE.name(), id, ttl)
+        })
+    }
+
+    /// Claims the first record of a table that isn't claimed already
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `ttl` - How long the lease lasts
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some((item, lease)))` with the claimed record and its [`Lease`]
+    /// * `Ok(None)` if every record is claimed or the table is empty
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the decryption/deserialization fails, or if the commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// while let Some((job, lease)) = db.claim_next::<Job>(Duration::from_secs(30)).unwrap() {
+    ///     run(&job);
+    ///     db.remove::<Job>(lease.id()).unwrap();
+    ///     db.release(&lease).unwrap();
+    /// }
+    /// ```
+    pub fn claim_next<T>(&self, ttl: Duration) -> Result<Option<(T, Lease)>>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| {
+            let table = txn.txn.open_table(T::TABLE)?;
+
+            for entry in table.iter()? {
+                let (key, value) = entry?;
+                if let Some(lease) = lease::try_claim(&txn.txn, T::TABLE.name(), key.value(), ttl)?
+                {
+                    let item: T = self.codec.decode_record(key.value(), value.value())?;
+                    return Ok(Some((item, lease)));
+                }
+            }
+
+            Ok(None)
+        })
+    }
+
+    /// Gives back a lease before it expires
+    ///
+    /// ## Arguments
+    ///
+    /// * `lease` - The lease returned by [`MiniDB::claim`] or [`MiniDB::claim_next`]
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(true)` if the lease was released
+    /// * `Ok(false)` if it had already expired and was claimed again, or was released already
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.release(&lease).unwrap();
+    /// ```
+    pub fn release(&self, lease: &Lease) -> Result<bool> {
+        self.transaction(|txn| lease::release(&txn.txn, lease))
+    }
+
+    /// Checks if a record is currently claimed
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the record
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the transaction fails to begin or the lease can't be read
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let busy = db.is_claimed::<Job>(&id).unwrap();
+    /// ```
+    pub fn is_claimed<T>(&self, id: &str) -> Result<bool>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        lease::is_claimed(&txn, T::TABLE.name(), id)
+    }
+
+    /// Pins a record so it can't be removed until [`MiniDB::unpin`] is called, to protect seed or reference rows
+    ///
+    /// Removing a pinned record fails with [`Error::RecordPinned`] and the [`Quota`] eviction skips it. It can still be
+    /// updated
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the record
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(true)` if the record is pinned
+    /// * `Ok(false)` if the record was not found
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or if the commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.pin::<Country>("pt").unwrap();
+    /// assert!(db.remove::<Country>("pt").is_err());
+    /// ```
+    pub fn pin<T>(&self, id: &str) -> Result<bool>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| {
+            if txn.txn.open_table(T::TABLE)?.get(id)?.is_none() {
+                return Ok(false);
+            }
+
+            pin::pin(&txn.txn, T::TABLE.name(), id)?;
+            Ok(true)
+        })
+    }
+
+    /// Unpins a record pinned with [`MiniDB::pin`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the record
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(true)` if the record was pinned
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.unpin::<Country>("pt").unwrap();
+    /// ```
+    pub fn unpin<T>(&self, id: &str) -> Result<bool>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| pin::unpin(&txn.txn, T::TABLE.name(), id))
+    }
+
+    /// Checks if a record was pinned with [`MiniDB::pin`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the record
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the transaction fails to begin or the pins can't be read
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let protected = db.is_pinned::<Country>("pt").unwrap();
+    /// ```
+    pub fn is_pinned<T>(&self, id: &str) -> Result<bool>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        pin::is_pinned(&txn, T::TABLE.name(), id)
+    }
+
+    /// Makes a table read-only until [`MiniDB::unfreeze`] is called, the flag is stored so it survives reopening
+    ///
+    /// Every write to the table fails with [`Error::TableFrozen`] while it's frozen, which is useful during migrations,
+    /// archiving or incident response
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the transaction fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.freeze::<Person>().unwrap();
+    /// assert!(db.insert(&mut person).is_err());
+    /// ```
+    pub fn freeze<T>(&self) -> Result<()>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| {
+            let mut meta = txn.txn.open_table(META_TABLE)?;
+            meta.insert(
+                format!("{META_KEY_FROZEN_PREFIX}{}", T::TABLE.name()).as_str(),
+                [].as_slice(),
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Makes a table frozen with [`MiniDB::freeze`] writable again
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(true)` if the table was frozen
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the transaction fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.unfreeze::<Person>().unwrap();
+    /// ```
+    pub fn unfreeze<T>(&self) -> Result<bool>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| {
+            let mut meta = txn.txn.open_table(META_TABLE)?;
+            let removed =
+                meta.remove(format!("{META_KEY_FROZEN_PREFIX}{}", T::TABLE.name()).as_str())?;
+            Ok(removed.is_some())
+        })
+    }
+
+    /// Checks if a table was frozen with [`MiniDB::freeze`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the meta table couldn't be opened
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// if db.is_frozen::<Person>().unwrap() {
+    ///     println!("people are read-only");
+    /// }
+    /// ```
+    pub fn is_frozen<T>(&self) -> Result<bool>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let meta = txn.open_table(META_TABLE)?;
+        Ok(meta
+            .get(format!("{META_KEY_FROZEN_PREFIX}{}", T::TABLE.name()).as_str())?
+            .is_some())
+    }
+
+    /// Returns the consumer side of the outbox, where events published with [`Transaction::publish`] wait to be delivered
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// for event in db.outbox().poll(100).unwrap() {
+    ///     queue.send(&event.topic, &event.payload)?;
+    ///     db.outbox().ack(event.seq).unwrap();
+    /// }
+    /// ```
+    #[must_use]
+    pub fn outbox(&self) -> Outbox<'_> {
+        Outbox { db: self }
+    }
+
+    /// Retrieves the salt from the meta table
+    pub(crate) fn get_salt(&self) -> Result<String> {
+        let value: Option<String> = self.get_meta(META_KEY_SALT)?;
+
+        if let Some(salt) = value {
+            Ok(salt)
+        } else {
+            let new_salt_string = SaltString::generate(&mut OsRng);
+            self.set_meta(META_KEY_SALT, &new_salt_string.to_string())?;
+            Ok(new_salt_string.to_string())
+        }
+    }
+
+    /// Retrieves an item from the meta table
+    pub(crate) fn get_meta<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(META_TABLE)?;
+        let value = table.get(key)?;
+
+        if let Some(bytes) = value {
+            let item: T = postcard::from_bytes(bytes.value())?;
+            Ok(Some(item))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Retrieves an item from the settings table
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `key` - The key of the item to retrieve
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(item))` if the item was found
+    /// * `Ok(None)` if the item was not found
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let setting = db.get_setting::<String>("key").unwrap().unwrap();
+    ///
+    /// // or
+    ///
+    /// let setting: Option<String> = db.get_setting("key").unwrap();
+    /// ```
+    pub fn get_setting<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(SETTINGS_TABLE)?;
+        let value = table.get(key)?;
+
+        let Some(bytes) = value else {
+            return Ok(None);
+        };
+
+        let item: T = self.codec.decode(bytes.value())?;
+
+        Ok(Some(item))
+    }
+
+    /// Removes an item from the table
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `key` - The key of the item to remove
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(item))` if the item was removed
+    /// * `Ok(None)` if the item was not found
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let removed_person = db.remove::<Person>("person_id").unwrap().unwrap();
+    ///
+    /// // or
+    ///
+    /// let removed_person: Option<Person> = db.remove("person_id").unwrap();
+    /// if let Some(person) = removed_person {
+    ///     println!("Person name: {}", person.name);
+    /// }
+    /// ```
+    pub fn remove<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| txn.remove(key))
+    }
+
+    /// Removes multiple items from the table
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `keys` - The keys of the items to remove
+    ///
+    /// ## Returns
+    ///
+    /// * `Vec<T>` - The items that were removed
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let removed_people = db.remove_many::<Person>(&["person_id1", "person_id2", ...]).unwrap();
+    ///
+    /// // or
+    ///
+    /// let removed_people: Vec<Person> = db.remove_many(&["person_id1", "person_id2", ...]).unwrap();
+    /// ```
+    pub fn remove_many<T>(&self, keys: &[&str]) -> Result<Vec<T>>
+    where
+        T: Table,
+    {
+        self.transaction(|txn| txn.remove_many(keys))
+    }
+
+    /// Removes every item of a table that matches a predicate, in a single transaction
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `predicate` - Returns `true` for the items to remove
+    ///
+    /// ## Returns
+    ///
+    /// * `usize` - The number of items that were removed
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let removed = db.delete_where::<Session, _>(|session| session.expires_at < now).unwrap();
+    /// ```
+    pub fn delete_where<T, F>(&self, predicate: F) -> Result<usize>
+    where
+        T: Table,
+        F: FnMut(&T) -> bool,
+    {
+        self.transaction(|txn| txn.delete_where(predicate))
+    }
+
+    /// Applies a mutation to every item of a table that matches a filter and writes them back, in a single transaction
+    ///
+    /// The ID of the items can't be changed, it's restored after the mutation
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `filter` - Returns `true` for the items to update
+    /// * `mutator` - Changes a matching item
+    ///
+    /// ## Returns
+    ///
+    /// * `usize` - The number of items that were updated
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let updated = db
+    ///     .update_where::<Person, _, _>(|person| person.age < 18, |person| person.minor = true)
+    ///     .unwrap();
+    /// ```
+    pub fn update_where<T, F, M>(&self, filter: F, mutator: M) -> Result<usize>
+    where
+        T: Table,
+        F: FnMut(&T) -> bool,
+        M: FnMut(&mut T),
+    {
+        self.transaction(|txn| txn.update_where(filter, mutator))
+    }
+
+    /// Sets an item in the meta table
+    pub(crate) fn set_meta<T>(&self, key: &str, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(META_TABLE)?;
+            let bytes = postcard::to_stdvec(value)?;
+            table.insert(key, bytes.as_slice())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Sets an item in the settings table
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `key` - The key of the item to set
+    /// * `value` - The value of the item to set
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.set_setting("key", "value").unwrap();
+    /// ```
+    pub fn set_setting<T>(&self, key: &str, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.transaction(|txn| txn.set_setting(key, value))
+    }
+
+    /// Retrieves the format the database is stored in
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(info))` with the stored [`FormatInfo`]
+    /// * `Ok(None)` if the database wasn't opened with [`MiniDB::builder`] since the format started being stored
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the meta table can't be read or the format can't be deserialized
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let format = db.format_info().unwrap().unwrap();
+    /// println!("format version {}", format.version);
+    /// ```
+    pub fn format_info(&self) -> Result<Option<FormatInfo>> {
+        self.get_meta(format::META_KEY_FORMAT)
+    }
+
+    /// Returns how many generated IDs were already taken and had to be generated again since the database was opened
+    ///
+    /// Collisions are very unlikely so anything other than zero usually means a table has grown past what the ID
+    /// length can comfortably handle
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// println!("{} ID collisions", db.id_retries());
+    /// ```
+    #[must_use]
+    pub fn id_retries(&self) -> u64 {
+        self.id_retries.load(Ordering::Relaxed)
+    }
+
+    /// Retrieves the stored information about a table
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the table
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(Some(info))` if the table was registered
+    /// * `Ok(None)` if the table was not found
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the meta table couldn't be opened or if the deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let info = db.table_info("people").unwrap().unwrap();
+    /// println!("{}: {:?}", info.name, info.comment);
+    /// ```
+    pub fn table_info(&self, name: &str) -> Result<Option<TableInfo>> {
+        self.get_meta(&format!("{META_KEY_TABLE_PREFIX}{name}"))
+    }
+
+    /// Retrieves the stored information about every registered table
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the [`TableInfo`] of every table, sorted by name
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the meta table couldn't be opened or if the deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// for info in db.tables().unwrap() {
+    ///     println!("{}: {}", info.name, info.comment.unwrap_or_default());
+    /// }
+    /// ```
+    pub fn tables(&self) -> Result<Vec<TableInfo>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(META_TABLE)?;
+
+        let mut results = Vec::new();
+        for item in table.range(META_KEY_TABLE_PREFIX..)? {
+            let (key, value) = item?;
+            if !key.value().starts_with(META_KEY_TABLE_PREFIX) {
+                break;
+            }
+
+            results.push(postcard::from_bytes(value.value())?);
+        }
+
+        Ok(results)
+    }
+
+    /// Retrieves an extra metadata entry
+    ///
+    /// Extras are free-form bytes stored next to the database metadata, for things like counters, schema fingerprints or
+    /// retention configs. Readers that don't know an entry just ignore it. They're **not** encrypted
     ///
     /// ## Arguments
     ///
-    /// * `T` - The table model
-    /// * `id` - The id of the item to retrieve
+    /// * `key` - The key of the entry
     ///
     /// ## Returns
     ///
-    /// * `Ok(Some(item))` if the item was found
-    /// * `Ok(None)` if the item was not found
+    /// * `Ok(Some(bytes))` if the entry exists
+    /// * `Ok(None)` if it doesn't
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    /// Returns an error if the meta table couldn't be opened
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// // retrieves an item from the table `Person`
-    /// let person = db.get::<Person>("person_id").unwrap().unwrap();
-    /// println!("Person name: {}", person.name);
-    ///
-    /// // or
-    ///
-    /// let person: Option<Person> = db.get("person_id").unwrap();
-    /// if let Some(person) = person {
-    ///     println!("Person name: {}", person.name);
-    /// }
+    /// let fingerprint = db.get_extra("schema_fingerprint").unwrap();
     /// ```
-    pub fn get<T>(&self, id: &str) -> Result<Option<T>>
-    where
-        T: Table,
-    {
+    pub fn get_extra(&self, key: &str) -> Result<Option<Vec<u8>>> {
         let txn = self.db.begin_read()?;
-        let table = txn.open_table(T::TABLE)?;
-        let value = table.get(id)?;
-
-        let Some(bytes) = value else {
-            return Ok(None);
-        };
-
-        let item: T = if let Some(cipher) = &self.cipher {
-            let decrypted = decrypt_bytes(cipher, bytes.value())?;
-            postcard::from_bytes(&decrypted)?
-        } else {
-            postcard::from_bytes(bytes.value())?
-        };
-
-        Ok(Some(item))
-    }
-
-    /// Retrieves the salt from the meta table
-    pub(crate) fn get_salt(&self) -> Result<String> {
-        let value: Option<String> = self.get_meta(META_KEY_SALT)?;
+        let table = txn.open_table(META_TABLE)?;
+        let value = table.get(format!("{META_KEY_EXTRA_PREFIX}{key}").as_str())?;
 
-        if let Some(salt) = value {
-            Ok(salt)
-        } else {
-            let new_salt_string = SaltString::generate(&mut OsRng);
-            self.set_meta(META_KEY_SALT, &new_salt_string.to_string())?;
-            Ok(new_salt_string.to_string())
-        }
+        Ok(value.map(|bytes| bytes.value().to_vec()))
     }
 
-    /// Retrieves an item from the meta table
-    pub(crate) fn get_meta<T>(&self, key: &str) -> Result<Option<T>>
-    where
-        T: for<'a> Deserialize<'a>,
-    {
-        let txn = self.db.begin_read()?;
-        let table = txn.open_table(META_TABLE)?;
-        let value = table.get(key)?;
-
-        if let Some(bytes) = value {
-            let item: T = postcard::from_bytes(bytes.value())?;
-            Ok(Some(item))
-        } else {
-            Ok(None)
+    /// Sets an extra metadata entry, see [`MiniDB::get_extra`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the entry
+    /// * `value` - The bytes to store
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the meta table couldn't be opened or the transaction fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.set_extra("retention_days", &30u32.to_le_bytes()).unwrap();
+    /// ```
+    pub fn set_extra(&self, key: &str, value: &[u8]) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(META_TABLE)?;
+            table.insert(format!("{META_KEY_EXTRA_PREFIX}{key}").as_str(), value)?;
         }
+        txn.commit()?;
+        Ok(())
     }
 
-    /// Retrieves an item from the settings table
+    /// Removes an extra metadata entry, see [`MiniDB::get_extra`]
     ///
     /// ## Arguments
     ///
-    /// * `T` - The table model
-    /// * `key` - The key of the item to retrieve
+    /// * `key` - The key of the entry
     ///
     /// ## Returns
     ///
-    /// * `Ok(Some(item))` if the item was found
-    /// * `Ok(None)` if the item was not found
+    /// `Ok(true)` if the entry existed
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    /// Returns an error if the meta table couldn't be opened or the transaction fails
+    pub fn remove_extra(&self, key: &str) -> Result<bool> {
+        let txn = self.db.begin_write()?;
+        let existed = {
+            let mut table = txn.open_table(META_TABLE)?;
+            table
+                .remove(format!("{META_KEY_EXTRA_PREFIX}{key}").as_str())?
+                .is_some()
+        };
+        txn.commit()?;
+        Ok(existed)
+    }
+
+    /// Retrieves every extra metadata entry, see [`MiniDB::get_extra`]
     ///
-    /// ## Example
+    /// ## Returns
     ///
-    /// ```rust,ignore
-    /// let setting = db.get_setting::<String>("key").unwrap().unwrap();
+    /// A [`Result`] containing the `(key, value)` pairs, sorted by key
     ///
-    /// // or
+    /// ## Errors
     ///
-    /// let setting: Option<String> = db.get_setting("key").unwrap();
-    /// ```
-    pub fn get_setting<T>(&self, key: &str) -> Result<Option<T>>
-    where
-        T: for<'a> Deserialize<'a>,
-    {
+    /// Returns an error if the meta table couldn't be opened
+    pub fn extras(&self) -> Result<Vec<(String, Vec<u8>)>> {
         let txn = self.db.begin_read()?;
-        let table = txn.open_table(SETTINGS_TABLE)?;
-        let value = table.get(key)?;
+        let table = txn.open_table(META_TABLE)?;
 
-        let Some(bytes) = value else {
-            return Ok(None);
-        };
+        let mut results = Vec::new();
+        for item in table.range(META_KEY_EXTRA_PREFIX..)? {
+            let (key, value) = item?;
+            let Some(name) = key.value().strip_prefix(META_KEY_EXTRA_PREFIX) else {
+                break;
+            };
 
-        let item: T = if let Some(cipher) = &self.cipher {
-            let decrypted = decrypt_bytes(cipher, bytes.value())?;
-            postcard::from_bytes(&decrypted)?
-        } else {
-            postcard::from_bytes(bytes.value())?
-        };
+            results.push((name.to_string(), value.value().to_vec()));
+        }
 
-        Ok(Some(item))
+        Ok(results)
     }
 
-    /// Removes an item from the table
+    /// Merges the records of a table from another copy of the database, the most recently written version wins
+    ///
+    /// Records that only exist in `other` are copied, records that differ are resolved with [`SyncResolution::newest`],
+    /// which needs [`MiniDBBuilder::record_timestamps`] on both copies to be meaningful. Deletions aren't propagated,
+    /// to merge both ways call it on each copy
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
-    /// * `key` - The key of the item to remove
+    /// * `other` - The database to merge from, it can use a different key
     ///
     /// ## Returns
     ///
-    /// * `Ok(Some(item))` if the item was removed
-    /// * `Ok(None)` if the item was not found
+    /// * `Ok(report)` with a [`SyncReport`] of what changed locally
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    /// Returns an error if either database can't be read, if a record can't be decrypted/deserialized, or if the commit fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// let removed_person = db.remove::<Person>("person_id").unwrap().unwrap();
-    ///
-    /// // or
-    ///
-    /// let removed_person: Option<Person> = db.remove("person_id").unwrap();
-    /// if let Some(person) = removed_person {
-    ///     println!("Person name: {}", person.name);
-    /// }
+    /// laptop.sync_from::<User>(&desktop)?;
+    /// desktop.sync_from::<User>(&laptop)?;
     /// ```
-    pub fn remove<T>(&self, key: &str) -> Result<Option<T>>
+    pub fn sync_from<T>(&self, other: &MiniDB) -> Result<SyncReport>
     where
         T: Table,
     {
-        let txn = self.db.begin_write()?;
-        let mut result = None;
-        {
-            let mut table = txn.open_table(T::TABLE)?;
-            let maybe_bytes = table.remove(key)?;
-
-            if let Some(bytes) = maybe_bytes {
-                let item: T = if let Some(cipher) = &self.cipher {
-                    let decrypted = decrypt_bytes(cipher, bytes.value())?;
-                    postcard::from_bytes(&decrypted)?
-                } else {
-                    postcard::from_bytes(bytes.value())?
-                };
-
-                result = Some(item);
-            }
-        }
-        txn.commit()?;
-        Ok(result)
+        self.sync_from_with::<T, _>(other, SyncResolution::newest)
     }
 
-    /// Removes multiple items from the table
+    /// Retrieves an item, falling back to a replica when the local record is missing or can't be decrypted/deserialized
+    ///
+    /// The version found in the replica is written back locally with its timestamps, so the next read doesn't need the
+    /// replica. Only use it with replicas kept in sync with [`MiniDB::sync_from`], otherwise records deleted locally
+    /// come back from the replica
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
-    /// * `keys` - The keys of the items to remove
+    /// * `id` - The ID of the item
+    /// * `replica` - Another copy of the database, it can use a different key
     ///
     /// ## Returns
     ///
-    /// * `Vec<T>` - The items that were removed
+    /// * `Ok(Some(item))` with the local item, or the replica's one if the local record had to be repaired
+    /// * `Ok(None)` if neither copy has the item
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    /// Returns the local error if the record is damaged and the replica doesn't have it, or an error if either
+    /// database can't be read or if the commit fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// let removed_people = db.remove_many::<Person>(&["person_id1", "person_id2", ...]).unwrap();
-    ///
-    /// // or
-    ///
-    /// let removed_people: Vec<Person> = db.remove_many(&["person_id1", "person_id2", ...]).unwrap();
+    /// let user = primary.get_with_repair::<User>(&id, &backup).unwrap();
     /// ```
-    pub fn remove_many<T>(&self, keys: &[&str]) -> Result<Vec<T>>
+    pub fn get_with_repair<T>(&self, id: &str, replica: &MiniDB) -> Result<Option<T>>
     where
         T: Table,
     {
-        let txn = self.db.begin_write()?;
-        let mut result = Vec::new();
-        {
-            let mut table = txn.open_table(T::TABLE)?;
-            for key in keys {
-                let maybe_bytes = table.remove(key)?;
-
-                if let Some(bytes) = maybe_bytes {
-                    let item: T = if let Some(cipher) = &self.cipher {
-                        let decrypted = decrypt_bytes(cipher, bytes.value())?;
-                        postcard::from_bytes(&decrypted)?
-                    } else {
-                        postcard::from_bytes(bytes.value())?
-                    };
-
-                    result.push(item);
-                }
+        let local = {
+            let txn = self.db.begin_read()?;
+            let table = txn.open_table(T::TABLE)?;
+            self.read_record::<T>(&table, id)
+        };
+
+        let damaged = match local {
+            Ok(Some(item)) => return Ok(Some(item)),
+            Ok(None) => None,
+            Err(e @ (Error::Crypto(_) | Error::Serialization(_) | Error::UnknownFields(_))) => {
+                Some(e)
             }
-        }
-        txn.commit()?;
-        Ok(result)
-    }
+            Err(e) => return Err(e),
+        };
 
-    /// Sets an item in the meta table
-    pub(crate) fn set_meta<T>(&self, key: &str, value: &T) -> Result<()>
-    where
-        T: Serialize,
-    {
-        let txn = self.db.begin_write()?;
-        {
-            let mut table = txn.open_table(META_TABLE)?;
-            let bytes = postcard::to_stdvec(value)?;
-            table.insert(key, bytes.as_slice())?;
+        match self.transaction(|txn| sync::restore::<T>(txn, replica, id))? {
+            Some(item) => Ok(Some(item)),
+            None => damaged.map_or(Ok(None), Err),
         }
-        txn.commit()?;
-        Ok(())
     }
 
-    /// Sets an item in the settings table
+    /// Merges the records of a table from another copy of the database, resolving differences with a callback
+    ///
+    /// Works like [`MiniDB::sync_from`] but `resolve` is called for every record that exists in both copies with
+    /// different contents
     ///
     /// ## Arguments
     ///
     /// * `T` - The table model
-    /// * `key` - The key of the item to set
-    /// * `value` - The value of the item to set
+    /// * `other` - The database to merge from, it can use a different key
+    /// * `resolve` - Decides which version of a [`SyncConflict`] to keep
+    ///
+    /// ## Returns
+    ///
+    /// * `Ok(report)` with a [`SyncReport`] of what changed locally
     ///
     /// ## Errors
     ///
-    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    /// Returns an error if either database can't be read, if a record can't be decrypted/deserialized, or if the commit fails
     ///
     /// ## Example
     ///
     /// ```rust,ignore
-    /// db.set_setting("key", "value").unwrap();
+    /// laptop.sync_from_with::<User, _>(&desktop, |conflict| {
+    ///     if conflict.remote.age > conflict.local.age {
+    ///         SyncResolution::Remote
+    ///     } else {
+    ///         SyncResolution::Local
+    ///     }
+    /// })?;
     /// ```
-    pub fn set_setting<T>(&self, key: &str, value: &T) -> Result<()>
+    pub fn sync_from_with<T, F>(&self, other: &MiniDB, resolve: F) -> Result<SyncReport>
     where
-        T: Serialize,
+        T: Table,
+        F: FnMut(&SyncConflict<T>) -> SyncResolution,
     {
-        let txn = self.db.begin_write()?;
-        {
-            let mut table = txn.open_table(SETTINGS_TABLE)?;
-            let bytes = postcard::to_stdvec(value)?;
-
-            let to_write: Vec<u8> = if let Some(cipher) = &self.cipher {
-                encrypt_bytes(cipher, &bytes)?
-            } else {
-                bytes
-            };
-
-            table.insert(key, to_write.as_slice())?;
-        }
-        txn.commit()?;
-        Ok(())
+        self.transaction(|txn| sync::pull(txn, other, resolve))
     }
 
     /// Starts a write transaction.
@@ -849,13 +3170,10 @@ impl MiniDB {
         F: FnOnce(&Transaction) -> Result<R>,
     {
         let txn = self.db.begin_write()?;
-        let transaction = Transaction {
-            txn,
-            cipher: self.cipher.as_ref(),
-        };
+        let transaction = Transaction::new(txn, self);
 
         let result = f(&transaction)?;
-        transaction.txn.commit()?;
+        transaction.commit()?;
         Ok(result)
     }
 
@@ -887,25 +3205,7 @@ impl MiniDB {
     where
         T: Table,
     {
-        if item.get_id().trim().is_empty() {
-            return Err(Error::EmptyID);
-        }
-
-        let txn = self.db.begin_write()?;
-        {
-            let mut table = txn.open_table(T::TABLE)?;
-            let bytes = postcard::to_stdvec(&item)?;
-
-            let to_write: Vec<u8> = if let Some(cipher) = &self.cipher {
-                encrypt_bytes(cipher, &bytes)?
-            } else {
-                bytes
-            };
-
-            table.insert(item.get_id(), to_write.as_slice())?;
-        }
-        txn.commit()?;
-        Ok(())
+        self.transaction(|txn| txn.update(item))
     }
 
     /// Updates multiple items in the table
@@ -942,29 +3242,10 @@ impl MiniDB {
     where
         T: Table,
     {
-        let txn = self.db.begin_write()?;
-        {
-            let mut table = txn.open_table(T::TABLE)?;
-            for item in items {
-                if item.get_id().trim().is_empty() {
-                    return Err(Error::EmptyID);
-                }
-                let bytes = postcard::to_stdvec(item)?;
-
-                let to_write: Vec<u8> = if let Some(cipher) = &self.cipher {
-                    encrypt_bytes(cipher, &bytes)?
-                } else {
-                    bytes
-                };
-
-                table.insert(item.get_id(), to_write.as_slice())?;
-            }
-        }
-        txn.commit()?;
-        Ok(())
+        self.transaction(|txn| txn.update_many(items))
     }
 
-    /// Returns an iterator over all items in a table, allowing for custom processing
+    /// Returns an iterator over all items in a table in ID order, allowing for custom processing
     ///
     /// ## Arguments
     ///
@@ -993,12 +3274,82 @@ impl MiniDB {
     {
         let txn = self.db.begin_read()?;
         let table = txn.open_table(T::TABLE)?;
-        let mut iter = TableIterator::new(table.iter()?);
-
-        if let Some(cipher) = &self.cipher {
-            iter = iter.with_cipher(cipher);
-        }
+        let iter = TableIterator::new(table.iter()?).with_codec(&self.codec);
 
         Ok(f(iter))
     }
+
+    /// Returns a lazy iterator over all items in a table in ID order, decoding each item only when it's reached
+    ///
+    /// Unlike [`MiniDB::all`] the items aren't collected, so huge tables can be processed with constant memory. The
+    /// iterator reads from the snapshot taken when it was created, writes made meanwhile aren't seen
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the [`TableIterator`], which yields an error for every item that fails to decode
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or if the table is not initialized
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// for person in db.iter::<Person>().unwrap() {
+    ///     let person = person.unwrap();
+    ///     println!("{}", person.name);
+    /// }
+    /// ```
+    // opening the table can fail before there's anything to iterate
+    #[allow(clippy::iter_not_returning_iterator)]
+    pub fn iter<T>(&self) -> Result<TableIterator<'_, T>>
+    where
+        T: Table,
+    {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        // the inherent range of a read-only table keeps the snapshot alive on its own
+        Ok(TableIterator::new(table.range::<&str>(..)?).with_codec(&self.codec))
+    }
+
+    /// Returns an iterator over all items in a table in batches of up to `batch_size` items
+    ///
+    /// The IDs are listed up front, then every batch is read in a fresh read transaction, so a slow consumer doesn't
+    /// keep an old snapshot alive for the whole scan. Records removed meanwhile are skipped and records inserted
+    /// meanwhile aren't seen, updates made before a batch is read are
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `batch_size` - The maximum number of items in a batch, `0` is treated as `1`
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the [`Batches`] iterator, which yields an error for every batch that fails to read
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found or if the table is not initialized
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// for batch in db.batches::<Person>(500).unwrap() {
+    ///     for person in batch.unwrap() {
+    ///         println!("{}", person.name);
+    ///     }
+    /// }
+    /// ```
+    pub fn batches<T>(&self, batch_size: usize) -> Result<Batches<'_, T>>
+    where
+        T: Table,
+    {
+        let ids = self.ids::<T>()?;
+        Ok(Batches::new(self, ids, batch_size))
+    }
 }
@@ -0,0 +1,344 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::error::{Error, Result};
+
+/// How the database file is protected from being opened by more than one process
+///
+/// redb always takes an OS lock on the file, which is `flock` on Unix and the file share mode on Windows.
+/// Those are advisory on Unix, so they only stop other redb users, and on network filesystems like NFS or SMB they
+/// can silently do nothing depending on the server. [`LockBackend::PidFile`] adds a lock that only relies on
+/// exclusive file creation, which those filesystems do support
+///
+/// ## Variants
+///
+/// * `LockBackend::Os` - Only rely on the OS lock taken by redb
+/// * `LockBackend::PidFile` - Also create a `<database>.lock` file containing the PID and hostname of the owner, removed when the [`MiniDB`](crate::MiniDB) is dropped
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockBackend {
+    /// Only rely on the OS lock taken by redb
+    #[default]
+    Os,
+
+    /// Also use a lock file containing the PID and hostname of the owner
+    PidFile,
+}
+
+/// How old a lock file without a valid PID has to be before it's considered abandoned
+///
+/// Lock files are written to a staging file and linked into place, so a half-written one comes from a crash or
+/// another program, but it may still be getting written by a process that's slower than us
+const GARBLED_LOCK_AGE: Duration = Duration::from_secs(10);
+
+/// Makes the staging files of concurrent acquisitions within one process unique
+static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The owner of a `<database>.lock` file created by [`LockBackend::PidFile`], returned by
+/// [`MiniDB::lock_holder`](crate::MiniDB::lock_holder)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockHolder {
+    /// The path of the lock file
+    pub path: PathBuf,
+
+    /// The PID of the process holding the lock, [`None`] if the file doesn't contain a valid PID
+    pub pid: Option<u32>,
+
+    /// The hostname of the machine the process runs on, [`None`] for lock files written by older versions
+    pub host: Option<String>,
+
+    /// When the lock was taken, [`None`] if the filesystem doesn't keep modification times
+    pub since: Option<SystemTime>,
+
+    /// Whether the lock is known to be abandoned
+    ///
+    /// Either the process holding it is gone, which is only detected on Linux for processes of this same host, or the
+    /// file has no valid PID and is older than a few seconds
+    pub stale: bool,
+}
+
+/// Reads the owner of the lock file of a database, [`None`] if there's no lock file
+pub(crate) fn holder(db_path: &Path) -> Result<Option<LockHolder>> {
+    Ok(read_lock(&lock_path(db_path))?.map(|(_, holder)| holder))
+}
+
+/// Reads a lock file along with its raw contents, [`None`] if it doesn't exist
+fn read_lock(path: &Path) -> Result<Option<(String, LockHolder)>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut lines = contents.lines();
+    let pid = lines
+        .next()
+        .and_then(|line| line.trim().parse::<u32>().ok());
+    let host = lines
+        .next()
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+        .map(ToString::to_string);
+    let since = match fs::metadata(path) {
+        Ok(metadata) => metadata.modified().ok(),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let stale = match pid {
+        // PIDs of other machines sharing the filesystem mean nothing here
+        Some(pid) => host.as_deref().is_none_or(|host| host == hostname()) && is_gone(pid),
+        None => since
+            .and_then(|since| since.elapsed().ok())
+            .is_some_and(|age| age >= GARBLED_LOCK_AGE),
+    };
+
+    Ok(Some((
+        contents,
+        LockHolder {
+            path: path.to_path_buf(),
+            pid,
+            host,
+            since,
+            stale,
+        },
+    )))
+}
+
+/// Removes a lock file only if it still has the `expected` contents, returns whether it was removed
+///
+/// The file is first renamed to a name only this call knows, which is atomic, and checked there. If it changed hands
+/// since it was read it's linked back into place, unless yet another owner took the path meanwhile
+fn remove_if_unchanged(path: &Path, expected: &str) -> Result<bool> {
+    let taken = unique_path(path, ".taken");
+    match fs::rename(path, &taken) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    }
+
+    let unchanged = fs::read_to_string(&taken).is_ok_and(|contents| contents == expected);
+    if !unchanged && fs::hard_link(&taken, path).is_err() && !path.exists() {
+        let _ = fs::rename(&taken, path);
+    }
+    let _ = fs::remove_file(&taken);
+
+    Ok(unchanged)
+}
+
+/// Returns a path next to `path` that no other acquisition or removal uses at the same time
+fn unique_path(path: &Path, suffix: &str) -> PathBuf {
+    let n = STAGING_COUNTER.fetch_add(1, Ordering::Relaxed);
+    with_suffix(path, &format!(".{}.{n}{suffix}", std::process::id()))
+}
+
+/// Returns the name of this machine, so lock files written by other hosts on a shared filesystem can be told apart
+fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        ["/proc/sys/kernel/hostname", "/etc/hostname"]
+            .iter()
+            .find_map(|path| fs::read_to_string(path).ok())
+            .or_else(|| env::var("HOSTNAME").ok())
+            .or_else(|| env::var("COMPUTERNAME").ok())
+            .map(|host| host.trim().to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Removes the lock file of a database if it's still held by `pid`
+///
+/// The contents read here are checked again after the file is moved aside by [`remove_if_unchanged`], so a lock taken
+/// over in between is put back instead of deleted
+pub(crate) fn force_unlock(db_path: &Path, pid: Option<u32>) -> Result<bool> {
+    let path = lock_path(db_path);
+    let Some((contents, current)) = read_lock(&path)? else {
+        return Ok(false);
+    };
+    if current.pid != pid {
+        return Ok(false);
+    }
+
+    remove_if_unchanged(&path, &contents)
+}
+
+/// A lock file that gets removed when dropped
+#[derive(Debug)]
+pub(crate) struct LockFile {
+    path: PathBuf,
+}
+
+impl LockFile {
+    /// Creates the lock file for a database, replacing it if it's stale
+    ///
+    /// The contents are written to a staging file first and hard linked into place, which fails if the lock file
+    /// exists even on NFS, so the lock file is never seen half-written
+    pub(crate) fn acquire(db_path: &Path) -> Result<Self> {
+        let path = lock_path(db_path);
+        let contents = format!("{}\n{}\n", std::process::id(), hostname());
+
+        let staging = unique_path(&path, ".tmp");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&staging)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        let result = Self::link(&path, &staging, &contents);
+        let _ = fs::remove_file(&staging);
+        result.map(|()| Self { path })
+    }
+
+    /// Links the staging file into place, moving a stale lock file out of the way first
+    fn link(path: &Path, staging: &Path, contents: &str) -> Result<()> {
+        loop {
+            match fs::hard_link(staging, path) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let Some((found, holder)) = read_lock(path)? else {
+                        continue;
+                    };
+                    if !holder.stale {
+                        return Err(Error::Locked {
+                            path: path.to_path_buf(),
+                            pid: holder.pid,
+                        });
+                    }
+
+                    // only the process that moves this exact file away gets to retry first
+                    remove_if_unchanged(path, &found)?;
+                }
+                // filesystems without hard links still support exclusive creation
+                Err(_) => {
+                    let mut file = match OpenOptions::new().write(true).create_new(true).open(path)
+                    {
+                        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                            return Err(Error::Locked {
+                                path: path.to_path_buf(),
+                                pid: None,
+                            });
+                        }
+                        file => file?,
+                    };
+                    file.write_all(contents.as_bytes())?;
+                    file.sync_all()?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// How long to sleep between attempts while waiting for a lock
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs `f` until it succeeds, retrying while the database is locked by someone else and `timeout` hasn't passed
+///
+/// Without a timeout `f` only runs once
+pub(crate) fn retry_while_locked<T, F>(timeout: Option<Duration>, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    loop {
+        match f() {
+            Err(e) if is_lock_contention(&e) && deadline.is_some_and(|d| Instant::now() < d) => {
+                thread::sleep(LOCK_RETRY_INTERVAL);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Checks whether an error means another owner holds the database
+fn is_lock_contention(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Locked { .. } | Error::Database(redb::DatabaseError::DatabaseAlreadyOpen)
+    )
+}
+
+/// Tracks a write transaction while it's in flight
+///
+/// redb rolls back a transaction that's dropped without committing, but a panic in the middle of a write may leave the
+/// process in a state we can't reason about, so the guard leaves a `<database>.recover` marker behind when it's dropped
+/// during a panic. [`MiniDBBuilder::build`](crate::MiniDBBuilder::build) checks the integrity of the database when it finds one
+#[derive(Debug)]
+pub(crate) struct WriteGuard {
+    marker: Option<PathBuf>,
+}
+
+impl WriteGuard {
+    /// Starts tracking a write to the database at `db_path`, without a path nothing is marked
+    pub(crate) fn new(db_path: Option<&Path>) -> Self {
+        Self {
+            marker: db_path.map(recovery_path),
+        }
+    }
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+
+        if let Some(marker) = &self.marker {
+            let _ = fs::write(marker, std::process::id().to_string());
+        }
+    }
+}
+
+/// Returns the path of the lock file for a database
+pub(crate) fn lock_path(db_path: &Path) -> PathBuf {
+    with_suffix(db_path, ".lock")
+}
+
+/// Returns the path of the marker left when a write was interrupted by a panic
+pub(crate) fn recovery_path(db_path: &Path) -> PathBuf {
+    with_suffix(db_path, ".recover")
+}
+
+fn with_suffix(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Checks whether a process of this host is gone, only possible on Linux without extra dependencies
+///
+/// A lock created by this same process is never stale, the database is still open somewhere else in it
+#[cfg(target_os = "linux")]
+fn is_gone(pid: u32) -> bool {
+    pid != std::process::id() && !Path::new(&format!("/proc/{pid}")).exists()
+}
+
+/// Checks whether a process of this host is gone, only possible on Linux without extra dependencies
+///
+/// Other platforms can't tell so the lock is always considered held, remove the file manually after a crash
+#[cfg(not(target_os = "linux"))]
+fn is_gone(_pid: u32) -> bool {
+    false
+}
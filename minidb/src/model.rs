@@ -2,14 +2,15 @@
 // Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
 // with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::{fmt::Debug, marker::PhantomData};
+use std::{borrow::Cow, fmt::Debug, marker::PhantomData};
 
 use crate::{
-    encryption::decrypt_bytes,
+    MiniDB,
+    codec::Codec,
     error::{Error, Result},
 };
 use chacha20poly1305::XChaCha20Poly1305;
-use redb::{Range, TableDefinition};
+use redb::{Range, ReadableDatabase, TableDefinition, TableHandle};
 use serde::{Deserialize, Serialize};
 
 /// A table model. A table model is a struct that implements the [`Table`] trait.
@@ -46,17 +47,217 @@ pub trait Table: Serialize + for<'de> Deserialize<'de> {
     /// The table definition
     const TABLE: TableDefinition<'_, &'static str, &[u8]>;
 
+    /// A description of the table, stored in the database metadata
+    const COMMENT: Option<&'static str> = None;
+
+    /// Descriptions of the fields as `(field, comment)` pairs, stored in the database metadata
+    const FIELD_COMMENTS: &'static [(&'static str, &'static str)] = &[];
+
+    /// Whether [`Table::compute`] recalculates any field, writes skip it otherwise
+    const HAS_COMPUTED: bool = false;
+
+    /// Sensitive fields masked in exports like [`MiniDB::export_table`](crate::MiniDB::export_table), hand-written
+    /// models also have to reset them in [`Table::redact`]
+    const REDACTED_FIELDS: &'static [&'static str] = &[];
+
+    /// Fields with a secondary index, looked up with [`MiniDB::find_by_index`](crate::MiniDB::find_by_index)
+    const INDEXES: &'static [&'static str] = &[];
+
+    /// Indexed fields no two records can share a value of, writes that would break it fail with
+    /// [`Error::UniqueViolation`]
+    const UNIQUE: &'static [&'static str] = &[];
+
+    /// Fields whose words are kept in the full-text index searched by [`MiniDB::search`](crate::MiniDB::search)
+    const FULLTEXT: &'static [&'static str] = &[];
+
+    /// Whether the table holds at most one record, stored under [`SINGLETON_ID`](crate::SINGLETON_ID) and used through
+    /// [`MiniDB::singleton`](crate::MiniDB::singleton). Writes under any other ID fail with [`Error::NotSingleton`]
+    const SINGLETON: bool = false;
+
     /// Returns the id of the table model
     fn get_id(&self) -> &str;
 
     /// Sets the id of the table model
     fn set_id(&mut self, id: String);
+
+    /// Rebuilds runtime-only fields after the record is read from the database
+    ///
+    /// Fields skipped by serde come back as their default value, this is the place to reconstruct them (caches, handles, etc).
+    /// The derive macro implements it for fields marked with `#[minidb(transient, rehydrate = "...")]`
+    fn rehydrate(&mut self) {}
+
+    /// Recalculates stored fields derived from the rest of the record, before every write
+    ///
+    /// Unlike [`Table::rehydrate`] the results are stored, so scans see them without recomputing. Updates only get a
+    /// shared reference so the hook runs on a copy of the record, where transient fields are back to their default.
+    /// The derive macro implements it for fields marked with `#[minidb(computed = "...")]`
+    fn compute(&mut self) {}
+
+    /// Resets the [`Table::REDACTED_FIELDS`], called on a copy of the record by
+    /// [`MiniDB::export_record`](crate::MiniDB::export_record)
+    ///
+    /// The blob has to import back into the model, so the fields are reset instead of masked. The derive macro sets
+    /// fields marked with `#[minidb(redact)]` to their [`Default`] value
+    fn redact(&mut self) {}
+
+    /// Returns the serialized values of the [`Table::INDEXES`] fields as `(field, value)` pairs
+    ///
+    /// The derive macro implements it for fields marked with `#[index]`
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if a value can't be serialized
+    fn index_values(&self) -> Result<Vec<(&'static str, Vec<u8>)>> {
+        Ok(Vec::new())
+    }
+
+    /// Returns the text of the [`Table::FULLTEXT`] fields
+    ///
+    /// The derive macro implements it for fields marked with `#[fulltext]`
+    fn fulltext_values(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
+
+/// Information about a registered table, stored in the database metadata
+///
+/// Returned by [`MiniDB::tables`](crate::MiniDB::tables) and [`MiniDB::table_info`](crate::MiniDB::table_info)
+/// so a database can be understood without its source code
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableInfo {
+    /// The name of the table
+    pub name: String,
+
+    /// The description of the table
+    pub comment: Option<String>,
+
+    /// The descriptions of the fields as `(field, comment)` pairs
+    pub field_comments: Vec<(String, String)>,
+}
+
+impl TableInfo {
+    /// Creates the [`TableInfo`] of the table model `T`
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// A new [`TableInfo`]
+    #[must_use]
+    pub fn of<T>() -> Self
+    where
+        T: Table,
+    {
+        Self {
+            name: T::TABLE.name().to_string(),
+            comment: T::COMMENT.map(ToString::to_string),
+            field_comments: T::FIELD_COMMENTS
+                .iter()
+                .map(|(field, comment)| ((*field).to_string(), (*comment).to_string()))
+                .collect(),
+        }
+    }
 }
 
-/// An iterator over a table's items, with optional decryption
+/// The outcome of a lenient scan, returned by [`MiniDB::all_lenient`](crate::MiniDB::all_lenient)
+#[derive(Debug)]
+pub struct ScanReport<T> {
+    /// The items that were decoded
+    pub items: Vec<T>,
+
+    /// The records that couldn't be decrypted/deserialized, as `(id, error)` pairs
+    pub corrupted: Vec<(String, Error)>,
+}
+
+impl<T> ScanReport<T> {
+    /// Whether every record was decoded
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+}
+
+/// A page of items, returned by [`MiniDB::page`](crate::MiniDB::page) and [`MiniDB::page_at`](crate::MiniDB::page_at)
+#[derive(Debug)]
+pub struct Page<T> {
+    /// The items of the page, in ID order
+    pub items: Vec<T>,
+
+    /// The cursor to pass to [`MiniDB::page`](crate::MiniDB::page) for the next page, [`None`] on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// An iterator over a table's items in batches, returned by [`MiniDB::batches`](crate::MiniDB::batches)
+///
+/// Each batch is read in its own read transaction, so no snapshot is kept alive while the caller works on a batch
+pub struct Batches<'a, T> {
+    db: &'a MiniDB,
+    ids: std::vec::IntoIter<String>,
+    batch_size: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> Debug for Batches<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Batches")
+            .field("remaining", &self.ids.len())
+            .field("batch_size", &self.batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, T> Batches<'a, T> {
+    pub(crate) fn new(db: &'a MiniDB, ids: Vec<String>, batch_size: usize) -> Self {
+        Self {
+            db,
+            ids: ids.into_iter(),
+            batch_size: batch_size.max(1),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn read_batch(&mut self) -> Result<Vec<T>>
+    where
+        T: Table,
+    {
+        let txn = self.db.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        let mut items = Vec::with_capacity(self.batch_size.min(self.ids.len()));
+        for id in self.ids.by_ref().take(self.batch_size) {
+            // removed since the IDs were listed
+            let Some(value) = table.get(id.as_str())? else {
+                continue;
+            };
+            items.push(self.db.codec.decode_record(&id, value.value())?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T> Iterator for Batches<'_, T>
+where
+    T: Table,
+{
+    type Item = Result<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ids.len() > 0 {
+            match self.read_batch() {
+                Ok(items) if items.is_empty() => {}
+                result => return Some(result),
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over a table's items in ID order, with optional decryption
 pub struct TableIterator<'a, T> {
     inner: Range<'a, &'static str, &'static [u8]>,
-    cipher: Option<&'a XChaCha20Poly1305>,
+    codec: Cow<'a, Codec>,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -80,7 +281,7 @@ impl<'a, T> TableIterator<'a, T> {
     pub fn new(inner: Range<'a, &'static str, &'static [u8]>) -> Self {
         Self {
             inner,
-            cipher: None,
+            codec: Cow::Owned(Codec::default()),
             _phantom: PhantomData,
         }
     }
@@ -96,14 +297,20 @@ impl<'a, T> TableIterator<'a, T> {
     /// The [`TableIterator`] with the cipher added
     #[must_use]
     pub fn with_cipher(mut self, cipher: &'a XChaCha20Poly1305) -> Self {
-        self.cipher = Some(cipher);
+        self.codec = Cow::Owned(Codec::new(Some(cipher.clone())));
+        self
+    }
+
+    /// Uses the database's [`Codec`] for decoding
+    pub(crate) fn with_codec(mut self, codec: &'a Codec) -> Self {
+        self.codec = Cow::Borrowed(codec);
         self
     }
 }
 
 impl<T> Iterator for TableIterator<'_, T>
 where
-    T: for<'de> Deserialize<'de>,
+    T: Table,
 {
     type Item = Result<T>;
 
@@ -111,21 +318,7 @@ where
         let result = self.inner.next()?;
 
         match result {
-            Ok((_key, value)) => {
-                let bytes = if let Some(cipher) = &self.cipher {
-                    match decrypt_bytes(cipher, value.value()) {
-                        Ok(d) => d,
-                        Err(e) => return Some(Err(e)),
-                    }
-                } else {
-                    value.value().to_vec()
-                };
-
-                match postcard::from_bytes(&bytes) {
-                    Ok(item) => Some(Ok(item)),
-                    Err(e) => Some(Err(Error::Serialization(e))),
-                }
-            }
+            Ok((key, value)) => Some(self.codec.decode_record(key.value(), value.value())),
             Err(e) => Some(Err(Error::Storage(e))),
         }
     }
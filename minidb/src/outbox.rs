@@ -0,0 +1,163 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{META_TABLE, MiniDB, codec::Codec, error::Result};
+use redb::{ReadableDatabase, ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+
+/// Holds the published events that weren't acknowledged yet, keyed by their sequence number
+pub(crate) const OUTBOX_TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("outbox");
+
+/// The meta key of the next sequence number, so numbers aren't reused once the outbox is drained
+const META_KEY_OUTBOX_SEQ: &str = "outbox_seq";
+
+/// The stored event, encoded like a setting so it's encrypted if the database is
+#[derive(Serialize, Deserialize)]
+struct StoredEvent {
+    topic: String,
+    payload: Vec<u8>,
+}
+
+/// An event published with [`Transaction::publish`](crate::Transaction::publish) and returned by [`Outbox::poll`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxEvent {
+    /// The sequence number of the event, increasing in publish order
+    pub seq: u64,
+
+    /// The topic the event was published to
+    pub topic: String,
+
+    /// The event serialized with postcard
+    pub payload: Vec<u8>,
+}
+
+impl OutboxEvent {
+    /// Deserializes the payload of the event
+    ///
+    /// ## Arguments
+    ///
+    /// * `E` - The type the event was published as
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the payload isn't an `E`
+    pub fn decode<E>(&self) -> Result<E>
+    where
+        E: for<'de> Deserialize<'de>,
+    {
+        Ok(postcard::from_bytes(&self.payload)?)
+    }
+}
+
+/// Appends an event to the outbox and returns its sequence number
+pub(crate) fn publish<E>(
+    txn: &WriteTransaction,
+    codec: &Codec,
+    topic: &str,
+    event: &E,
+) -> Result<u64>
+where
+    E: Serialize + ?Sized,
+{
+    let mut meta = txn.open_table(META_TABLE)?;
+    let seq: u64 = meta
+        .get(META_KEY_OUTBOX_SEQ)?
+        .map(|bytes| postcard::from_bytes(bytes.value()))
+        .transpose()?
+        .unwrap_or_default();
+    meta.insert(
+        META_KEY_OUTBOX_SEQ,
+        postcard::to_stdvec(&(seq + 1))?.as_slice(),
+    )?;
+
+    let stored = StoredEvent {
+        topic: topic.to_string(),
+        payload: postcard::to_stdvec(event)?,
+    };
+    let mut outbox = txn.open_table(OUTBOX_TABLE)?;
+    outbox.insert(seq, codec.encode(&stored)?.as_slice())?;
+
+    Ok(seq)
+}
+
+/// The consumer side of the outbox, returned by [`MiniDB::outbox`]
+///
+/// Events stay in the outbox until they're acknowledged, so a consumer that crashes before calling [`Outbox::ack`]
+/// gets them again from the next [`Outbox::poll`]
+#[derive(Debug)]
+pub struct Outbox<'db> {
+    pub(crate) db: &'db MiniDB,
+}
+
+impl Outbox<'_> {
+    /// Returns the oldest events that weren't acknowledged yet, without removing them
+    ///
+    /// ## Arguments
+    ///
+    /// * `limit` - The maximum number of events to return
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the events in publish order
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the decryption/deserialization of any event fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// for event in db.outbox().poll(100).unwrap() {
+    ///     queue.send(&event.topic, &event.payload)?;
+    ///     db.outbox().ack(event.seq).unwrap();
+    /// }
+    /// ```
+    pub fn poll(&self, limit: usize) -> Result<Vec<OutboxEvent>> {
+        let txn = self.db.db.begin_read()?;
+        let outbox = match txn.open_table(OUTBOX_TABLE) {
+            Ok(outbox) => outbox,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut events = Vec::new();
+        for entry in outbox.iter()?.take(limit) {
+            let (seq, bytes) = entry?;
+            let stored: StoredEvent = self.db.codec.decode(bytes.value())?;
+            events.push(OutboxEvent {
+                seq: seq.value(),
+                topic: stored.topic,
+                payload: stored.payload,
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// Removes an event once it was delivered
+    ///
+    /// ## Arguments
+    ///
+    /// * `seq` - The sequence number of the event
+    ///
+    /// ## Returns
+    ///
+    /// `Ok(true)` if the event was removed, `Ok(false)` if it was already acknowledged
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the commit fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.outbox().ack(event.seq).unwrap();
+    /// ```
+    pub fn ack(&self, seq: u64) -> Result<bool> {
+        self.db.transaction(|txn| {
+            let mut outbox = txn.txn.open_table(OUTBOX_TABLE)?;
+            Ok(outbox.remove(seq)?.is_some())
+        })
+    }
+}
@@ -0,0 +1,41 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{error::Result, record_meta::meta_key};
+use redb::{ReadTransaction, ReadableTable, TableDefinition, WriteTransaction};
+
+/// Holds the pinned records, keyed like [`RECORD_META_TABLE`](crate::record_meta::RECORD_META_TABLE)
+pub(crate) const PIN_TABLE: TableDefinition<&'static str, ()> = TableDefinition::new("pins");
+
+/// Pins a record
+pub(crate) fn pin(txn: &WriteTransaction, table: &str, id: &str) -> Result<()> {
+    let mut pins = txn.open_table(PIN_TABLE)?;
+    pins.insert(meta_key(table, id).as_str(), ())?;
+    Ok(())
+}
+
+/// Unpins a record, returns whether it was pinned
+pub(crate) fn unpin(txn: &WriteTransaction, table: &str, id: &str) -> Result<bool> {
+    let mut pins = txn.open_table(PIN_TABLE)?;
+    Ok(pins.remove(meta_key(table, id).as_str())?.is_some())
+}
+
+/// Checks if a record is pinned in an open pin table
+pub(crate) fn is_pinned_in<R>(pins: &R, table: &str, id: &str) -> Result<bool>
+where
+    R: ReadableTable<&'static str, ()>,
+{
+    Ok(pins.get(meta_key(table, id).as_str())?.is_some())
+}
+
+/// Checks if a record is pinned
+pub(crate) fn is_pinned(txn: &ReadTransaction, table: &str, id: &str) -> Result<bool> {
+    let pins = match txn.open_table(PIN_TABLE) {
+        Ok(pins) => pins,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    is_pinned_in(&pins, table, id)
+}
@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::time::{Duration, Instant};
+
+use crate::{MiniDB, error::Result, fulltext, index, model::Table};
+use redb::{Database, ReadableDatabase, TableDefinition};
+
+/// The table the records written by [`MiniDB::bench_profile`] go to, so the real tables, their indexes and the quota
+/// are never touched
+const PROFILE_TABLE: TableDefinition<&'static str, &[u8]> = TableDefinition::new("__profile");
+
+/// The average time spent in each step of a write and a read, measured by [`MiniDB::bench_profile`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BenchProfile {
+    /// How many records were written and read
+    pub iterations: usize,
+
+    /// Waiting for the write transaction, long if other writers hold it
+    pub lock: Duration,
+
+    /// Serializing, computing and encrypting the record
+    pub encode: Duration,
+
+    /// Inserting the record into the open transaction, including [`BenchProfile::encode`] and working out its index and
+    /// full-text entries, which aren't stored
+    pub write: Duration,
+
+    /// Committing the transaction, mostly the fsync of the database file
+    pub commit: Duration,
+
+    /// Reading the stored bytes of the record for the first time since it was written, its pages are usually still in
+    /// redb's cache so this isn't a read from disk
+    pub first_get: Duration,
+
+    /// Reading the stored bytes of the same record again, served from redb's page cache
+    pub warm_get: Duration,
+
+    /// Decrypting and deserializing the record
+    pub decode: Duration,
+}
+
+/// Adds up the time spent in each step, before averaging
+#[derive(Default)]
+struct Totals {
+    lock: Duration,
+    encode: Duration,
+    write: Duration,
+    commit: Duration,
+    first_get: Duration,
+    warm_get: Duration,
+    decode: Duration,
+}
+
+/// Drops the scratch table when profiling ends, even if it ends with an error
+struct Scratch<'a>(&'a Database);
+
+impl Drop for Scratch<'_> {
+    fn drop(&mut self) {
+        if let Ok(txn) = self.0.begin_write()
+            && txn.delete_table(PROFILE_TABLE).is_ok()
+        {
+            let _ = txn.commit();
+        }
+    }
+}
+
+/// Writes and reads `iterations` copies of `sample` in a scratch table, which is dropped at the end
+pub(crate) fn run<T>(db: &MiniDB, sample: &T, iterations: usize) -> Result<BenchProfile>
+where
+    T: Table,
+{
+    let mut totals = Totals::default();
+    let _scratch = Scratch(&db.db);
+
+    for i in 0..iterations {
+        let mut item: T = postcard::from_bytes(&postcard::to_stdvec(sample)?)?;
+        item.set_id(i.to_string());
+        item.compute();
+
+        let start = Instant::now();
+        db.codec.encode_record(&item)?;
+        totals.encode += start.elapsed();
+
+        let start = Instant::now();
+        let txn = db.db.begin_write()?;
+        totals.lock += start.elapsed();
+
+        let start = Instant::now();
+        {
+            let bytes = db.codec.encode_record(&item)?;
+            index::values_of(&item)?;
+            fulltext::terms_of(&item);
+            txn.open_table(PROFILE_TABLE)?
+                .insert(item.get_id(), bytes.as_slice())?;
+        }
+        totals.write += start.elapsed();
+
+        let start = Instant::now();
+        txn.commit()?;
+        totals.commit += start.elapsed();
+
+        let mut stored = Vec::new();
+        for warm in [false, true] {
+            let start = Instant::now();
+            let read_txn = db.db.begin_read()?;
+            let table = read_txn.open_table(PROFILE_TABLE)?;
+            if let Some(bytes) = table.get(item.get_id())? {
+                stored = bytes.value().to_vec();
+            }
+            let elapsed = start.elapsed();
+
+            if warm {
+                totals.warm_get += elapsed;
+            } else {
+                totals.first_get += elapsed;
+            }
+        }
+
+        let start = Instant::now();
+        let _: T = db.codec.decode_record(item.get_id(), &stored)?;
+        totals.decode += start.elapsed();
+    }
+
+    let average = |total: Duration| {
+        total
+            .checked_div(u32::try_from(iterations).unwrap_or(u32::MAX))
+            .unwrap_or_default()
+    };
+    Ok(BenchProfile {
+        iterations,
+        lock: average(totals.lock),
+        encode: average(totals.encode),
+        write: average(totals.write),
+        commit: average(totals.commit),
+        first_get: average(totals.first_get),
+        warm_get: average(totals.warm_get),
+        decode: average(totals.decode),
+    })
+}
@@ -0,0 +1,555 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{collections::BTreeMap, fmt::Debug, ops::Not};
+
+use crate::{MiniDB, error::Result, model::Table};
+use redb::{ReadableDatabase, ReadableTable};
+
+/// A typed reference to a field of a table model, generated by the derive macro as an associated constant
+/// (`Person::AGE` for `age`) when the struct is marked with `#[minidb(fields)]`
+///
+/// Comparing it with a value builds a [`Filter`] for [`Query::filter`], so a typo in the field or a value of the
+/// wrong type is a compile error instead of an empty result
+pub struct Field<T, V> {
+    name: &'static str,
+    get: fn(&T) -> &V,
+}
+
+impl<T, V> Field<T, V> {
+    /// Creates a new [`Field`], used by the derive macro
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the field
+    /// * `get` - Returns a reference to the field of an item
+    #[must_use]
+    pub const fn new(name: &'static str, get: fn(&T) -> &V) -> Self {
+        Self { name, get }
+    }
+
+    /// Returns the name of the field
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns a reference to the field of an item
+    pub fn get<'a>(&self, item: &'a T) -> &'a V {
+        (self.get)(item)
+    }
+}
+
+impl<T, V> Field<T, V>
+where
+    T: 'static,
+    V: 'static,
+{
+    /// Builds a [`Filter`] from a comparison of the field against a value
+    fn compare(self, value: V, keep: fn(&V, &V) -> bool) -> Filter<T> {
+        Filter::new(move |item| keep((self.get)(item), &value))
+    }
+}
+
+impl<T, V> Field<T, V>
+where
+    T: 'static,
+    V: PartialEq + 'static,
+{
+    /// Matches the items whose field equals `value`
+    #[must_use]
+    pub fn eq(self, value: V) -> Filter<T> {
+        self.compare(value, PartialEq::eq)
+    }
+
+    /// Matches the items whose field doesn't equal `value`
+    #[must_use]
+    pub fn ne(self, value: V) -> Filter<T> {
+        self.compare(value, PartialEq::ne)
+    }
+}
+
+impl<T, V> Field<T, V>
+where
+    T: 'static,
+    V: PartialOrd + 'static,
+{
+    /// Matches the items whose field is greater than `value`
+    #[must_use]
+    pub fn gt(self, value: V) -> Filter<T> {
+        self.compare(value, PartialOrd::gt)
+    }
+
+    /// Matches the items whose field is greater than or equal to `value`
+    #[must_use]
+    pub fn ge(self, value: V) -> Filter<T> {
+        self.compare(value, PartialOrd::ge)
+    }
+
+    /// Matches the items whose field is less than `value`
+    #[must_use]
+    pub fn lt(self, value: V) -> Filter<T> {
+        self.compare(value, PartialOrd::lt)
+    }
+
+    /// Matches the items whose field is less than or equal to `value`
+    #[must_use]
+    pub fn le(self, value: V) -> Filter<T> {
+        self.compare(value, PartialOrd::le)
+    }
+}
+
+impl<T, V> Clone for Field<T, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, V> Copy for Field<T, V> {}
+
+impl<T, V> Debug for Field<T, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Field").field(&self.name).finish()
+    }
+}
+
+/// A condition on the items of a table, built from a [`Field`] and combined with [`Filter::and`], [`Filter::or`] and
+/// `!`
+pub struct Filter<T> {
+    keep: Box<dyn Fn(&T) -> bool>,
+}
+
+impl<T> Filter<T>
+where
+    T: 'static,
+{
+    /// Creates a [`Filter`] from any predicate, for conditions a [`Field`] can't express
+    ///
+    /// ## Arguments
+    ///
+    /// * `keep` - Returns `true` for the items to keep
+    #[must_use]
+    pub fn new<F>(keep: F) -> Self
+    where
+        F: Fn(&T) -> bool + 'static,
+    {
+        Self {
+            keep: Box::new(keep),
+        }
+    }
+
+    /// Matches the items both filters match
+    #[must_use]
+    pub fn and(self, other: Self) -> Self {
+        Self::new(move |item| self.matches(item) && other.matches(item))
+    }
+
+    /// Matches the items either filter matches
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        Self::new(move |item| self.matches(item) || other.matches(item))
+    }
+}
+
+impl<T> Filter<T> {
+    /// Checks if an item matches the filter
+    pub fn matches(&self, item: &T) -> bool {
+        (self.keep)(item)
+    }
+}
+
+impl<T> Not for Filter<T>
+where
+    T: 'static,
+{
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self::new(move |item| !self.matches(item))
+    }
+}
+
+impl<T> Debug for Filter<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Filter").finish_non_exhaustive()
+    }
+}
+
+/// A scan of a table built with [`MiniDB::query`]
+///
+/// The table is scanned in ID order inside a single read transaction, records are decoded one at a time and the scan
+/// stops once the limit is reached
+#[derive(Debug)]
+pub struct Query<'db, T> {
+    db: &'db MiniDB,
+    filters: Vec<Filter<T>>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl<'db, T> Query<'db, T>
+where
+    T: Table,
+{
+    /// Creates a new [`Query`] over the whole table
+    pub(crate) fn new(db: &'db MiniDB) -> Self {
+        Self {
+            db,
+            filters: Vec::new(),
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    /// Only keeps the items that match `filter`, calling it again requires every filter to match
+    #[must_use]
+    pub fn filter(mut self, filter: Filter<T>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Skips the first `offset` matching items
+    #[must_use]
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns at most `limit` items
+    #[must_use]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Runs the query
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the vector of matching items, in ID order
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any scanned record fails
+    pub fn run(self) -> Result<Vec<T>> {
+        let mut results = Vec::new();
+        self.for_each_match(|item| results.push(item))?;
+        Ok(results)
+    }
+
+    /// Runs the query and keeps only some fields of every matching item, see [`MiniDB::select`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `project` - Takes an item and returns the selected fields
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the vector of selected fields, in ID order
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any scanned record fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let names: Vec<String> = db
+    ///     .query::<Person>()
+    ///     .filter(Person::AGE.ge(18))
+    ///     .select(|person| person.name)
+    ///     .unwrap();
+    /// ```
+    pub fn select<P, F>(self, mut project: F) -> Result<Vec<P>>
+    where
+        F: FnMut(T) -> P,
+    {
+        let mut results = Vec::new();
+        self.for_each_match(|item| results.push(project(item)))?;
+        Ok(results)
+    }
+
+    /// Runs the query and folds a value out of every matching item, without keeping the items
+    ///
+    /// ## Arguments
+    ///
+    /// * `f` - Returns the value to aggregate from an item
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the [`Aggregate`] of the values
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any scanned record fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let total = db
+    ///     .query::<Order>()
+    ///     .filter(Order::PAID.eq(true))
+    ///     .aggregate(|order| order.price)
+    ///     .unwrap()
+    ///     .sum();
+    /// ```
+    pub fn aggregate<V, F>(self, mut f: F) -> Result<Aggregate<V>>
+    where
+        V: AggregateValue,
+        F: FnMut(&T) -> V,
+    {
+        let mut aggregate = Aggregate::default();
+        self.for_each_match(|item| aggregate.push(f(&item)))?;
+        Ok(aggregate)
+    }
+
+    /// Splits the matching items into groups by a key, to count or aggregate each group
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - Returns the group of an item
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let orders_per_customer = db
+    ///     .query::<Order>()
+    ///     .group_by(|order| order.customer_id.clone())
+    ///     .count()
+    ///     .unwrap();
+    /// ```
+    #[must_use]
+    pub fn group_by<K, G>(self, key: G) -> GroupBy<'db, T, G>
+    where
+        K: Ord,
+        G: FnMut(&T) -> K,
+    {
+        GroupBy { query: self, key }
+    }
+
+    /// Scans the table and calls `f` with every matching item inside the offset and limit
+    fn for_each_match<F>(self, mut f: F) -> Result<()>
+    where
+        F: FnMut(T),
+    {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        if limit == 0 {
+            return Ok(());
+        }
+
+        let txn = self.db.db.begin_read()?;
+        let table = txn.open_table(T::TABLE)?;
+
+        let mut skipped = 0;
+        let mut taken = 0;
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let item: T = self.db.codec.decode_record(key.value(), value.value())?;
+            if !self.filters.iter().all(|filter| filter.matches(&item)) {
+                continue;
+            }
+
+            if skipped < self.offset {
+                skipped += 1;
+                continue;
+            }
+
+            f(item);
+            taken += 1;
+            if taken == limit {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A [`Query`] split into groups, built with [`Query::group_by`]
+///
+/// Groups are returned in a [`BTreeMap`] ordered by their key
+#[derive(Debug)]
+pub struct GroupBy<'db, T, G> {
+    query: Query<'db, T>,
+    key: G,
+}
+
+impl<T, K, G> GroupBy<'_, T, G>
+where
+    T: Table,
+    K: Ord,
+    G: FnMut(&T) -> K,
+{
+    /// Counts the items of each group
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the number of items per key
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any scanned record fails
+    pub fn count(mut self) -> Result<BTreeMap<K, usize>> {
+        let mut groups = BTreeMap::new();
+        self.query.for_each_match(|item| {
+            *groups.entry((self.key)(&item)).or_default() += 1;
+        })?;
+        Ok(groups)
+    }
+
+    /// Aggregates a value taken from the items of each group
+    ///
+    /// ## Arguments
+    ///
+    /// * `f` - Returns the value to aggregate from an item
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the [`Aggregate`] per key
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// of any scanned record fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let spent = db
+    ///     .query::<Order>()
+    ///     .group_by(|order| order.customer_id.clone())
+    ///     .aggregate(|order| order.price)
+    ///     .unwrap();
+    /// ```
+    pub fn aggregate<V, F>(mut self, mut f: F) -> Result<BTreeMap<K, Aggregate<V>>>
+    where
+        V: AggregateValue,
+        F: FnMut(&T) -> V,
+    {
+        let mut groups: BTreeMap<K, Aggregate<V>> = BTreeMap::new();
+        self.query.for_each_match(|item| {
+            groups.entry((self.key)(&item)).or_default().push(f(&item));
+        })?;
+        Ok(groups)
+    }
+}
+
+/// The count, sum and extrema of values taken from the items of a table, returned by [`Query::aggregate`] and
+/// [`MiniDB::aggregate`](crate::MiniDB::aggregate)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Aggregate<V> {
+    count: usize,
+    sum: V,
+    total: f64,
+    min: Option<V>,
+    max: Option<V>,
+}
+
+impl<V> Aggregate<V>
+where
+    V: AggregateValue,
+{
+    /// Adds a value
+    fn push(&mut self, value: V) {
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.total += value.to_f64();
+        if self.min.is_none_or(|min| value < min) {
+            self.min = Some(value);
+        }
+        if self.max.is_none_or(|max| value > max) {
+            self.max = Some(value);
+        }
+    }
+
+    /// Returns how many values were aggregated
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the sum of the values, the default of `V` if there were none
+    ///
+    /// Integer sums saturate at the bounds of `V`, aggregate into a wider type to get the exact sum of large values
+    #[must_use]
+    pub fn sum(&self) -> V {
+        self.sum
+    }
+
+    /// Returns the smallest value, [`None`] if there were none
+    #[must_use]
+    pub fn min(&self) -> Option<V> {
+        self.min
+    }
+
+    /// Returns the largest value, [`None`] if there were none
+    #[must_use]
+    pub fn max(&self) -> Option<V> {
+        self.max
+    }
+
+    /// Returns the average of the values, [`None`] if there were none
+    ///
+    /// The values are summed as [`f64`] for it, so it isn't affected by a saturated [`sum`](Self::sum)
+    #[must_use]
+    // counts only lose precision past 2^52 values
+    #[allow(clippy::cast_precision_loss)]
+    pub fn avg(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total / self.count as f64)
+    }
+}
+
+/// A value that can be aggregated with [`Query::aggregate`], implemented for the integer and floating point primitives
+pub trait AggregateValue: Copy + PartialOrd + Default {
+    /// Adds two values, integers saturate instead of overflowing
+    #[must_use]
+    fn saturating_add(self, other: Self) -> Self;
+
+    /// Converts the value to [`f64`], large integers lose precision
+    fn to_f64(self) -> f64;
+}
+
+/// Implements [`AggregateValue`] for integer primitives
+macro_rules! aggregate_int {
+    ($($ty:ty),*) => {$(
+        impl AggregateValue for $ty {
+            fn saturating_add(self, other: Self) -> Self {
+                <$ty>::saturating_add(self, other)
+            }
+
+            #[allow(clippy::cast_precision_loss, clippy::cast_lossless)]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    )*};
+}
+
+aggregate_int!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize
+);
+
+impl AggregateValue for f32 {
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl AggregateValue for f64 {
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
@@ -0,0 +1,53 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{Transaction, error::Result, model::Table};
+
+/// Removes a record of a table evicted by the quota, type erased so the policy can name tables
+pub(crate) type Evictor = fn(&Transaction, &str) -> Result<()>;
+
+/// Removes a record the same way a delete does, so its indexes, unique claims and full-text entries go with it
+pub(crate) fn evict<T>(txn: &Transaction, id: &str) -> Result<()>
+where
+    T: Table,
+{
+    let mut table = txn.txn.open_table(T::TABLE)?;
+    txn.remove_item::<T>(&mut table, id)?;
+    Ok(())
+}
+
+/// A size budget for the whole database
+///
+/// The used size is what redb reports as stored and metadata bytes, free pages that haven't been
+/// compacted away yet aren't counted. It's checked when a write transaction commits and by
+/// [`MiniDB::enforce_quota`](crate::MiniDB::enforce_quota)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quota {
+    /// The maximum amount of bytes the data can use
+    pub max_bytes: u64,
+
+    /// What to do when the budget is exceeded
+    pub policy: QuotaPolicy,
+}
+
+/// What to do when a write goes over the [`Quota`]
+///
+/// ## Variants
+///
+/// * `QuotaPolicy::Reject` - The write fails with [`Error::QuotaExceeded`](crate::Error::QuotaExceeded) and nothing is committed
+/// * `QuotaPolicy::Evict(Vec<String>)` - Records are removed from the listed tables, in order, until the data fits again.
+///   The least recently written records go first when [`MiniDBBuilder::record_timestamps`](crate::MiniDBBuilder::record_timestamps)
+///   is enabled, otherwise they go in key order, which is only their age with
+///   [`MiniDBBuilder::sortable_ids`](crate::MiniDBBuilder::sortable_ids). Records written by the transaction being
+///   committed, pinned records and frozen tables are left alone, and so are tables whose model wasn't registered with
+///   the builder. If it still doesn't fit the write is rejected
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Reject the write
+    #[default]
+    Reject,
+
+    /// Evict records from the named cache tables
+    Evict(Vec<String>),
+}
@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+use redb::{ReadTransaction, ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+
+/// Holds the timestamps of every record when [`MiniDBBuilder::record_timestamps`](crate::MiniDBBuilder::record_timestamps) is enabled
+pub(crate) const RECORD_META_TABLE: TableDefinition<&'static str, &[u8]> =
+    TableDefinition::new("record_meta");
+
+/// Information about a stored record, returned by [`MiniDB::record_meta`](crate::MiniDB::record_meta)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordMeta {
+    /// The size of the stored record in bytes, after serialization and encryption
+    pub size: usize,
+
+    /// When the record was first written, [`None`] if timestamps weren't tracked at the time
+    pub created: Option<SystemTime>,
+
+    /// When the record was last written, [`None`] if timestamps weren't tracked at the time
+    pub modified: Option<SystemTime>,
+}
+
+/// The stored timestamps, in milliseconds since the Unix epoch
+#[derive(Serialize, Deserialize)]
+struct Timestamps {
+    created: u64,
+    modified: u64,
+}
+
+/// Returns the key of a record in [`RECORD_META_TABLE`]
+pub(crate) fn meta_key(table: &str, id: &str) -> String {
+    format!("{table}\0{id}")
+}
+
+pub(crate) fn now_millis() -> u64 {
+    to_millis(SystemTime::now())
+}
+
+pub(crate) fn to_millis(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    u64::try_from(since_epoch.as_millis()).unwrap_or(u64::MAX)
+}
+
+pub(crate) fn from_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// Records that a record was written now, keeping its creation time if it already existed
+pub(crate) fn touch(txn: &WriteTransaction, table: &str, id: &str) -> Result<()> {
+    let mut meta = txn.open_table(RECORD_META_TABLE)?;
+    let key = meta_key(table, id);
+    let now = now_millis();
+
+    let created = match meta.get(key.as_str())? {
+        Some(bytes) => postcard::from_bytes::<Timestamps>(bytes.value())?.created,
+        None => now,
+    };
+
+    let bytes = postcard::to_stdvec(&Timestamps {
+        created,
+        modified: now,
+    })?;
+    meta.insert(key.as_str(), bytes.as_slice())?;
+    Ok(())
+}
+
+/// Removes the timestamps of a deleted record
+pub(crate) fn forget(txn: &WriteTransaction, table: &str, id: &str) -> Result<()> {
+    let mut meta = txn.open_table(RECORD_META_TABLE)?;
+    meta.remove(meta_key(table, id).as_str())?;
+    Ok(())
+}
+
+/// Sets the timestamps of a record, used when copying it from another database
+pub(crate) fn set(
+    txn: &WriteTransaction,
+    table: &str,
+    id: &str,
+    created: SystemTime,
+    modified: SystemTime,
+) -> Result<()> {
+    let mut meta = txn.open_table(RECORD_META_TABLE)?;
+    let bytes = postcard::to_stdvec(&Timestamps {
+        created: to_millis(created),
+        modified: to_millis(modified),
+    })?;
+    meta.insert(meta_key(table, id).as_str(), bytes.as_slice())?;
+    Ok(())
+}
+
+/// Reads the `(created, modified)` timestamps of a record from an open [`RECORD_META_TABLE`]
+pub(crate) fn timestamps<M>(
+    meta: &M,
+    table: &str,
+    id: &str,
+) -> Result<Option<(SystemTime, SystemTime)>>
+where
+    M: ReadableTable<&'static str, &'static [u8]>,
+{
+    if let Some(bytes) = meta.get(meta_key(table, id).as_str())? {
+        let timestamps: Timestamps = postcard::from_bytes(bytes.value())?;
+        Ok(Some((
+            from_millis(timestamps.created),
+            from_millis(timestamps.modified),
+        )))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads the timestamps of a record, if they were tracked
+pub(crate) fn read(
+    txn: &ReadTransaction,
+    table: &str,
+    id: &str,
+    size: usize,
+) -> Result<RecordMeta> {
+    let mut info = RecordMeta {
+        size,
+        created: None,
+        modified: None,
+    };
+
+    let meta = match txn.open_table(RECORD_META_TABLE) {
+        Ok(meta) => meta,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(info),
+        Err(e) => return Err(e.into()),
+    };
+
+    if let Some((created, modified)) = timestamps(&meta, table, id)? {
+        info.created = Some(created);
+        info.modified = Some(modified);
+    }
+
+    Ok(info)
+}
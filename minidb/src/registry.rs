@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt::Debug;
+
+use crate::{
+    builder::init_table,
+    error::Result,
+    index,
+    model::Table,
+    quota::{self, Evictor},
+};
+use redb::{TableHandle, WriteTransaction};
+
+/// A table registered at compile time by `#[derive(Table)]`
+///
+/// Every non-generic struct deriving [`Table`](crate::Table) submits one of these when the `registry` feature is enabled,
+/// so [`MiniDBBuilder::all_tables`](crate::MiniDBBuilder::all_tables) can register them without listing each one
+pub struct TableRegistration {
+    pub(crate) init: fn(&WriteTransaction) -> Result<()>,
+    pub(crate) name: fn() -> String,
+    pub(crate) evict: Evictor,
+    pub(crate) indexes: fn() -> Vec<String>,
+}
+
+impl Debug for TableRegistration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TableRegistration").finish_non_exhaustive()
+    }
+}
+
+impl TableRegistration {
+    /// Creates a new [`TableRegistration`] for the table model `T`
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    ///
+    /// ## Returns
+    ///
+    /// A new [`TableRegistration`]
+    #[must_use]
+    pub const fn new<T>() -> Self
+    where
+        T: Table + 'static,
+    {
+        Self {
+            init: init_table::<T>,
+            name: table_name::<T>,
+            evict: quota::evict::<T>,
+            indexes: index::names::<T>,
+        }
+    }
+}
+
+/// Returns the name of the table of `T`, which can't be read in a const fn
+fn table_name<T>() -> String
+where
+    T: Table,
+{
+    T::TABLE.name().to_string()
+}
+
+inventory::collect!(TableRegistration);
+
+/// Registers a table model in the global registry, used by the derive macro
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_table {
+    ($ty:ty) => {
+        $crate::__private::inventory::submit! {
+            $crate::TableRegistration::new::<$ty>()
+        }
+    };
+}
@@ -0,0 +1,136 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::{MiniDB, error::Result, model::Table};
+use redb::TableHandle;
+
+/// The ID the record of a singleton table is stored under
+pub const SINGLETON_ID: &str = "singleton";
+
+/// A table that holds at most one record, for config-like data, built with [`MiniDB::singleton`]
+///
+/// The record is always stored under [`SINGLETON_ID`], so there's no ID to generate or keep track of. The model has to
+/// set [`Table::SINGLETON`] (`#[minidb(singleton)]` with the derive macro), which makes every other write to the table
+/// under another ID fail with [`Error::NotSingleton`](crate::Error::NotSingleton)
+pub struct Singleton<'db, T> {
+    db: &'db MiniDB,
+    _phantom: PhantomData<T>,
+}
+
+impl<T> Debug for Singleton<'_, T>
+where
+    T: Table,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Singleton")
+            .field("table", &T::TABLE.name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'db, T> Singleton<'db, T>
+where
+    T: Table,
+{
+    /// Creates a new [`Singleton`]
+    pub(crate) fn new(db: &'db MiniDB) -> Self {
+        Self {
+            db,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Retrieves the record
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the record, [`None`] if it was never set
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let settings = db.singleton::<Settings>().get().unwrap();
+    /// ```
+    pub fn get(&self) -> Result<Option<T>> {
+        self.db.get(SINGLETON_ID)
+    }
+
+    /// Retrieves the record, or its default value if it was never set
+    ///
+    /// The default isn't stored, call [`Singleton::set`] to keep it
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the record
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let settings = db.singleton::<Settings>().get_or_default().unwrap();
+    /// ```
+    pub fn get_or_default(&self) -> Result<T>
+    where
+        T: Default,
+    {
+        Ok(self.get()?.unwrap_or_else(|| {
+            let mut item = T::default();
+            item.set_id(SINGLETON_ID.to_string());
+            item
+        }))
+    }
+
+    /// Stores the record, replacing the previous one
+    ///
+    /// ## Arguments
+    ///
+    /// * `item` - The record, its ID is replaced with [`SINGLETON_ID`]
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization
+    /// fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// settings.theme = "dark".to_string();
+    /// db.singleton::<Settings>().set(&mut settings).unwrap();
+    /// ```
+    pub fn set(&self, item: &mut T) -> Result<()> {
+        item.set_id(SINGLETON_ID.to_string());
+        self.db.update(item)
+    }
+
+    /// Removes the record
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the removed record, [`None`] if it was never set
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization
+    /// fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.singleton::<Settings>().clear().unwrap();
+    /// ```
+    pub fn clear(&self) -> Result<Option<T>> {
+        self.db.remove(SINGLETON_ID)
+    }
+}
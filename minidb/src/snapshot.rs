@@ -0,0 +1,112 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::Write;
+
+use crate::{MiniDB, error::Result, export, model::Table};
+use redb::{ReadTransaction, ReadableDatabase, ReadableTable, TableHandle};
+
+/// Writes every record of one table as ndjson lines, returning how many were written
+type TableWriter = fn(&MiniDB, &ReadTransaction, &mut dyn Write) -> Result<usize>;
+
+/// A consistent export of several tables for analytics tools, built with [`MiniDB::snapshot`]
+///
+/// Every table is read from the same read transaction, so the bundle reflects a single point in time even while other
+/// threads keep writing
+#[derive(Debug)]
+pub struct Snapshot<'db> {
+    db: &'db MiniDB,
+    tables: Vec<TableWriter>,
+}
+
+impl<'db> Snapshot<'db> {
+    /// Creates a new empty [`Snapshot`]
+    pub(crate) fn new(db: &'db MiniDB) -> Self {
+        Self {
+            db,
+            tables: Vec::new(),
+        }
+    }
+
+    /// Adds a table to the snapshot
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    #[must_use]
+    pub fn table<T>(mut self) -> Self
+    where
+        T: Table,
+    {
+        self.tables.push(write_table::<T>);
+        self
+    }
+
+    /// Writes the snapshot as newline-delimited JSON, one `{"table": ..., "record": ...}` object per line
+    ///
+    /// The format loads directly into pandas (`read_json(lines=True)`) and DuckDB (`read_json_auto`). Fields in
+    /// [`Table::REDACTED_FIELDS`] are replaced with `"[REDACTED]"`
+    ///
+    /// ## Arguments
+    ///
+    /// * `writer` - Where to write the bundle
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the number of records written
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if a table is not found, if the decryption/deserialization of any record fails, or if writing
+    /// fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// let file = std::fs::File::create("snapshot.ndjson").unwrap();
+    /// db.snapshot()
+    ///     .table::<Person>()
+    ///     .table::<Order>()
+    ///     .write_ndjson(std::io::BufWriter::new(file))
+    ///     .unwrap();
+    /// ```
+    pub fn write_ndjson<W>(self, mut writer: W) -> Result<usize>
+    where
+        W: Write,
+    {
+        let txn = self.db.db.begin_read()?;
+
+        let mut written = 0;
+        for write in &self.tables {
+            written += write(self.db, &txn, &mut writer)?;
+        }
+
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+/// Writes the records of the table model `T`
+fn write_table<T>(db: &MiniDB, txn: &ReadTransaction, writer: &mut dyn Write) -> Result<usize>
+where
+    T: Table,
+{
+    let table = txn.open_table(T::TABLE)?;
+
+    let mut written = 0;
+    for entry in table.iter()? {
+        let (key, value) = entry?;
+        let item: T = db.codec.decode_record(key.value(), value.value())?;
+
+        let line = serde_json::json!({
+            "table": T::TABLE.name(),
+            "record": export::to_redacted_json(&item)?,
+        });
+        serde_json::to_writer(&mut *writer, &line)?;
+        writer.write_all(b"\n")?;
+        written += 1;
+    }
+
+    Ok(written)
+}
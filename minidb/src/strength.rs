@@ -0,0 +1,94 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+/// Passwords that are weak no matter how they're estimated, also checked as substrings
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456", "password", "qwerty", "letmein", "welcome", "admin", "iloveyou", "monkey", "dragon",
+    "football", "baseball", "master", "sunshine", "princess", "abc123", "111111", "passw0rd",
+    "trustno1", "secret", "changeme",
+];
+
+/// Estimates how hard a password is to guess, zxcvbn-style
+///
+/// The estimate is a rough entropy count: every character adds bits based on the character classes used, characters
+/// that repeat or continue a sequence (`aaa`, `abc`, `321`) add almost nothing, and anything containing a very common
+/// password scores 0. It's meant to reject trivially weak passwords, not to replace a proper strength meter
+///
+/// ## Arguments
+///
+/// * `password` - The password to estimate
+///
+/// ## Returns
+///
+/// A score from 0 (trivial to guess) to 4 (very hard to guess)
+///
+/// ## Example
+///
+/// ```rust
+/// use minidb::password_strength;
+///
+/// assert_eq!(password_strength("password123"), 0);
+/// assert!(password_strength("correct horse battery staple") >= 3);
+/// ```
+#[must_use]
+pub fn password_strength(password: &str) -> u8 {
+    let lowercase = password.to_lowercase();
+    if COMMON_PASSWORDS
+        .iter()
+        .any(|common| lowercase.contains(common))
+    {
+        return 0;
+    }
+
+    let bits = entropy_bits(password);
+    match bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    }
+}
+
+/// Estimates the entropy of a password in bits
+fn entropy_bits(password: &str) -> f64 {
+    let mut pool = 0u32;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0.0;
+    }
+
+    let bits_per_char = f64::from(pool).log2();
+    let mut bits = 0.0;
+    let mut prev: Option<char> = None;
+
+    for c in password.chars() {
+        let predictable = prev.is_some_and(|p| {
+            let diff = i64::from(u32::from(c)) - i64::from(u32::from(p));
+            diff.abs() <= 1
+        });
+
+        bits += if predictable { 1.0 } else { bits_per_char };
+        prev = Some(c);
+    }
+
+    bits
+}
@@ -0,0 +1,183 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::time::SystemTime;
+
+use crate::{
+    MiniDB, Table, Transaction,
+    error::Result,
+    record_meta::{self, RECORD_META_TABLE},
+};
+use redb::{ReadableDatabase, ReadableTable, TableHandle};
+
+/// A record that differs between the local and the remote database, passed to the resolver of
+/// [`MiniDB::sync_from_with`]
+#[derive(Debug)]
+pub struct SyncConflict<'a, T> {
+    /// The local version of the record
+    pub local: &'a T,
+
+    /// The remote version of the record
+    pub remote: &'a T,
+
+    /// When the local version was last written, [`None`] if timestamps weren't tracked
+    pub local_modified: Option<SystemTime>,
+
+    /// When the remote version was last written, [`None`] if timestamps weren't tracked
+    pub remote_modified: Option<SystemTime>,
+}
+
+/// Which version of a conflicting record to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncResolution {
+    /// Keep the local version
+    Local,
+
+    /// Replace the local version with the remote one
+    Remote,
+}
+
+impl SyncResolution {
+    /// The default resolution, the most recently written version wins
+    ///
+    /// The local version is kept if the timestamps are equal or the remote one is unknown
+    #[must_use]
+    pub fn newest<T>(conflict: &SyncConflict<T>) -> Self {
+        match (conflict.local_modified, conflict.remote_modified) {
+            (Some(local), Some(remote)) if remote > local => Self::Remote,
+            (None, Some(_)) => Self::Remote,
+            _ => Self::Local,
+        }
+    }
+}
+
+/// The outcome of [`MiniDB::sync_from`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Records that only existed in the remote database and were copied
+    pub pulled: usize,
+
+    /// Local records that were replaced by their remote version
+    pub replaced: usize,
+
+    /// Local records that were kept over a different remote version
+    pub kept: usize,
+}
+
+/// Copies the records of a table from `remote` into the transaction, resolving differences with `resolve`
+pub(crate) fn pull<T, F>(txn: &Transaction, remote: &MiniDB, mut resolve: F) -> Result<SyncReport>
+where
+    T: Table,
+    F: FnMut(&SyncConflict<T>) -> SyncResolution,
+{
+    let mut report = SyncReport::default();
+    let name = T::TABLE.name();
+
+    let remote_txn = remote.db.begin_read()?;
+    let remote_table = match remote_txn.open_table(T::TABLE) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(report),
+        Err(e) => return Err(e.into()),
+    };
+    let remote_meta = match remote_txn.open_table(RECORD_META_TABLE) {
+        Ok(meta) => Some(meta),
+        Err(redb::TableError::TableDoesNotExist(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut table = txn.txn.open_table(T::TABLE)?;
+    for entry in remote_table.iter()? {
+        let (key, value) = entry?;
+        let id = key.value();
+        let remote_item: T = remote.codec.decode_record(id, value.value())?;
+        let remote_times = match &remote_meta {
+            Some(meta) => record_meta::timestamps(meta, name, id)?,
+            None => None,
+        };
+
+        let local_item: Option<T> = table
+            .get(id)?
+            .map(|bytes| txn.db.codec.decode_record(id, bytes.value()))
+            .transpose()?;
+
+        if let Some(local_item) = local_item {
+            if postcard::to_stdvec(&local_item)? == postcard::to_stdvec(&remote_item)? {
+                continue;
+            }
+
+            let local_times = if txn.db.record_timestamps {
+                let meta = txn.txn.open_table(RECORD_META_TABLE)?;
+                record_meta::timestamps(&meta, name, id)?
+            } else {
+                None
+            };
+
+            let conflict = SyncConflict {
+                local: &local_item,
+                remote: &remote_item,
+                local_modified: local_times.map(|(_, modified)| modified),
+                remote_modified: remote_times.map(|(_, modified)| modified),
+            };
+            if resolve(&conflict) == SyncResolution::Local {
+                report.kept += 1;
+                continue;
+            }
+
+            report.replaced += 1;
+        } else {
+            report.pulled += 1;
+        }
+
+        // records read back from a database had their computed fields recalculated when they were written
+        txn.write_item(&mut table, &remote_item)?;
+        if txn.db.record_timestamps
+            && let Some((created, modified)) = remote_times
+        {
+            record_meta::set(&txn.txn, name, id, created, modified)?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Copies a record from `replica` into the transaction, keeping its timestamps
+pub(crate) fn restore<T>(txn: &Transaction, replica: &MiniDB, id: &str) -> Result<Option<T>>
+where
+    T: Table,
+{
+    let name = T::TABLE.name();
+
+    let replica_txn = replica.db.begin_read()?;
+    let replica_table = match replica_txn.open_table(T::TABLE) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let Some(bytes) = replica_table.get(id)? else {
+        return Ok(None);
+    };
+    let item: T = replica.codec.decode_record(id, bytes.value())?;
+    let times = match replica_txn.open_table(RECORD_META_TABLE) {
+        Ok(meta) => record_meta::timestamps(&meta, name, id)?,
+        Err(redb::TableError::TableDoesNotExist(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut table = txn.txn.open_table(T::TABLE)?;
+    // a damaged local record has no index values to take out, so it's dropped before the replica's version goes in
+    let damaged = table
+        .get(id)?
+        .is_some_and(|bytes| txn.db.codec.decode_record::<T>(id, bytes.value()).is_err());
+    if damaged {
+        table.remove(id)?;
+    }
+    txn.write_item(&mut table, &item)?;
+    if txn.db.record_timestamps
+        && let Some((created, modified)) = times
+    {
+        record_meta::set(&txn.txn, name, id, created, modified)?;
+    }
+
+    Ok(Some(item))
+}
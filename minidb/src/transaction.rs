@@ -2,25 +2,46 @@
 // Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
 // with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::fmt::Debug;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    fmt::Debug,
+    sync::atomic::Ordering,
+};
 
 use crate::{
-    SETTINGS_TABLE,
-    encryption::{decrypt_bytes, encrypt_bytes},
+    META_KEY_FROZEN_PREFIX, META_TABLE, MiniDB, SETTINGS_TABLE, SINGLETON_ID,
+    cache_layer::CacheUpdate,
+    codec,
     error::{Error, Result},
+    fulltext, index, lease,
+    lock::WriteGuard,
     model::Table,
+    outbox, pin,
+    quota::QuotaPolicy,
+    record_meta, unique_group,
 };
-use chacha20poly1305::XChaCha20Poly1305;
-use redb::WriteTransaction;
+use redb::{ReadableTable, Table as RedbTable, TableDefinition, TableHandle, WriteTransaction};
 use serde::Serialize;
 
+type RecordTable<'txn> = RedbTable<'txn, &'static str, &'static [u8]>;
+
+/// How many IDs are generated for a record before giving up on collisions
+const MAX_ID_ATTEMPTS: u32 = 5;
+
 /// A write transaction.
 ///
 /// This struct allows grouping multiple database operations within a single, atomic transaction.
 /// It is created by calling [`MiniDB::transaction`](crate::MiniDB::transaction).
 pub struct Transaction<'a> {
     pub(crate) txn: WriteTransaction,
-    pub(crate) cipher: Option<&'a XChaCha20Poly1305>,
+    pub(crate) db: &'a MiniDB,
+    pub(crate) wrote: Cell<bool>,
+    cache_updates: RefCell<Vec<CacheUpdate>>,
+    // the `table\0id` of every record written, the quota never evicts them
+    written: RefCell<HashSet<String>>,
+    // dropped after `txn` so a panic is only recorded once the transaction was rolled back
+    _guard: WriteGuard,
 }
 
 impl Debug for Transaction<'_> {
@@ -29,7 +50,288 @@ impl Debug for Transaction<'_> {
     }
 }
 
-impl Transaction<'_> {
+impl<'a> Transaction<'a> {
+    /// Creates a new [`Transaction`] from a redb write transaction
+    pub(crate) fn new(txn: WriteTransaction, db: &'a MiniDB) -> Self {
+        Self {
+            txn,
+            db,
+            wrote: Cell::new(false),
+            cache_updates: RefCell::new(Vec::new()),
+            written: RefCell::new(HashSet::new()),
+            _guard: WriteGuard::new(db.path.as_deref()),
+        }
+    }
+
+    /// Commits the transaction, enforcing the quota if anything was written and updating the [`CacheLayer`](crate::CacheLayer) afterwards
+    pub(crate) fn commit(self) -> Result<()> {
+        if self.wrote.get() {
+            self.enforce_quota()?;
+        }
+
+        self.txn.commit()?;
+
+        if let Some(cache) = &self.db.cache {
+            for (table, id, bytes) in self.cache_updates.take() {
+                match bytes {
+                    Some(bytes) => cache.put(&self.db.cache_namespace, &table, &id, &bytes),
+                    None => cache.invalidate(&self.db.cache_namespace, &table, &id),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues a change for the [`CacheLayer`](crate::CacheLayer), applied only if the transaction commits
+    fn queue_cache_update(&self, table: &str, id: &str, bytes: Option<&[u8]>) {
+        if self.db.cache.is_some() {
+            self.cache_updates.borrow_mut().push((
+                table.to_string(),
+                id.to_string(),
+                bytes.map(<[u8]>::to_vec),
+            ));
+        }
+    }
+
+    /// Makes sure the stored data fits the quota, evicting records if the policy allows it
+    pub(crate) fn enforce_quota(&self) -> Result<()> {
+        let Some(quota) = &self.db.quota else {
+            return Ok(());
+        };
+
+        let mut used = self.used_bytes()?;
+        if used <= quota.max_bytes {
+            return Ok(());
+        }
+
+        if let QuotaPolicy::Evict(tables) = &quota.policy {
+            for name in tables {
+                let Some(evict) = self.db.evictors.get(name) else {
+                    continue;
+                };
+                if !self.has_table(name)? || self.is_frozen(name)? {
+                    continue;
+                }
+
+                let mut candidates = self.eviction_candidates(name)?.into_iter();
+                loop {
+                    // free roughly what's over the budget before measuring again since stats walk the whole tree
+                    let over = used - quota.max_bytes;
+                    let mut freed = 0;
+                    let mut evicted = 0;
+                    for (id, size) in candidates.by_ref() {
+                        evict(self, &id)?;
+                        freed += size;
+                        evicted += 1;
+                        if freed >= over {
+                            break;
+                        }
+                    }
+
+                    used = self.used_bytes()?;
+                    if used <= quota.max_bytes {
+                        return Ok(());
+                    }
+                    // only records that can't be evicted are left
+                    if evicted == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Err(Error::QuotaExceeded {
+            used,
+            max: quota.max_bytes,
+        })
+    }
+
+    /// Checks if a table exists, without creating it like opening it in a write transaction does
+    fn has_table(&self, name: &str) -> Result<bool> {
+        Ok(self.txn.list_tables()?.any(|table| table.name() == name))
+    }
+
+    /// Returns the IDs and sizes of the records of a table the quota may evict, in the order they should go
+    fn eviction_candidates(&self, name: &str) -> Result<Vec<(String, u64)>> {
+        let table = self
+            .txn
+            .open_table(TableDefinition::<&str, &[u8]>::new(name))?;
+        let pins = self.txn.open_table(pin::PIN_TABLE)?;
+        let written = self.written.borrow();
+
+        let mut candidates = Vec::new();
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let id = key.value();
+            if written.contains(&record_meta::meta_key(name, id))
+                || pin::is_pinned_in(&pins, name, id)?
+            {
+                continue;
+            }
+            candidates.push((id.to_string(), (id.len() + value.value().len()) as u64));
+        }
+
+        // records written before timestamps were tracked count as the oldest
+        if self.db.record_timestamps {
+            let meta = self.txn.open_table(record_meta::RECORD_META_TABLE)?;
+            let mut dated = Vec::with_capacity(candidates.len());
+            for (id, size) in candidates {
+                let modified =
+                    record_meta::timestamps(&meta, name, &id)?.map(|(_, modified)| modified);
+                dated.push((modified, id, size));
+            }
+            dated.sort_by_key(|(modified, ..)| *modified);
+            candidates = dated.into_iter().map(|(_, id, size)| (id, size)).collect();
+        }
+
+        Ok(candidates)
+    }
+
+    /// Returns how many bytes are used by stored data
+    fn used_bytes(&self) -> Result<u64> {
+        let stats = self.txn.stats()?;
+        Ok(stats.stored_bytes() + stats.metadata_bytes())
+    }
+
+    /// Checks if a table was frozen with [`MiniDB::freeze`]
+    fn is_frozen(&self, name: &str) -> Result<bool> {
+        let meta = self.txn.open_table(META_TABLE)?;
+        let key = format!("{META_KEY_FROZEN_PREFIX}{name}");
+        Ok(meta.get(key.as_str())?.is_some())
+    }
+
+    /// Fails with [`Error::TableFrozen`] if the table was frozen with [`MiniDB::freeze`]
+    fn ensure_writable<T>(&self) -> Result<()>
+    where
+        T: Table,
+    {
+        if self.is_frozen(T::TABLE.name())? {
+            return Err(Error::TableFrozen(T::TABLE.name().to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Encodes an item and writes it into an open table, its computed fields have to be recalculated already
+    pub(crate) fn write_item<T>(&self, table: &mut RecordTable<'_>, item: &T) -> Result<()>
+    where
+        T: Table,
+    {
+        self.ensure_writable::<T>()?;
+        if T::SINGLETON && item.get_id() != SINGLETON_ID {
+            return Err(Error::NotSingleton {
+                table: T::TABLE.name().to_string(),
+                id: item.get_id().to_string(),
+            });
+        }
+        let to_write = self.db.codec.encode_record(item)?;
+        let values = index::values_of(item)?;
+        if !T::UNIQUE.is_empty() {
+            index::check_unique::<T, _>(&self.txn, &self.db.codec, table, item.get_id(), &values)?;
+        }
+
+        let previous = table.insert(item.get_id(), to_write.as_slice())?;
+        if !T::INDEXES.is_empty() || !T::FULLTEXT.is_empty() {
+            let name = T::TABLE.name();
+            let old: Option<T> = previous
+                .map(|bytes| self.db.codec.decode_record(item.get_id(), bytes.value()))
+                .transpose()?;
+
+            if !T::INDEXES.is_empty() {
+                if let Some(old) = &old {
+                    let old_values = index::values_of(old)?;
+                    index::remove(&self.txn, &self.db.codec, name, item.get_id(), &old_values)?;
+                    unique_group::release(self, name, item.get_id(), &old_values)?;
+                }
+                index::add(&self.txn, &self.db.codec, name, item.get_id(), &values)?;
+                unique_group::claim(self, &*table, name, item.get_id(), &values)?;
+            }
+
+            if !T::FULLTEXT.is_empty() {
+                if let Some(old) = &old {
+                    let terms = fulltext::terms_of(old);
+                    fulltext::remove(&self.txn, &self.db.codec, name, item.get_id(), &terms)?;
+                }
+                let terms = fulltext::terms_of(item);
+                fulltext::add(&self.txn, &self.db.codec, name, item.get_id(), &terms)?;
+            }
+        }
+        if self.db.record_timestamps {
+            record_meta::touch(&self.txn, T::TABLE.name(), item.get_id())?;
+        }
+        self.queue_cache_update(T::TABLE.name(), item.get_id(), Some(&to_write));
+        self.written
+            .borrow_mut()
+            .insert(record_meta::meta_key(T::TABLE.name(), item.get_id()));
+
+        self.wrote.set(true);
+        Ok(())
+    }
+
+    /// Generates an ID that isn't taken in an open table, retrying a few times on collisions
+    fn generate_id<T>(&self, table: &RecordTable<'_>) -> Result<String>
+    where
+        T: Table,
+    {
+        let mut attempts = 1;
+        loop {
+            let id = self.db.new_id();
+            if table.get(id.as_str())?.is_none() {
+                return Ok(id);
+            }
+
+            if attempts == MAX_ID_ATTEMPTS {
+                return Err(Error::RecordExists {
+                    table: T::TABLE.name().to_string(),
+                    id,
+                });
+            }
+
+            attempts += 1;
+            self.db.id_retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes an item from an open table and decodes it
+    pub(crate) fn remove_item<T>(&self, table: &mut RecordTable<'_>, key: &str) -> Result<Option<T>>
+    where
+        T: Table,
+    {
+        self.ensure_writable::<T>()?;
+        if self.has_table(pin::PIN_TABLE.name())?
+            && pin::is_pinned_in(&self.txn.open_table(pin::PIN_TABLE)?, T::TABLE.name(), key)?
+        {
+            return Err(Error::RecordPinned {
+                table: T::TABLE.name().to_string(),
+                id: key.to_string(),
+            });
+        }
+        let maybe_bytes = table.remove(key)?;
+
+        if let Some(bytes) = maybe_bytes {
+            let item: T = self.db.codec.decode_record(key, bytes.value())?;
+            let values = index::values_of(&item)?;
+            index::remove(&self.txn, &self.db.codec, T::TABLE.name(), key, &values)?;
+            unique_group::release(self, T::TABLE.name(), key, &values)?;
+            if !T::FULLTEXT.is_empty() {
+                let terms = fulltext::terms_of(&item);
+                fulltext::remove(&self.txn, &self.db.codec, T::TABLE.name(), key, &terms)?;
+            }
+            // the record may have been written while timestamps were tracked, even if they aren't now
+            if self.has_table(record_meta::RECORD_META_TABLE.name())? {
+                record_meta::forget(&self.txn, T::TABLE.name(), key)?;
+            }
+            if self.has_table(lease::LEASE_TABLE.name())? {
+                lease::forget(&self.txn, T::TABLE.name(), key)?;
+            }
+            self.queue_cache_update(T::TABLE.name(), key, None);
+
+            Ok(Some(item))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Inserts an item into a table
     ///
     /// ## Arguments
@@ -53,22 +355,106 @@ impl Transaction<'_> {
     where
         T: Table,
     {
+        let mut table = self.txn.open_table(T::TABLE)?;
         if item.get_id().trim().is_empty() {
-            let id = cuid2::slug();
+            let id = self.generate_id::<T>(&table)?;
             item.set_id(id);
         }
+        item.compute();
+
+        self.write_item(&mut table, item)
+    }
+
+    /// Inserts an item into a table under an ID chosen by the caller, instead of a generated one
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `item` - The item to insert, its ID is replaced with `id`
+    /// * `id` - The ID to store the item under
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::EmptyID`] if the ID is empty, [`Error::RecordExists`] if it's already taken, or an error if the
+    /// table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.transaction(|txn| {
+    ///     txn.insert_with_id(&mut person, "employee-1042")?;
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn insert_with_id<T>(&self, item: &mut T, id: &str) -> Result<()>
+    where
+        T: Table,
+    {
+        if id.trim().is_empty() {
+            return Err(Error::EmptyID);
+        }
 
         let mut table = self.txn.open_table(T::TABLE)?;
-        let bytes = postcard::to_stdvec(item)?;
+        if table.get(id)?.is_some() {
+            return Err(Error::RecordExists {
+                table: T::TABLE.name().to_string(),
+                id: id.to_string(),
+            });
+        }
 
-        let to_write: Vec<u8> = if let Some(cipher) = self.cipher {
-            encrypt_bytes(cipher, &bytes)?
-        } else {
-            bytes
-        };
+        item.set_id(id.to_string());
+        item.compute();
+        self.write_item(&mut table, item)
+    }
 
-        table.insert(item.get_id(), to_write.as_slice())?;
-        Ok(())
+    /// Retrieves an item by ID, or inserts the one built by `make` under that ID if it doesn't exist
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `id` - The ID of the item
+    /// * `make` - Builds the item to insert, its ID is replaced with `id`
+    ///
+    /// ## Returns
+    ///
+    /// * `T` - The existing item or the inserted one
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::EmptyID`] if the ID is empty, or an error if the table is not found, if the table is not
+    /// initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.transaction(|txn| {
+    ///     let counter = txn.get_or_insert_with("visits", || Counter::default())?;
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn get_or_insert_with<T, F>(&self, id: &str, make: F) -> Result<T>
+    where
+        T: Table,
+        F: FnOnce() -> T,
+    {
+        if id.trim().is_empty() {
+            return Err(Error::EmptyID);
+        }
+
+        let mut table = self.txn.open_table(T::TABLE)?;
+        let existing: Option<T> = table
+            .get(id)?
+            .map(|bytes| self.db.codec.decode_record(id, bytes.value()))
+            .transpose()?;
+        if let Some(item) = existing {
+            return Ok(item);
+        }
+
+        let mut item = make();
+        item.set_id(id.to_string());
+        item.compute();
+        self.write_item(&mut table, &item)?;
+        Ok(item)
     }
 
     /// Inserts multiple items into a table
@@ -97,19 +483,12 @@ impl Transaction<'_> {
         let mut table = self.txn.open_table(T::TABLE)?;
         for item in items {
             if item.get_id().trim().is_empty() {
-                let id = cuid2::slug();
+                let id = self.generate_id::<T>(&table)?;
                 item.set_id(id);
             }
+            item.compute();
 
-            let bytes = postcard::to_stdvec(&item)?;
-
-            let to_write: Vec<u8> = if let Some(cipher) = self.cipher {
-                encrypt_bytes(cipher, &bytes)?
-            } else {
-                bytes
-            };
-
-            table.insert(item.get_id(), to_write.as_slice())?;
+            self.write_item(&mut table, item)?;
         }
         Ok(())
     }
@@ -142,16 +521,8 @@ impl Transaction<'_> {
         }
 
         let mut table = self.txn.open_table(T::TABLE)?;
-        let bytes = postcard::to_stdvec(&item)?;
-
-        let to_write: Vec<u8> = if let Some(cipher) = self.cipher {
-            encrypt_bytes(cipher, &bytes)?
-        } else {
-            bytes
-        };
-
-        table.insert(item.get_id(), to_write.as_slice())?;
-        Ok(())
+        let computed = codec::computed(item)?;
+        self.write_item(&mut table, computed.as_ref().unwrap_or(item))
     }
 
     /// Updates multiple items in the table
@@ -182,15 +553,9 @@ impl Transaction<'_> {
             if item.get_id().trim().is_empty() {
                 return Err(Error::EmptyID);
             }
-            let bytes = postcard::to_stdvec(item)?;
-
-            let to_write: Vec<u8> = if let Some(cipher) = self.cipher {
-                encrypt_bytes(cipher, &bytes)?
-            } else {
-                bytes
-            };
 
-            table.insert(item.get_id(), to_write.as_slice())?;
+            let computed = codec::computed(item)?;
+            self.write_item(&mut table, computed.as_ref().unwrap_or(item))?;
         }
         Ok(())
     }
@@ -224,20 +589,7 @@ impl Transaction<'_> {
         T: Table,
     {
         let mut table = self.txn.open_table(T::TABLE)?;
-        let maybe_bytes = table.remove(key)?;
-
-        if let Some(bytes) = maybe_bytes {
-            let item: T = if let Some(cipher) = self.cipher {
-                let decrypted = decrypt_bytes(cipher, bytes.value())?;
-                postcard::from_bytes(&decrypted)?
-            } else {
-                postcard::from_bytes(bytes.value())?
-            };
-
-            Ok(Some(item))
-        } else {
-            Ok(None)
-        }
+        self.remove_item(&mut table, key)
     }
 
     /// Removes multiple items from the table
@@ -270,22 +622,110 @@ impl Transaction<'_> {
         let mut result = Vec::new();
         let mut table = self.txn.open_table(T::TABLE)?;
         for key in keys {
-            let maybe_bytes = table.remove(key)?;
-
-            if let Some(bytes) = maybe_bytes {
-                let item: T = if let Some(cipher) = &self.cipher {
-                    let decrypted = decrypt_bytes(cipher, bytes.value())?;
-                    postcard::from_bytes(&decrypted)?
-                } else {
-                    postcard::from_bytes(bytes.value())?
-                };
-
+            if let Some(item) = self.remove_item(&mut table, key)? {
                 result.push(item);
             }
         }
         Ok(result)
     }
 
+    /// Removes every item of a table that matches a predicate
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `predicate` - Returns `true` for the items to remove
+    ///
+    /// ## Returns
+    ///
+    /// * `usize` - The number of items that were removed
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the decryption/deserialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.transaction(|txn| {
+    ///     txn.delete_where::<Session, _>(|session| session.expired())?;
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn delete_where<T, F>(&self, mut predicate: F) -> Result<usize>
+    where
+        T: Table,
+        F: FnMut(&T) -> bool,
+    {
+        let mut table = self.txn.open_table(T::TABLE)?;
+
+        let mut matching = Vec::new();
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let item: T = self.db.codec.decode_record(key.value(), value.value())?;
+            if predicate(&item) {
+                matching.push(key.value().to_string());
+            }
+        }
+
+        for key in &matching {
+            self.remove_item::<T>(&mut table, key)?;
+        }
+        Ok(matching.len())
+    }
+
+    /// Applies a mutation to every item of a table that matches a filter and writes them back
+    ///
+    /// The ID of the items can't be changed, it's restored after the mutation
+    ///
+    /// ## Arguments
+    ///
+    /// * `T` - The table model
+    /// * `filter` - Returns `true` for the items to update
+    /// * `mutator` - Changes a matching item
+    ///
+    /// ## Returns
+    ///
+    /// * `usize` - The number of items that were updated
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the table is not found, if the table is not initialized, or if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.transaction(|txn| {
+    ///     txn.update_where::<Person, _, _>(|person| person.age < 18, |person| person.minor = true)?;
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn update_where<T, F, M>(&self, mut filter: F, mut mutator: M) -> Result<usize>
+    where
+        T: Table,
+        F: FnMut(&T) -> bool,
+        M: FnMut(&mut T),
+    {
+        let mut table = self.txn.open_table(T::TABLE)?;
+
+        let mut matching = Vec::new();
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let item: T = self.db.codec.decode_record(key.value(), value.value())?;
+            if filter(&item) {
+                matching.push((key.value().to_string(), item));
+            }
+        }
+
+        for (key, item) in &mut matching {
+            mutator(item);
+            item.set_id(key.clone());
+            item.compute();
+            self.write_item(&mut table, item)?;
+        }
+        Ok(matching.len())
+    }
+
     /// Sets an item in the settings table
     ///
     /// ## Arguments
@@ -311,15 +751,43 @@ impl Transaction<'_> {
         T: Serialize,
     {
         let mut table = self.txn.open_table(SETTINGS_TABLE)?;
-        let bytes = postcard::to_stdvec(value)?;
-
-        let to_write: Vec<u8> = if let Some(cipher) = self.cipher {
-            encrypt_bytes(cipher, &bytes)?
-        } else {
-            bytes
-        };
+        let to_write = self.db.codec.encode(value)?;
 
         table.insert(key, to_write.as_slice())?;
+        self.wrote.set(true);
         Ok(())
     }
+
+    /// Appends an event to the outbox, it's only visible to [`MiniDB::outbox`] if the transaction commits
+    ///
+    /// ## Arguments
+    ///
+    /// * `topic` - The topic to publish the event to, e.g. the queue it's meant for
+    /// * `event` - The event to publish
+    ///
+    /// ## Returns
+    ///
+    /// A [`Result`] containing the sequence number of the event
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the encryption/serialization fails
+    ///
+    /// ## Example
+    ///
+    /// ```rust,ignore
+    /// db.transaction(|txn| {
+    ///     txn.insert(&mut order)?;
+    ///     txn.publish("orders", &OrderPlaced { id: order.id.clone() })?;
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn publish<E>(&self, topic: &str, event: &E) -> Result<u64>
+    where
+        E: Serialize + ?Sized,
+    {
+        let seq = outbox::publish(&self.txn, &self.db.codec, topic, event)?;
+        self.wrote.set(true);
+        Ok(seq)
+    }
 }
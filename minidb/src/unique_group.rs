@@ -0,0 +1,252 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    META_TABLE, Transaction,
+    error::{Error, Result},
+    index::{self, IndexValues},
+    model::Table,
+    record_meta::meta_key,
+};
+use redb::{ReadableTable, TableDefinition, TableHandle, WriteTransaction};
+
+/// Maps `group\0value` to the `table\0id` of the record that holds the value, values are hidden behind the index key in
+/// encrypted databases
+pub(crate) const UNIQUE_GROUP_TABLE: TableDefinition<&[u8], &str> =
+    TableDefinition::new("unique_groups");
+
+/// The prefix of the meta keys marking the members whose existing records were claimed
+const BACKFILL_PREFIX: &str = "unique_group:";
+
+/// Claims the values of the records written before the member was registered
+type Backfill = fn(&Transaction, &UniqueMember) -> Result<()>;
+
+/// Decodes a stored record of a member's table and returns the current value of its field
+type CurrentValue = fn(&Transaction, &UniqueMember, &str, &[u8]) -> Result<Option<Vec<u8>>>;
+
+/// A field that shares a uniqueness constraint with fields of other tables, registered with
+/// [`MiniDBBuilder::unique_across`](crate::MiniDBBuilder::unique_across)
+pub(crate) struct UniqueMember {
+    pub(crate) group: String,
+    pub(crate) table: String,
+    pub(crate) field: String,
+    pub(crate) indexes: &'static [&'static str],
+    pub(crate) backfill: Backfill,
+    current_value: CurrentValue,
+}
+
+impl UniqueMember {
+    /// Creates the member for the field of the table model `T`
+    pub(crate) fn new<T>(group: &str, field: &str) -> Self
+    where
+        T: Table,
+    {
+        Self {
+            group: group.to_string(),
+            table: T::TABLE.name().to_string(),
+            field: field.to_string(),
+            indexes: T::INDEXES,
+            backfill: backfill::<T>,
+            current_value: current_value::<T>,
+        }
+    }
+
+    /// Returns the meta key marking that the existing records of the member were claimed
+    fn marker(&self) -> String {
+        format!(
+            "{BACKFILL_PREFIX}{}\0{}\0{}",
+            self.group, self.table, self.field
+        )
+    }
+
+    /// Returns the key of a value in [`UNIQUE_GROUP_TABLE`]
+    fn key(&self, txn: &Transaction, value: &[u8]) -> Vec<u8> {
+        let mut key = format!("{}\0", self.group).into_bytes();
+        key.extend(txn.db.codec.index_token(value));
+        key
+    }
+}
+
+/// Returns the value of a member's field in a stored record
+fn current_value<T>(
+    txn: &Transaction,
+    member: &UniqueMember,
+    id: &str,
+    bytes: &[u8],
+) -> Result<Option<Vec<u8>>>
+where
+    T: Table,
+{
+    let item: T = txn.db.codec.decode_record(id, bytes)?;
+    Ok(index::values_of(&item)?
+        .into_iter()
+        .find(|(field, _)| *field == member.field)
+        .map(|(_, value)| value))
+}
+
+/// Checks if the record holding a claim still exists and still has the claimed value
+///
+/// Claims are only released by writes made while the group is registered, so a record changed in between keeps a
+/// claim it no longer backs. `open` is the table being written, which can't be opened a second time in the same
+/// transaction
+fn is_live<R>(
+    txn: &Transaction,
+    open: (&str, &R),
+    group: &str,
+    owner: &str,
+    value: &[u8],
+) -> Result<bool>
+where
+    R: ReadableTable<&'static str, &'static [u8]>,
+{
+    let Some((table, id)) = owner.split_once('\0') else {
+        return Ok(false);
+    };
+    // a table that left the group can't hold its values anymore
+    let Some(member) = txn
+        .db
+        .unique_groups
+        .iter()
+        .find(|m| m.group == group && m.table == table)
+    else {
+        return Ok(false);
+    };
+
+    let bytes = if table == open.0 {
+        open.1.get(id)?.map(|bytes| bytes.value().to_vec())
+    } else {
+        match txn
+            .txn
+            .open_table(TableDefinition::<&str, &[u8]>::new(table))
+        {
+            Ok(records) => records.get(id)?.map(|bytes| bytes.value().to_vec()),
+            Err(redb::TableError::TableDoesNotExist(_)) => None,
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    match bytes {
+        Some(bytes) => Ok((member.current_value)(txn, member, id, &bytes)?
+            .is_some_and(|current| current == value)),
+        None => Ok(false),
+    }
+}
+
+/// Forgets which members had their existing records claimed, except for the ones registered now
+///
+/// Records written while a member isn't registered don't claim their values, so it has to be backfilled again the next
+/// time it is
+pub(crate) fn forget_unregistered(txn: &WriteTransaction, members: &[UniqueMember]) -> Result<()> {
+    let registered: Vec<_> = members.iter().map(UniqueMember::marker).collect();
+    let mut meta = txn.open_table(META_TABLE)?;
+    meta.retain_in::<&str, _>(BACKFILL_PREFIX.., |key, _| {
+        !key.starts_with(BACKFILL_PREFIX) || registered.iter().any(|marker| marker == key)
+    })?;
+    Ok(())
+}
+
+/// Claims the values of a record in every group its table is part of
+///
+/// ## Errors
+///
+/// Returns [`Error::UniqueViolation`] naming the other record if a value is already claimed
+pub(crate) fn claim<R>(
+    txn: &Transaction,
+    records: &R,
+    table: &str,
+    id: &str,
+    values: &IndexValues,
+) -> Result<()>
+where
+    R: ReadableTable<&'static str, &'static [u8]>,
+{
+    let owner = meta_key(table, id);
+    for member in txn.db.unique_groups.iter().filter(|m| m.table == table) {
+        let Some((_, value)) = values.iter().find(|(field, _)| *field == member.field) else {
+            continue;
+        };
+        let key = member.key(txn, value);
+
+        let current = txn
+            .txn
+            .open_table(UNIQUE_GROUP_TABLE)?
+            .get(key.as_slice())?
+            .map(|holder| holder.value().to_string());
+        if let Some(current) = current
+            && current != owner
+            && is_live(txn, (table, records), &member.group, &current, value)?
+        {
+            let (other_table, other_id) = current.split_once('\0').unwrap_or_default();
+            return Err(Error::UniqueViolation {
+                table: other_table.to_string(),
+                field: member.field.clone(),
+                id: other_id.to_string(),
+            });
+        }
+
+        txn.txn
+            .open_table(UNIQUE_GROUP_TABLE)?
+            .insert(key.as_slice(), owner.as_str())?;
+    }
+
+    Ok(())
+}
+
+/// Releases the values of a record in every group its table is part of, values claimed by other records are left alone
+pub(crate) fn release(
+    txn: &Transaction,
+    table: &str,
+    id: &str,
+    values: &IndexValues,
+) -> Result<()> {
+    let owner = meta_key(table, id);
+    for member in txn.db.unique_groups.iter().filter(|m| m.table == table) {
+        let Some((_, value)) = values.iter().find(|(field, _)| *field == member.field) else {
+            continue;
+        };
+        let key = member.key(txn, value);
+
+        let mut claims = txn.txn.open_table(UNIQUE_GROUP_TABLE)?;
+        let held = claims
+            .get(key.as_slice())?
+            .is_some_and(|holder| holder.value() == owner);
+        if held {
+            claims.remove(key.as_slice())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Claims the values of every record of a member's table the first time the member is registered
+fn backfill<T>(txn: &Transaction, member: &UniqueMember) -> Result<()>
+where
+    T: Table,
+{
+    let marker = member.marker();
+    if txn
+        .txn
+        .open_table(META_TABLE)?
+        .get(marker.as_str())?
+        .is_some()
+    {
+        return Ok(());
+    }
+
+    let records = txn.txn.open_table(T::TABLE)?;
+    let mut pending = Vec::new();
+    for entry in records.iter()? {
+        let (key, value) = entry?;
+        let item: T = txn.db.codec.decode_record(key.value(), value.value())?;
+        pending.push((key.value().to_string(), index::values_of(&item)?));
+    }
+    for (id, values) in &pending {
+        claim(txn, &records, &member.table, id, values)?;
+    }
+
+    txn.txn
+        .open_table(META_TABLE)?
+        .insert(marker.as_str(), [].as_slice())?;
+    Ok(())
+}
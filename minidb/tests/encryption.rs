@@ -2,12 +2,12 @@
 // Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
 // with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Arc};
 
 use anyhow::Result;
-use minidb::{KeySource, MiniDB, Table};
+use minidb::{ArgonParams, ArgonPresets, KeySource, MemoryKeyCache, MiniDB, Table};
 use rand::seq::IndexedRandom;
-use redb::TableDefinition;
+use redb::{ReadableTable, TableDefinition};
 use serde::{Deserialize, Serialize};
 use tempfile::NamedTempFile;
 
@@ -77,6 +77,37 @@ fn test_minidb_with_encryption_insert() {
     assert_eq!(all_restaurants.len(), 1);
 }
 
+#[test]
+fn test_minidb_with_encryption_all_parallel() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .key_source(KeySource::PreDerived(KEY))
+        .build()
+        .expect("failed to build store");
+
+    assert!(
+        db.all_parallel::<Restaurant>(4)
+            .expect("failed to get all restaurants")
+            .is_empty()
+    );
+
+    let mut restaurants: Vec<Restaurant> =
+        (0..101).map(|_| Restaurant { id: String::new() }).collect();
+    db.insert_many(&mut restaurants)
+        .expect("failed to insert restaurants");
+
+    let serial = db
+        .all::<Restaurant>()
+        .expect("failed to get all restaurants");
+    for threads in [0, 1, 4, 200] {
+        let parallel = db
+            .all_parallel::<Restaurant>(threads)
+            .expect("failed to get all restaurants");
+        assert!(parallel == serial, "{threads} threads changed the result");
+    }
+}
+
 #[test]
 fn test_minidb_with_encryption_insert_many() {
     const N: usize = 1000;
@@ -471,3 +502,318 @@ fn test_minidb_with_encryption_view_all() {
 
     assert_eq!(first_five.len(), 5);
 }
+
+#[test]
+fn test_minidb_with_encryption_password_preset() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let build = || {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::Password("secretpassword".to_string()))
+            .argon_params(ArgonParams::fast_insecure_for_tests())
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build();
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    drop(db);
+
+    let db = build();
+    let restaurant: Restaurant = db
+        .get(&r.id)
+        .expect("failed to get restaurant")
+        .expect("restaurant was not inserted");
+    assert_eq!(restaurant.id, r.id);
+}
+
+#[test]
+fn test_minidb_with_encryption_key_cache() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let cache = Arc::new(MemoryKeyCache::default());
+    let build = |password: &str| {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::Password(password.to_string()))
+            .argon_params(ArgonParams::fast_insecure_for_tests())
+            .key_cache(cache.clone())
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build("secretpassword");
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    drop(db);
+
+    // the cached key is only used for the password it was derived from
+    let db = build("not the password");
+    assert!(db.get::<Restaurant>(&r.id).is_err());
+    drop(db);
+
+    let db = build("secretpassword");
+    assert!(
+        db.get::<Restaurant>(&r.id)
+            .expect("failed to get")
+            .is_some()
+    );
+    drop(db);
+
+    cache.clear();
+    let db = build("not the password");
+    assert!(db.get::<Restaurant>(&r.id).is_err());
+}
+
+#[test]
+fn test_minidb_with_encryption_key_file() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let key_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let key_path = key_dir.path().join("minidb.key");
+    let build = || {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_file(&key_path)
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build();
+    let key = std::fs::read(&key_path).expect("key file was not created");
+    assert_eq!(key.len(), 32);
+
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    drop(db);
+
+    let db = build();
+    assert!(
+        db.get::<Restaurant>(&r.id)
+            .expect("failed to get")
+            .is_some()
+    );
+    assert_eq!(std::fs::read(&key_path).expect("failed to read key"), key);
+    drop(db);
+
+    // a new key wouldn't decrypt the existing records
+    std::fs::remove_file(&key_path).expect("failed to remove key file");
+    assert!(matches!(
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_file(&key_path)
+            .build(),
+        Err(minidb::Error::MissingKeyFile(path)) if path == key_path
+    ));
+    assert!(!key_path.exists());
+}
+
+#[test]
+fn test_minidb_with_encryption_bind_records() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let build = || {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::PreDerived(KEY))
+            .bind_records(true)
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build();
+    let mut r1 = Restaurant { id: String::new() };
+    let mut r2 = Restaurant { id: String::new() };
+    db.insert(&mut r1).expect("failed to insert restaurant");
+    db.insert(&mut r2).expect("failed to insert restaurant");
+    assert!(
+        db.get::<Restaurant>(&r1.id)
+            .expect("failed to get")
+            .is_some()
+    );
+    drop(db);
+
+    // copy the ciphertext of r1 over r2 behind MiniDB's back
+    let raw = redb::Database::open(temp_file.path()).expect("failed to open raw database");
+    let txn = raw.begin_write().expect("failed to begin write");
+    {
+        let mut table = txn
+            .open_table(Restaurant::TABLE)
+            .expect("failed to open table");
+        let bytes = table
+            .get(r1.id.as_str())
+            .expect("failed to get raw record")
+            .expect("raw record is missing")
+            .value()
+            .to_vec();
+        table
+            .insert(r2.id.as_str(), bytes.as_slice())
+            .expect("failed to overwrite raw record");
+    }
+    txn.commit().expect("failed to commit");
+    drop(raw);
+
+    let db = build();
+    assert!(
+        db.get::<Restaurant>(&r1.id)
+            .expect("failed to get")
+            .is_some()
+    );
+    assert!(matches!(
+        db.get::<Restaurant>(&r2.id),
+        Err(minidb::Error::Crypto(_))
+    ));
+    drop(db);
+
+    // reading bound records without the binding would fail on every record
+    assert!(matches!(
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::PreDerived(KEY))
+            .build(),
+        Err(minidb::Error::OptionMismatch { option, stored: true }) if option == "bind_records"
+    ));
+}
+
+#[test]
+fn test_minidb_with_encryption_crypto_erase() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let key_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let key_path = key_dir.path().join("minidb.key");
+    let build_with_password = || {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::Password("secretpassword".to_string()))
+            .argon_params(ArgonParams::fast_insecure_for_tests())
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build_with_password();
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    db.crypto_erase().expect("failed to crypto erase");
+
+    let db = build_with_password();
+    assert!(db.get::<Restaurant>(&r.id).is_err());
+    drop(db);
+
+    // key files are only created for new databases
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .key_file(&key_path)
+        .build()
+        .expect("failed to build store");
+    assert!(key_path.exists());
+    db.crypto_erase().expect("failed to crypto erase");
+    assert!(!key_path.exists());
+}
+
+#[test]
+fn test_minidb_with_encryption_crypto_erase_unencrypted() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let build = || {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build();
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    assert!(matches!(
+        db.crypto_erase(),
+        Err(minidb::Error::NotEncrypted)
+    ));
+
+    let db = build();
+    assert!(
+        db.get::<Restaurant>(&r.id)
+            .expect("failed to get restaurant")
+            .is_some()
+    );
+}
+
+#[cfg(feature = "secrecy")]
+#[test]
+fn test_minidb_with_encryption_secret_password() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let build = || {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::SecretPassword(
+                "secretpassword".to_string().into(),
+            ))
+            .argon_params(ArgonParams::fast_insecure_for_tests())
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build();
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    drop(db);
+
+    let db = build();
+    assert!(
+        db.get::<Restaurant>(&r.id)
+            .expect("failed to get")
+            .is_some()
+    );
+}
+
+#[test]
+fn test_minidb_with_encryption_pepper() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    // the cached key of one pepper must not be returned for another
+    let cache = Arc::new(MemoryKeyCache::default());
+    let build = |pepper: &str| {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::Password("secretpassword".to_string()))
+            .argon_params(ArgonParams::fast_insecure_for_tests())
+            .key_cache(cache.clone())
+            .pepper(pepper)
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build("pepper");
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    drop(db);
+
+    let db = build("pepper");
+    assert!(
+        db.get::<Restaurant>(&r.id)
+            .expect("failed to get")
+            .is_some()
+    );
+    drop(db);
+
+    let db = build("other pepper");
+    assert!(db.get::<Restaurant>(&r.id).is_err());
+}
+
+#[cfg(feature = "password-strength")]
+#[test]
+fn test_minidb_with_encryption_weak_password() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let build = |password: &str| {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .key_source(KeySource::Password(password.to_string()))
+            .argon_params(ArgonParams::fast_insecure_for_tests())
+            .min_password_strength(3)
+            .build()
+    };
+
+    assert!(matches!(
+        build("password123"),
+        Err(minidb::Error::WeakPassword { score: 0, min: 3 })
+    ));
+    assert!(matches!(
+        build("aaaaaaaaaaaaaaaa"),
+        Err(minidb::Error::WeakPassword { .. })
+    ));
+    build("correct horse battery staple").expect("failed to build store");
+}
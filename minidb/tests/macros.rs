@@ -4,15 +4,19 @@
 
 #![cfg(feature = "macros")]
 
-use minidb::{MiniDB, Table};
+use std::time::Duration;
+
+use minidb::{MiniDB, QuotaPolicy, Table, redb::TableHandle};
 use serde::{Deserialize, Serialize};
 use tempfile::NamedTempFile;
 
 #[derive(Table, Serialize, Deserialize)]
-#[minidb(name = "people")]
+#[minidb(name = "people", comment = "People we know", builder, fields)]
 struct Person {
     #[key]
     id: String,
+
+    #[minidb(comment = "Full name")]
     name: String,
     age: u8,
 
@@ -44,3 +48,1081 @@ fn test_minidb_with_macros_insert() {
     assert_eq!(p.age, 31);
     assert!(!p.ignored_field);
 }
+
+#[test]
+fn test_minidb_with_macros_builder() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Person>()
+        .build()
+        .expect("failed to create database");
+
+    let mut p = Person::builder().name("John").age(31).build();
+    assert!(p.id.is_empty());
+    assert!(!p.ignored_field);
+    db.insert(&mut p).expect("failed to insert person");
+    assert!(!p.id.is_empty());
+
+    let stored = db
+        .get::<Person>(&p.id)
+        .expect("failed to get person")
+        .expect("person was not inserted");
+    assert_eq!(stored.name, "John");
+    assert_eq!(stored.age, 31);
+
+    // unset fields are defaulted and the ID can still be set by hand
+    let p = Person::builder().id("custom").build();
+    assert_eq!(p.id, "custom");
+    assert!(p.name.is_empty());
+    assert_eq!(p.age, 0);
+}
+
+#[test]
+fn test_minidb_with_macros_query() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Person>()
+        .build()
+        .expect("failed to create database");
+
+    for (name, age) in [("John", 31), ("Jane", 45), ("Bob", 19), ("Alice", 60)] {
+        let mut person = Person {
+            id: String::new(),
+            name: name.to_string(),
+            age,
+            ignored_field: false,
+        };
+        db.insert(&mut person).expect("failed to insert person");
+    }
+
+    assert_eq!(Person::AGE.name(), "age");
+
+    let over_30 = db
+        .query::<Person>()
+        .filter(Person::AGE.gt(30))
+        .run()
+        .expect("failed to run query");
+    assert_eq!(over_30.len(), 3);
+    assert!(over_30.iter().all(|person| person.age > 30));
+
+    let page = db
+        .query::<Person>()
+        .filter(Person::AGE.gt(30))
+        .offset(1)
+        .limit(1)
+        .run()
+        .expect("failed to run query");
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].id, over_30[1].id);
+
+    let named = db
+        .query::<Person>()
+        .filter(Person::NAME.eq("Jane".to_string()).or(Person::AGE.le(19)))
+        .filter(!Person::NAME.eq("Bob".to_string()))
+        .run()
+        .expect("failed to run query");
+    assert_eq!(named.len(), 1);
+    assert_eq!(named[0].name, "Jane");
+
+    let mut names = db
+        .query::<Person>()
+        .filter(Person::AGE.ge(45))
+        .select(|person| person.name)
+        .expect("failed to select names");
+    names.sort_unstable();
+    assert_eq!(names, ["Alice", "Jane"]);
+
+    let (name, age) = db
+        .select::<Person, _, _>(&named[0].id, |person| (person.name, person.age))
+        .expect("failed to select person")
+        .expect("person was not found");
+    assert_eq!((name.as_str(), age), ("Jane", 45));
+    assert!(
+        db.select::<Person, _, _>("missing", |person| person.age)
+            .expect("failed to select person")
+            .is_none()
+    );
+}
+
+#[test]
+fn test_minidb_with_macros_query_eq_only() {
+    #[derive(Serialize, Deserialize, PartialEq)]
+    enum Status {
+        Open,
+        Closed,
+    }
+
+    #[derive(Table, Serialize, Deserialize)]
+    #[minidb(fields)]
+    struct Ticket {
+        #[key]
+        id: String,
+        status: Status,
+    }
+
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Ticket>()
+        .build()
+        .expect("failed to create database");
+
+    for status in [Status::Open, Status::Closed, Status::Open] {
+        let mut ticket = Ticket {
+            id: String::new(),
+            status,
+        };
+        db.insert(&mut ticket).expect("failed to insert ticket");
+    }
+
+    let open = db
+        .query::<Ticket>()
+        .filter(Ticket::STATUS.eq(Status::Open))
+        .run()
+        .expect("failed to run query");
+    assert_eq!(open.len(), 2);
+    let closed = db
+        .query::<Ticket>()
+        .filter(Ticket::STATUS.ne(Status::Open))
+        .run()
+        .expect("failed to run query");
+    assert_eq!(closed.len(), 1);
+}
+
+#[test]
+fn test_minidb_with_macros_singleton() {
+    #[derive(Table, Default, Serialize, Deserialize)]
+    #[minidb(singleton)]
+    struct Settings {
+        #[key]
+        id: String,
+        theme: String,
+    }
+
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Settings>()
+        .build()
+        .expect("failed to create database");
+
+    let mut settings = db
+        .singleton::<Settings>()
+        .get_or_default()
+        .expect("failed to get settings");
+    settings.theme = "dark".to_string();
+    db.singleton::<Settings>()
+        .set(&mut settings)
+        .expect("failed to set settings");
+    assert!(matches!(
+        db.insert(&mut Settings::default()),
+        Err(minidb::Error::NotSingleton { .. })
+    ));
+}
+
+#[test]
+fn test_minidb_with_macros_comments() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Person>()
+        .table::<Tag>()
+        .build()
+        .expect("failed to create database");
+
+    let info = db
+        .table_info("people")
+        .expect("failed to get table info")
+        .expect("people table info was not stored");
+    assert_eq!(info.comment.as_deref(), Some("People we know"));
+    assert_eq!(
+        info.field_comments,
+        vec![("name".to_string(), "Full name".to_string())]
+    );
+
+    let tables = db.tables().expect("failed to get tables");
+    let names: Vec<&str> = tables.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["people", "tag"]);
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Tag(#[key] String, String);
+
+#[test]
+fn test_minidb_with_macros_tuple_struct() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Tag>()
+        .build()
+        .expect("failed to create database");
+
+    let mut t = Tag(String::new(), "rust".to_string());
+    db.insert(&mut t).expect("failed to insert tag");
+    assert!(!t.0.is_empty());
+
+    let tag: Tag = db
+        .get(&t.0)
+        .expect("failed to get tag")
+        .expect("tag was not inserted");
+    assert_eq!(tag.1, "rust");
+    assert_eq!(Tag::TABLE.name(), "tag");
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Document {
+    #[key]
+    id: String,
+    body: String,
+
+    #[serde(skip)]
+    #[minidb(transient, rehydrate = "Document::count_words")]
+    word_count: usize,
+}
+
+impl Document {
+    fn count_words(&self) -> usize {
+        self.body.split_whitespace().count()
+    }
+}
+
+#[test]
+fn test_minidb_with_macros_transient_field() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Document>()
+        .build()
+        .expect("failed to create database");
+
+    let mut d = Document {
+        id: String::new(),
+        body: "the quick brown fox".to_string(),
+        word_count: 0,
+    };
+    db.insert(&mut d).expect("failed to insert document");
+
+    let doc: Document = db
+        .get(&d.id)
+        .expect("failed to get document")
+        .expect("document was not inserted");
+    assert_eq!(doc.word_count, 4);
+
+    let all_docs = db.all::<Document>().expect("failed to get all documents");
+    assert_eq!(all_docs[0].word_count, 4);
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Invoice {
+    #[key]
+    id: String,
+    lines: Vec<u32>,
+
+    #[minidb(computed = "Invoice::sum_lines")]
+    total: u32,
+}
+
+impl Invoice {
+    fn sum_lines(&self) -> u32 {
+        self.lines.iter().sum()
+    }
+}
+
+#[test]
+fn test_minidb_with_macros_computed_field() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Invoice>()
+        .build()
+        .expect("failed to create database");
+
+    let mut invoice = Invoice {
+        id: String::new(),
+        lines: vec![10, 20],
+        total: 0,
+    };
+    db.insert(&mut invoice).expect("failed to insert invoice");
+    assert_eq!(invoice.total, 30);
+
+    invoice.lines.push(5);
+    db.update(&invoice).expect("failed to update invoice");
+    assert_eq!(invoice.total, 30);
+
+    let stored: Invoice = db
+        .get(&invoice.id)
+        .expect("failed to get invoice")
+        .expect("invoice was not inserted");
+    assert_eq!(stored.total, 35);
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Quote {
+    #[key]
+    id: String,
+    price: u32,
+
+    #[serde(skip)]
+    #[minidb(transient)]
+    discount: u32,
+
+    #[minidb(computed = "Quote::discounted")]
+    total: u32,
+}
+
+impl Quote {
+    fn discounted(&self) -> u32 {
+        self.price - self.discount
+    }
+}
+
+#[test]
+fn test_minidb_with_macros_computed_field_on_insert() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Quote>()
+        .build()
+        .expect("failed to create database");
+
+    // inserts compute on the caller's item, so transient fields are still set
+    let mut quote = Quote {
+        id: String::new(),
+        price: 100,
+        discount: 15,
+        total: 0,
+    };
+    db.insert(&mut quote).expect("failed to insert quote");
+    assert_eq!(quote.total, 85);
+
+    let stored: Quote = db
+        .get(&quote.id)
+        .expect("failed to get quote")
+        .expect("quote was not inserted");
+    assert_eq!(stored.total, 85);
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Account {
+    #[key]
+    id: String,
+    user: String,
+
+    #[minidb(redact)]
+    token: String,
+}
+
+#[test]
+fn test_minidb_with_macros_redacted_field() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Account>()
+        .build()
+        .expect("failed to create database");
+
+    let mut account = Account {
+        id: String::new(),
+        user: "john".to_string(),
+        token: "hunter2".to_string(),
+    };
+    db.insert(&mut account).expect("failed to insert account");
+
+    let debug = format!("{account:?}");
+    assert!(debug.contains("john"));
+    assert!(!debug.contains("hunter2"));
+
+    let json = db
+        .export_table::<Account>(false)
+        .expect("failed to export accounts");
+    assert!(json.contains("\"token\":\"[REDACTED]\""));
+    assert!(!json.contains("hunter2"));
+
+    // record exports reset the field instead, so they can still be imported
+    let mut blob = Vec::new();
+    db.export_record::<Account, _>(&account.id, &mut blob)
+        .expect("failed to export account");
+    assert!(!blob.windows(7).any(|window| window == b"hunter2"));
+    let other_file = NamedTempFile::new().expect("failed to create temp file");
+    let other = MiniDB::builder(other_file.path())
+        .table::<Account>()
+        .build()
+        .expect("failed to create database");
+    let imported = other
+        .import_record::<Account, _>(blob.as_slice())
+        .expect("failed to import account");
+    assert_eq!(
+        (imported.user.as_str(), imported.token.as_str()),
+        ("john", "")
+    );
+
+    let stored: Account = db
+        .get(&account.id)
+        .expect("failed to get account")
+        .expect("account was not inserted");
+    assert_eq!(stored.token, "hunter2");
+}
+
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
+enum Role {
+    #[default]
+    Member,
+    Admin,
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Membership {
+    #[key]
+    id: String,
+    user: String,
+
+    #[minidb(redact)]
+    role: Role,
+}
+
+#[test]
+fn test_minidb_with_macros_redacted_enum_field() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Membership>()
+        .build()
+        .expect("failed to create database");
+
+    let mut membership = Membership {
+        id: String::new(),
+        user: "john".to_string(),
+        role: Role::Admin,
+    };
+    db.insert(&mut membership)
+        .expect("failed to insert membership");
+
+    let mut blob = Vec::new();
+    db.export_record::<Membership, _>(&membership.id, &mut blob)
+        .expect("failed to export membership");
+    db.remove::<Membership>(&membership.id)
+        .expect("failed to remove membership");
+
+    // the field gets its default value, an emptied variant name wouldn't decode
+    let imported = db
+        .import_record::<Membership, _>(blob.as_slice())
+        .expect("failed to import membership");
+    assert_eq!(imported.user, "john");
+    assert_eq!(imported.role, Role::Member);
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Employee {
+    #[key]
+    id: String,
+    name: String,
+
+    #[index]
+    city: String,
+}
+
+#[test]
+fn test_minidb_with_macros_index() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Employee>()
+        .build()
+        .expect("failed to create database");
+
+    let mut employees = ["john", "jane", "bob"].map(|name| Employee {
+        id: String::new(),
+        name: name.to_string(),
+        city: if name == "bob" { "Berlin" } else { "Paris" }.to_string(),
+    });
+    for employee in &mut employees {
+        db.insert(employee).expect("failed to insert employee");
+    }
+
+    let in_paris: Vec<Employee> = db
+        .find_by_index("city", "Paris")
+        .expect("failed to find employees");
+    let mut names: Vec<_> = in_paris.iter().map(|e| e.name.as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["jane", "john"]);
+
+    employees[0].city = "Berlin".to_string();
+    db.update(&employees[0]).expect("failed to update employee");
+    db.remove::<Employee>(&employees[2].id)
+        .expect("failed to remove employee");
+
+    let in_berlin: Vec<Employee> = db
+        .find_by_index("city", "Berlin")
+        .expect("failed to find employees");
+    assert_eq!(in_berlin.len(), 1);
+    assert_eq!(in_berlin[0].name, "john");
+    assert_eq!(
+        db.find_by_index::<Employee, _>("city", "Paris")
+            .expect("failed to find employees")
+            .len(),
+        1
+    );
+
+    assert!(matches!(
+        db.find_by_index::<Employee, _>("name", "john"),
+        Err(minidb::Error::UnknownIndex { .. })
+    ));
+}
+
+#[test]
+fn test_minidb_with_macros_quota_evict() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Employee>()
+        .record_timestamps(true)
+        .quota(64 * 1024, QuotaPolicy::Evict(vec!["employee".to_string()]))
+        .build()
+        .expect("failed to create database");
+
+    // the IDs go down so the oldest records aren't the first in key order
+    for i in (0..10).rev() {
+        let mut employee = Employee {
+            id: format!("{i:02}"),
+            name: "x".repeat(10 * 1024),
+            city: "Paris".to_string(),
+        };
+        db.insert(&mut employee).expect("failed to insert employee");
+        std::thread::sleep(Duration::from_millis(2));
+    }
+
+    let left = db.all::<Employee>().expect("failed to get employees");
+    assert!(left.len() < 10);
+    assert!(db.get::<Employee>("09").expect("failed to get").is_none());
+    assert!(db.get::<Employee>("00").expect("failed to get").is_some());
+
+    // evicted records leave the index with them
+    let in_paris: Vec<Employee> = db
+        .find_by_index("city", "Paris")
+        .expect("failed to find employees");
+    assert_eq!(in_paris.len(), left.len());
+}
+
+// the same table as `Employee` with a layout it can't read
+#[derive(Table, Serialize, Deserialize)]
+#[minidb(name = "employee")]
+struct EmployeeBadge {
+    #[key]
+    id: String,
+    badge: u8,
+}
+
+#[test]
+fn test_minidb_with_macros_get_with_repair() {
+    let primary_file = NamedTempFile::new().expect("failed to create temp file");
+    let replica_file = NamedTempFile::new().expect("failed to create temp file");
+    let build = |path| {
+        MiniDB::builder(path)
+            .table::<Employee>()
+            .build()
+            .expect("failed to create database")
+    };
+    let primary = build(primary_file.path());
+    let replica = build(replica_file.path());
+
+    primary
+        .transaction(|txn| {
+            txn.insert_with_id(
+                &mut EmployeeBadge {
+                    id: String::new(),
+                    badge: 200,
+                },
+                "john",
+            )
+        })
+        .expect("failed to insert badge");
+    replica
+        .transaction(|txn| {
+            txn.insert_with_id(
+                &mut Employee {
+                    id: String::new(),
+                    name: "john".to_string(),
+                    city: "Paris".to_string(),
+                },
+                "john",
+            )
+        })
+        .expect("failed to insert employee");
+    assert!(primary.get::<Employee>("john").is_err());
+
+    let john = primary
+        .get_with_repair::<Employee>("john", &replica)
+        .expect("failed to repair employee")
+        .expect("replica has the employee");
+    assert_eq!(john.city, "Paris");
+    let in_paris: Vec<Employee> = primary
+        .find_by_index("city", "Paris")
+        .expect("failed to find employees");
+    assert_eq!(in_paris.len(), 1);
+    assert_eq!(in_paris[0].id, "john");
+}
+
+#[test]
+fn test_minidb_with_macros_field_stats() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let mut db = MiniDB::builder(temp_file.path())
+        .table::<Employee>()
+        .build()
+        .expect("failed to create database");
+
+    for (name, city) in [("john", "Paris"), ("jane", "Paris"), ("bob", "Berlin")] {
+        let mut employee = Employee {
+            id: String::new(),
+            name: name.to_string(),
+            city: city.to_string(),
+        };
+        db.insert(&mut employee).expect("failed to insert employee");
+    }
+    assert!(db.field_stats().expect("failed to get stats").is_empty());
+
+    db.compact().expect("failed to compact");
+    let stats = db.field_stats().expect("failed to get stats");
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].table, "employee");
+    assert_eq!(stats[0].field, "city");
+    assert_eq!(stats[0].entries, 3);
+    assert_eq!(stats[0].cardinality, 2);
+
+    // strings are prefixed with their length, so the shorter one comes first
+    let first: String = postcard::from_bytes(stats[0].min_bytes.as_deref().unwrap()).unwrap();
+    let last: String = postcard::from_bytes(stats[0].max_bytes.as_deref().unwrap()).unwrap();
+    assert_eq!((first.as_str(), last.as_str()), ("Paris", "Berlin"));
+}
+
+// the same table as `Employee` from before its field was indexed
+#[derive(Table, Serialize, Deserialize)]
+#[minidb(name = "employee")]
+struct UnindexedEmployee {
+    #[key]
+    id: String,
+    name: String,
+    city: String,
+}
+
+#[test]
+fn test_minidb_with_macros_build_index() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<UnindexedEmployee>()
+        .build()
+        .expect("failed to create database");
+
+    for name in ["john", "jane", "bob"] {
+        let mut employee = UnindexedEmployee {
+            id: String::new(),
+            name: name.to_string(),
+            city: "Paris".to_string(),
+        };
+        db.insert(&mut employee).expect("failed to insert employee");
+    }
+
+    assert!(matches!(
+        db.build_index::<Employee>("name", 1, Duration::ZERO),
+        Err(minidb::Error::UnknownIndex { .. })
+    ));
+    db.build_index::<Employee>("city", 1, Duration::from_millis(1))
+        .expect("failed to build index");
+
+    let in_paris: Vec<Employee> = db
+        .find_by_index("city", "Paris")
+        .expect("failed to find employees");
+    assert_eq!(in_paris.len(), 3);
+}
+
+#[test]
+fn test_minidb_with_macros_index_reregistered() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Employee>()
+        .build()
+        .expect("failed to create database");
+    db.insert(&mut Employee {
+        id: String::new(),
+        name: "john".to_string(),
+        city: "Paris".to_string(),
+    })
+    .expect("failed to insert employee");
+    drop(db);
+
+    let db = MiniDB::builder(temp_file.path())
+        .table::<UnindexedEmployee>()
+        .build()
+        .expect("failed to create database");
+    db.insert(&mut UnindexedEmployee {
+        id: String::new(),
+        name: "jane".to_string(),
+        city: "Paris".to_string(),
+    })
+    .expect("failed to insert employee");
+    drop(db);
+
+    // jane was written while the index wasn't registered, so it's built again
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Employee>()
+        .build()
+        .expect("failed to create database");
+    let in_paris: Vec<Employee> = db
+        .find_by_index("city", "Paris")
+        .expect("failed to find employees");
+    let mut names: Vec<_> = in_paris.iter().map(|e| e.name.as_str()).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["jane", "john"]);
+}
+
+#[derive(Table, Serialize, Deserialize)]
+#[minidb(fields)]
+struct Subscriber {
+    #[key]
+    id: String,
+
+    #[unique]
+    email: String,
+}
+
+#[test]
+fn test_minidb_with_macros_unique() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Subscriber>()
+        .build()
+        .expect("failed to create database");
+
+    let mut john = Subscriber {
+        id: String::new(),
+        email: "john@example.com".to_string(),
+    };
+    let mut jane = Subscriber {
+        id: String::new(),
+        email: "jane@example.com".to_string(),
+    };
+    db.insert(&mut john).expect("failed to insert subscriber");
+    db.insert(&mut jane).expect("failed to insert subscriber");
+    db.update(&john).expect("failed to update subscriber");
+
+    let mut copy = Subscriber {
+        id: String::new(),
+        email: john.email.clone(),
+    };
+    let err = db
+        .insert(&mut copy)
+        .expect_err("duplicate email was inserted");
+    assert!(matches!(err, minidb::Error::UniqueViolation { ref id, .. } if *id == john.id));
+
+    // the table was empty when registered, so the index was used instead of a scan from the first write
+    assert_eq!(
+        db.find_by_index::<Subscriber, _>("email", "jane@example.com")
+            .expect("failed to find subscriber")
+            .len(),
+        1
+    );
+    assert_eq!(
+        db.get_by(Subscriber::EMAIL, &john.email)
+            .expect("failed to get subscriber")
+            .map(|subscriber| subscriber.id),
+        Some(john.id.clone())
+    );
+    assert!(
+        db.get_by(Subscriber::EMAIL, &"nobody@example.com".to_string())
+            .expect("failed to get subscriber")
+            .is_none()
+    );
+    assert!(matches!(
+        db.get_by(Subscriber::ID, &john.id),
+        Err(minidb::Error::NotUnique { .. })
+    ));
+    jane.email.clone_from(&john.email);
+    assert!(matches!(
+        db.update(&jane),
+        Err(minidb::Error::UniqueViolation { .. })
+    ));
+
+    db.remove::<Subscriber>(&john.id)
+        .expect("failed to remove subscriber");
+    db.update(&jane).expect("freed email should be usable");
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn test_minidb_with_macros_bench_profile_unique() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Subscriber>()
+        .build()
+        .expect("failed to create database");
+
+    // every copy has the same email, which the real table wouldn't accept
+    let sample = Subscriber {
+        id: String::new(),
+        email: "john@example.com".to_string(),
+    };
+    let profile = db.bench_profile(&sample, 3).expect("failed to profile");
+    assert_eq!(profile.iterations, 3);
+    assert!(db.is_empty::<Subscriber>().expect("failed to check table"));
+
+    let mut john = sample;
+    db.insert(&mut john).expect("failed to insert subscriber");
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Admin {
+    #[key]
+    id: String,
+
+    #[unique]
+    username: String,
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Customer {
+    #[key]
+    id: String,
+
+    #[index]
+    username: String,
+    email: String,
+}
+
+fn open_with_usernames(path: &std::path::Path) -> Result<MiniDB, minidb::Error> {
+    MiniDB::builder(path)
+        .table::<Admin>()
+        .table::<Customer>()
+        .unique_across::<Admin>("usernames", "username")
+        .unique_across::<Customer>("usernames", "username")
+        .build()
+}
+
+#[test]
+fn test_minidb_with_macros_unique_across() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = open_with_usernames(temp_file.path()).expect("failed to create database");
+
+    let mut admin = Admin {
+        id: String::new(),
+        username: "root".to_string(),
+    };
+    db.insert(&mut admin).expect("failed to insert admin");
+    let mut customer = Customer {
+        id: String::new(),
+        username: "root".to_string(),
+        email: "root@example.com".to_string(),
+    };
+    match db.insert(&mut customer) {
+        Err(minidb::Error::UniqueViolation { table, id, .. }) => {
+            assert_eq!(table, "admin");
+            assert_eq!(id, admin.id);
+        }
+        other => panic!("expected a unique violation, got {other:?}"),
+    }
+
+    customer.username = "jane".to_string();
+    db.insert(&mut customer).expect("failed to insert customer");
+    // updating a record keeps its own value and frees the old one
+    customer.email = "jane@example.com".to_string();
+    db.update(&customer).expect("failed to update customer");
+    admin.username = "jane".to_string();
+    assert!(matches!(
+        db.update(&admin),
+        Err(minidb::Error::UniqueViolation { .. })
+    ));
+    admin.username = "boss".to_string();
+    db.update(&admin).expect("failed to rename admin");
+    let mut other = Customer {
+        id: String::new(),
+        username: "root".to_string(),
+        email: String::new(),
+    };
+    db.insert(&mut other)
+        .expect("freed username should be usable");
+
+    db.remove::<Customer>(&customer.id)
+        .expect("failed to remove customer");
+    admin.username = "jane".to_string();
+    db.update(&admin)
+        .expect("removed username should be usable");
+    drop(db);
+
+    // records written before the group existed are checked when it's registered
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Admin>()
+        .table::<Customer>()
+        .build()
+        .expect("failed to create database");
+    db.insert(&mut Admin {
+        id: String::new(),
+        username: "root".to_string(),
+    })
+    .expect("failed to insert admin");
+    db.insert(&mut Customer {
+        id: String::new(),
+        username: "root".to_string(),
+        email: String::new(),
+    })
+    .expect("failed to insert customer");
+    drop(db);
+    assert!(matches!(
+        open_with_usernames(temp_file.path()),
+        Err(minidb::Error::UniqueViolation { .. })
+    ));
+
+    assert!(matches!(
+        MiniDB::builder(temp_file.path())
+            .unique_across::<Customer>("emails", "email")
+            .build(),
+        Err(minidb::Error::UnknownIndex { .. })
+    ));
+}
+
+#[test]
+fn test_minidb_with_macros_unique_across_reregistered() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = open_with_usernames(temp_file.path()).expect("failed to create database");
+    let mut admin = Admin {
+        id: String::new(),
+        username: "root".to_string(),
+    };
+    db.insert(&mut admin).expect("failed to insert admin");
+    drop(db);
+
+    // writes made without the group don't release or claim anything
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Admin>()
+        .table::<Customer>()
+        .build()
+        .expect("failed to open database");
+    admin.username = "boss".to_string();
+    db.update(&admin).expect("failed to rename admin");
+    let mut customer = Customer {
+        id: String::new(),
+        username: "root".to_string(),
+        email: String::new(),
+    };
+    db.insert(&mut customer).expect("failed to insert customer");
+    drop(db);
+
+    // the claim left on the old name is ignored and the new records are claimed again
+    let db = open_with_usernames(temp_file.path()).expect("failed to reopen database");
+    let mut other = Admin {
+        id: String::new(),
+        username: "root".to_string(),
+    };
+    match db.insert(&mut other) {
+        Err(minidb::Error::UniqueViolation { table, id, .. }) => {
+            assert_eq!(table, "customer");
+            assert_eq!(id, customer.id);
+        }
+        other => panic!("expected a unique violation, got {other:?}"),
+    }
+    assert!(matches!(
+        db.insert(&mut Customer {
+            id: String::new(),
+            username: "boss".to_string(),
+            email: String::new(),
+        }),
+        Err(minidb::Error::UniqueViolation { .. })
+    ));
+}
+
+#[derive(Table, Serialize, Deserialize)]
+struct Article {
+    #[key]
+    id: String,
+
+    #[fulltext]
+    title: String,
+
+    #[fulltext]
+    body: String,
+}
+
+#[test]
+fn test_minidb_with_macros_search() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Article>()
+        .build()
+        .expect("failed to create database");
+
+    let mut articles = [
+        ("Rust databases", "Embedded databases written in Rust"),
+        ("Cooking", "Rust on a cast iron pan, and how to clean it"),
+        ("Gardening", "Tomatoes need sun"),
+    ]
+    .map(|(title, body)| Article {
+        id: String::new(),
+        title: title.to_string(),
+        body: body.to_string(),
+    });
+    db.insert_many(&mut articles)
+        .expect("failed to insert articles");
+
+    // words aren't stemmed so "database" doesn't match "databases", the first article wins by mentioning rust twice
+    let hits = db
+        .search::<Article>("RUST database")
+        .expect("failed to search");
+    let titles: Vec<_> = hits.iter().map(|hit| hit.item.title.as_str()).collect();
+    assert_eq!(titles, ["Rust databases", "Cooking"]);
+    assert!(hits[0].score > hits[1].score);
+    assert!(
+        db.search::<Article>("database")
+            .expect("failed to search")
+            .is_empty()
+    );
+    assert_eq!(
+        db.search::<Article>("databases pan")
+            .expect("failed to search")
+            .len(),
+        2
+    );
+    assert!(
+        db.search::<Article>("")
+            .expect("failed to search")
+            .is_empty()
+    );
+
+    // updates replace the old words and removals drop them
+    articles[2].body = "Tomatoes and rust".to_string();
+    db.update(&articles[2]).expect("failed to update article");
+    assert!(
+        db.search::<Article>("sun")
+            .expect("failed to search")
+            .is_empty()
+    );
+    assert_eq!(
+        db.search::<Article>("rust")
+            .expect("failed to search")
+            .len(),
+        3
+    );
+    db.remove::<Article>(&articles[1].id)
+        .expect("failed to remove article");
+    assert_eq!(
+        db.search::<Article>("rust")
+            .expect("failed to search")
+            .len(),
+        2
+    );
+
+    assert_eq!(
+        db.rebuild_fulltext::<Article>()
+            .expect("failed to rebuild index"),
+        2
+    );
+    assert_eq!(
+        db.search::<Article>("tomatoes")
+            .expect("failed to search")
+            .first()
+            .map(|hit| hit.item.id.as_str()),
+        Some(articles[2].id.as_str())
+    );
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn test_minidb_with_macros_all_tables() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .all_tables()
+        .build()
+        .expect("failed to create database");
+
+    assert!(
+        db.is_empty::<Person>()
+            .expect("people table was not registered")
+    );
+    assert!(db.is_empty::<Tag>().expect("tag table was not registered"));
+}
@@ -2,12 +2,15 @@
 // Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
 // with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use anyhow::{Result, anyhow};
-use minidb::{MiniDB, Table};
+use minidb::{ImportPolicy, LockBackend, MiniDB, QuotaPolicy, SINGLETON_ID, Table};
 use rand::seq::IndexedRandom;
-use redb::TableDefinition;
+use redb::{ReadableDatabase, TableDefinition, TableHandle};
 use serde::{Deserialize, Serialize};
 use tempfile::NamedTempFile;
 
@@ -106,6 +109,53 @@ fn test_minidb_insert() {
     assert_eq!(all_restaurants.len(), 1);
 }
 
+#[test]
+fn test_minidb_insert_with_id() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut r = Restaurant { id: String::new() };
+    db.insert_with_id(&mut r, "golden-dragon")
+        .expect("failed to insert restaurant");
+    assert_eq!(r.id, "golden-dragon");
+    assert!(db.get::<Restaurant>("golden-dragon").unwrap().is_some());
+
+    let mut duplicate = Restaurant { id: String::new() };
+    assert!(matches!(
+        db.insert_with_id(&mut duplicate, "golden-dragon"),
+        Err(minidb::Error::RecordExists { .. })
+    ));
+    assert!(matches!(
+        db.insert_with_id(&mut duplicate, " "),
+        Err(minidb::Error::EmptyID)
+    ));
+}
+
+#[test]
+fn test_minidb_get_or_insert_with() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItem>()
+        .build()
+        .expect("failed to build store");
+
+    let first: MenuItem = db
+        .get_or_insert_with("special", || MenuItem {
+            id: String::new(),
+            name: "Soup".to_string(),
+        })
+        .expect("failed to get or insert menu item");
+    assert_eq!(first.id, "special");
+
+    let second: MenuItem = db
+        .get_or_insert_with("special", || panic!("the item already exists"))
+        .expect("failed to get or insert menu item");
+    assert_eq!(second.name, "Soup");
+}
+
 #[test]
 fn test_minidb_insert_many() {
     const N: usize = 1000;
@@ -135,6 +185,21 @@ fn test_minidb_insert_many() {
     assert_eq!(ids.len(), N);
 }
 
+#[test]
+fn test_minidb_id_retries() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut restaurants: Vec<Restaurant> =
+        (0..100).map(|_| Restaurant { id: String::new() }).collect();
+    db.insert_many(&mut restaurants)
+        .expect("failed to insert many restaurants");
+    assert_eq!(db.id_retries(), 0);
+}
+
 #[test]
 fn test_minidb_update() {
     let temp_file = NamedTempFile::new().expect("failed to create temp file");
@@ -266,6 +331,59 @@ fn test_minidb_get_non_existent() {
         .expect("restaurant is non-existent for some reason");
 }
 
+#[test]
+fn test_minidb_get_many() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut restaurants = vec![
+        Restaurant { id: String::new() },
+        Restaurant { id: String::new() },
+    ];
+    db.insert_many(&mut restaurants)
+        .expect("failed to insert many restaurants");
+
+    let found: Vec<Option<Restaurant>> = db
+        .get_many(&[&restaurants[1].id, "missing", &restaurants[0].id])
+        .expect("failed to get many restaurants");
+    let ids: Vec<Option<&str>> = found
+        .iter()
+        .map(|r| r.as_ref().map(|r| r.id.as_str()))
+        .collect();
+    assert_eq!(
+        ids,
+        vec![
+            Some(restaurants[1].id.as_str()),
+            None,
+            Some(restaurants[0].id.as_str())
+        ]
+    );
+}
+
+#[test]
+fn test_minidb_exists_many() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut restaurants = vec![
+        Restaurant { id: String::new() },
+        Restaurant { id: String::new() },
+    ];
+    db.insert_many(&mut restaurants)
+        .expect("failed to insert many restaurants");
+
+    let exists = db
+        .exists_many::<Restaurant>(&[&restaurants[1].id, "missing", &restaurants[0].id])
+        .expect("failed to check restaurants");
+    assert_eq!(exists, [true, false, true]);
+}
+
 #[test]
 fn test_minidb_all() {
     const N: usize = 1000;
@@ -290,6 +408,52 @@ fn test_minidb_all() {
     assert_eq!(all_restaurants.len(), N);
 }
 
+#[test]
+fn test_minidb_all_sorted_by_id() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    for id in ["pear", "apple", "Zebra", "banana"] {
+        db.insert_with_id(&mut Restaurant { id: String::new() }, id)
+            .expect("failed to insert restaurant");
+    }
+
+    let ids: Vec<String> = db
+        .all::<Restaurant>()
+        .expect("failed to get all restaurants")
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+    assert_eq!(ids, vec!["Zebra", "apple", "banana", "pear"]);
+
+    let mut visited = Vec::new();
+    db.for_each::<Restaurant, _>(|r| visited.push(r.id.clone()))
+        .expect("failed to iterate restaurants");
+    assert_eq!(visited, ids);
+}
+
+#[test]
+fn test_minidb_ids() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    for id in ["pear", "apple", "Zebra", "banana"] {
+        db.insert_with_id(&mut Restaurant { id: String::new() }, id)
+            .expect("failed to insert restaurant");
+    }
+
+    assert_eq!(
+        db.ids::<Restaurant>().expect("failed to get ids"),
+        ["Zebra", "apple", "banana", "pear"]
+    );
+}
+
 #[test]
 fn test_minidb_all_from_empty_table() {
     let temp_file = NamedTempFile::new().expect("failed to create temp file");
@@ -421,6 +585,74 @@ fn test_minidb_settings() {
     assert_eq!(r.id, rest.id);
 }
 
+#[derive(Default, Serialize, Deserialize)]
+struct AppConfig {
+    id: String,
+    theme: String,
+    volume: u8,
+}
+
+impl Table for AppConfig {
+    const TABLE: TableDefinition<'_, &'static str, &[u8]> = TableDefinition::new("app_config");
+    const SINGLETON: bool = true;
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+}
+
+#[test]
+fn test_minidb_singleton() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<AppConfig>()
+        .build()
+        .expect("failed to build store");
+    let config = db.singleton::<AppConfig>();
+
+    assert!(config.get().expect("failed to get config").is_none());
+    let mut current = config.get_or_default().expect("failed to get config");
+    assert_eq!(current.id, SINGLETON_ID);
+    assert!(db.is_empty::<AppConfig>().expect("failed to check table"));
+
+    current.theme = "dark".to_string();
+    config.set(&mut current).expect("failed to set config");
+    let mut other = AppConfig {
+        id: "ignored".to_string(),
+        theme: "light".to_string(),
+        volume: 80,
+    };
+    config.set(&mut other).expect("failed to set config");
+
+    // every set replaces the one record
+    assert_eq!(db.count::<AppConfig>().expect("failed to count"), 1);
+    let stored = config
+        .get()
+        .expect("failed to get config")
+        .expect("config was not stored");
+    assert_eq!(stored.theme, "light");
+    assert_eq!(stored.volume, 80);
+
+    // writes that go around the singleton can't add a second record
+    other.id = "other".to_string();
+    assert!(matches!(
+        db.update(&other),
+        Err(minidb::Error::NotSingleton { ref id, .. }) if id == "other"
+    ));
+    assert!(matches!(
+        db.insert(&mut AppConfig::default()),
+        Err(minidb::Error::NotSingleton { .. })
+    ));
+    assert_eq!(db.count::<AppConfig>().expect("failed to count"), 1);
+
+    assert!(config.clear().expect("failed to clear config").is_some());
+    assert!(config.get().expect("failed to get config").is_none());
+}
+
 #[test]
 fn test_minidb_export_table() {
     const N: usize = 1000;
@@ -552,3 +784,1629 @@ fn test_minidb_view_all() {
 
     assert_eq!(first_five.len(), 5);
 }
+
+#[test]
+fn test_minidb_iter() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut rests: Vec<Restaurant> = (0..110).map(|_| Restaurant { id: String::new() }).collect();
+    db.insert_many(&mut rests)
+        .expect("failed to insert many restaurants");
+
+    let iter = db.iter::<Restaurant>().expect("failed to iterate");
+    let mut extra = Restaurant { id: String::new() };
+    db.insert(&mut extra).expect("failed to insert restaurant");
+
+    // the iterator keeps reading the snapshot it was created from
+    let mut count = 0;
+    for restaurant in iter {
+        restaurant.expect("failed to decode restaurant");
+        count += 1;
+    }
+    assert_eq!(count, 110);
+    assert_eq!(
+        db.iter::<Restaurant>().expect("failed to iterate").count(),
+        111
+    );
+}
+
+#[test]
+fn test_minidb_batches() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut rests: Vec<Restaurant> = (0..110).map(|_| Restaurant { id: String::new() }).collect();
+    db.insert_many(&mut rests)
+        .expect("failed to insert many restaurants");
+
+    // batches read the latest data, so a record removed after the IDs were listed is skipped
+    let ids = db.ids::<Restaurant>().expect("failed to get ids");
+    let mut batches = db.batches::<Restaurant>(50).expect("failed to scan");
+    let first = batches
+        .next()
+        .expect("missing first batch")
+        .expect("failed to read batch");
+    assert_eq!(first.len(), 50);
+    db.remove::<Restaurant>(&ids[100])
+        .expect("failed to remove restaurant");
+    let sizes: Vec<usize> = batches
+        .map(|batch| batch.expect("failed to read batch").len())
+        .collect();
+    assert_eq!(sizes, [50, 9]);
+}
+
+#[derive(Serialize, Deserialize)]
+struct MenuItemV1 {
+    pub id: String,
+    pub name: String,
+    pub price: u32,
+}
+
+impl Table for MenuItemV1 {
+    const TABLE: TableDefinition<'_, &'static str, &[u8]> = TableDefinition::new("menu");
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+}
+
+/// Returns the Pizza (12), Soup (6) and Steak (25) menu items, without IDs
+fn menu_items() -> Vec<MenuItemV1> {
+    [("Pizza", 12), ("Soup", 6), ("Steak", 25)]
+        .into_iter()
+        .map(|(name, price)| MenuItemV1 {
+            id: String::new(),
+            name: name.to_string(),
+            price,
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+struct MenuItem {
+    pub id: String,
+    pub name: String,
+}
+
+impl Table for MenuItem {
+    const TABLE: TableDefinition<'_, &'static str, &[u8]> = TableDefinition::new("menu");
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+}
+
+#[test]
+fn test_minidb_strict_unknown_fields() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let mut db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut item = MenuItemV1 {
+        id: String::new(),
+        name: "Pizza".to_string(),
+        price: 10,
+    };
+    db.insert(&mut item).expect("failed to insert menu item");
+
+    let lenient: MenuItem = db
+        .get(&item.id)
+        .expect("failed to get menu item")
+        .expect("menu item was not inserted");
+    assert_eq!(lenient.name, "Pizza");
+
+    db.set_strict(true);
+    assert!(matches!(
+        db.get::<MenuItem>(&item.id),
+        Err(minidb::Error::UnknownFields(_))
+    ));
+    assert!(db.get::<MenuItemV1>(&item.id).is_ok());
+}
+
+#[derive(Serialize, Deserialize)]
+struct Thumbnail {
+    pub id: String,
+    pub data: Vec<u8>,
+}
+
+impl Table for Thumbnail {
+    const TABLE: TableDefinition<'_, &'static str, &[u8]> = TableDefinition::new("thumbnails");
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+}
+
+#[test]
+fn test_minidb_quota_reject() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Thumbnail>()
+        .quota(64 * 1024, QuotaPolicy::Reject)
+        .build()
+        .expect("failed to build store");
+
+    let mut small = Thumbnail {
+        id: String::new(),
+        data: vec![1; 1024],
+    };
+    db.insert(&mut small).expect("failed to insert thumbnail");
+
+    let mut big = Thumbnail {
+        id: String::new(),
+        data: vec![1; 128 * 1024],
+    };
+    assert!(matches!(
+        db.insert(&mut big),
+        Err(minidb::Error::QuotaExceeded { .. })
+    ));
+
+    let all = db.all::<Thumbnail>().expect("failed to get all thumbnails");
+    assert_eq!(all.len(), 1);
+}
+
+#[test]
+fn test_minidb_quota_evict() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Thumbnail>()
+        .quota(
+            64 * 1024,
+            QuotaPolicy::Evict(vec![Thumbnail::TABLE.name().to_string()]),
+        )
+        .build()
+        .expect("failed to build store");
+
+    for i in 0..10 {
+        let mut t = Thumbnail {
+            id: format!("{i:02}"),
+            data: vec![1; 10 * 1024],
+        };
+        db.insert(&mut t).expect("failed to insert thumbnail");
+
+        // pinned records are skipped by the eviction
+        if i == 1 {
+            db.pin::<Thumbnail>("01").expect("failed to pin");
+        }
+    }
+
+    let all = db.all::<Thumbnail>().expect("failed to get all thumbnails");
+    assert!(all.len() < 10);
+    assert!(db.get::<Thumbnail>("00").expect("failed to get").is_none());
+    assert!(db.get::<Thumbnail>("01").expect("failed to get").is_some());
+    assert!(db.get::<Thumbnail>("09").expect("failed to get").is_some());
+}
+
+#[test]
+fn test_minidb_pid_lock_file() {
+    let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = temp_dir.path().join("locked.redb");
+    let lock_path = temp_dir.path().join("locked.redb.lock");
+
+    let db = MiniDB::builder(&path)
+        .lock_backend(LockBackend::PidFile)
+        .build()
+        .expect("failed to build store");
+    let contents = std::fs::read_to_string(&lock_path).expect("lock file was not created");
+    assert_eq!(
+        contents.lines().next(),
+        Some(std::process::id().to_string().as_str())
+    );
+    let holder = MiniDB::lock_holder(&path)
+        .expect("failed to read lock")
+        .expect("lock holder was not found");
+    assert_eq!(holder.pid, Some(std::process::id()));
+    assert!(holder.host.is_some());
+    assert!(!holder.stale);
+
+    assert!(matches!(
+        MiniDB::builder(&path)
+            .lock_backend(LockBackend::PidFile)
+            .build(),
+        Err(minidb::Error::Locked { pid: Some(_), .. })
+    ));
+
+    drop(db);
+    assert!(!lock_path.exists());
+
+    MiniDB::builder(&path)
+        .lock_backend(LockBackend::PidFile)
+        .build()
+        .expect("failed to reopen store");
+}
+
+#[test]
+fn test_minidb_force_unlock() {
+    let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = temp_dir.path().join("hung.redb");
+    let lock_path = temp_dir.path().join("hung.redb.lock");
+
+    assert!(
+        MiniDB::lock_holder(&path)
+            .expect("failed to read lock")
+            .is_none()
+    );
+
+    // a lock left behind by a process that's still alive, PID 1 always is
+    std::fs::write(&lock_path, "1").expect("failed to write lock file");
+    let holder = MiniDB::lock_holder(&path)
+        .expect("failed to read lock")
+        .expect("lock holder was not found");
+    assert_eq!(holder.path, lock_path);
+    assert_eq!(holder.pid, Some(1));
+    assert!(holder.since.is_some());
+    assert!(!holder.stale);
+
+    assert!(matches!(
+        MiniDB::builder(&path)
+            .lock_backend(LockBackend::PidFile)
+            .build(),
+        Err(minidb::Error::Locked { pid: Some(1), .. })
+    ));
+
+    assert!(!MiniDB::force_unlock(&path, Some(2)).expect("failed to force unlock"));
+    assert!(lock_path.exists());
+    assert!(MiniDB::force_unlock(&path, holder.pid).expect("failed to force unlock"));
+    assert!(!MiniDB::force_unlock(&path, holder.pid).expect("failed to force unlock"));
+
+    MiniDB::builder(&path)
+        .lock_backend(LockBackend::PidFile)
+        .build()
+        .expect("failed to open store after unlocking");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_minidb_stale_lock_file() {
+    let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = temp_dir.path().join("stale.redb");
+    let lock_path = temp_dir.path().join("stale.redb.lock");
+
+    // no process can have a PID above the kernel's limit
+    let host =
+        std::fs::read_to_string("/proc/sys/kernel/hostname").expect("failed to read hostname");
+    std::fs::write(&lock_path, format!("999999999\n{}\n", host.trim()))
+        .expect("failed to write lock file");
+    assert!(
+        MiniDB::lock_holder(&path)
+            .expect("failed to read lock")
+            .expect("lock holder was not found")
+            .stale
+    );
+    drop(
+        MiniDB::builder(&path)
+            .lock_backend(LockBackend::PidFile)
+            .build()
+            .expect("failed to replace stale lock"),
+    );
+    assert!(!lock_path.exists());
+
+    // the process may be alive on the other machine
+    std::fs::write(&lock_path, "999999999\nanother-host\n").expect("failed to write lock file");
+    let holder = MiniDB::lock_holder(&path)
+        .expect("failed to read lock")
+        .expect("lock holder was not found");
+    assert_eq!(holder.host.as_deref(), Some("another-host"));
+    assert!(!holder.stale);
+    assert!(matches!(
+        MiniDB::builder(&path)
+            .lock_backend(LockBackend::PidFile)
+            .build(),
+        Err(minidb::Error::Locked {
+            pid: Some(999_999_999),
+            ..
+        })
+    ));
+
+    // a garbled lock file is only abandoned once it's old
+    std::fs::write(&lock_path, "garbage").expect("failed to write lock file");
+    assert!(matches!(
+        MiniDB::builder(&path)
+            .lock_backend(LockBackend::PidFile)
+            .build(),
+        Err(minidb::Error::Locked { pid: None, .. })
+    ));
+    std::fs::File::options()
+        .write(true)
+        .open(&lock_path)
+        .expect("failed to open lock file")
+        .set_modified(std::time::SystemTime::now() - Duration::from_secs(60))
+        .expect("failed to age lock file");
+    MiniDB::builder(&path)
+        .lock_backend(LockBackend::PidFile)
+        .build()
+        .expect("failed to replace garbled lock");
+}
+
+#[test]
+fn test_minidb_recovery_after_panic() {
+    let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = temp_dir.path().join("panicked.redb");
+    let marker = temp_dir.path().join("panicked.redb.recover");
+
+    let db = MiniDB::builder(&path)
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        db.transaction::<_, ()>(|txn| {
+            txn.insert(&mut Restaurant { id: String::new() })?;
+            panic!("interrupted write");
+        })
+    }));
+    assert!(result.is_err());
+    assert!(marker.exists());
+
+    drop(db);
+    let db = MiniDB::builder(&path)
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to reopen store");
+    assert!(!marker.exists());
+    assert!(db.is_empty::<Restaurant>().expect("failed to check table"));
+}
+
+#[test]
+fn test_minidb_lock_timeout() {
+    let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = temp_dir.path().join("waiting.redb");
+    let build = |timeout: Duration| {
+        MiniDB::builder(&path)
+            .lock_backend(LockBackend::PidFile)
+            .lock_timeout(timeout)
+            .build()
+    };
+
+    let db = build(Duration::ZERO).expect("failed to build store");
+    assert!(matches!(
+        build(Duration::from_millis(100)),
+        Err(minidb::Error::Locked { .. })
+    ));
+
+    let holder = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        drop(db);
+    });
+    build(Duration::from_secs(10)).expect("failed to wait for the lock");
+    holder.join().expect("holder thread panicked");
+}
+
+#[test]
+fn test_minidb_max_record_size() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let mut db = MiniDB::builder(temp_file.path())
+        .table::<Thumbnail>()
+        .build()
+        .expect("failed to build store");
+
+    let mut big = Thumbnail {
+        id: String::new(),
+        data: vec![1; 4096],
+    };
+    db.insert(&mut big).expect("failed to insert thumbnail");
+
+    db.set_max_record_size(Some(1024));
+    assert!(matches!(
+        db.get::<Thumbnail>(&big.id),
+        Err(minidb::Error::RecordTooLarge { max: 1024, .. })
+    ));
+
+    let mut small = Thumbnail {
+        id: String::new(),
+        data: vec![1; 16],
+    };
+    db.insert(&mut small).expect("failed to insert thumbnail");
+    assert!(matches!(
+        db.insert(&mut big),
+        Err(minidb::Error::RecordTooLarge { .. })
+    ));
+}
+
+#[test]
+fn test_minidb_format_version() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .build()
+        .expect("failed to build store");
+
+    let format = db
+        .format_info()
+        .expect("failed to get format")
+        .expect("format was not stored");
+    assert_eq!(&format.magic, b"MNDB");
+    assert_eq!(format.version, 1);
+    assert_eq!(format.codec, "postcard");
+    drop(db);
+
+    // pretend a newer version wrote the database
+    let raw = redb::Database::open(temp_file.path()).expect("failed to open raw database");
+    let txn = raw.begin_write().expect("failed to begin write");
+    {
+        let mut meta = txn
+            .open_table(TableDefinition::<&str, &[u8]>::new("meta"))
+            .expect("failed to open meta table");
+        let mut bytes = b"MNDB".to_vec();
+        bytes.push(99);
+        bytes.push(8);
+        bytes.extend_from_slice(b"postcard");
+        meta.insert("format", bytes.as_slice())
+            .expect("failed to overwrite format");
+    }
+    txn.commit().expect("failed to commit");
+    drop(raw);
+
+    assert!(matches!(
+        MiniDB::builder(temp_file.path()).build(),
+        Err(minidb::Error::UnsupportedFormat(info)) if info.version == 99
+    ));
+}
+
+#[test]
+fn test_minidb_extras() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .build()
+        .expect("failed to build store");
+
+    assert!(
+        db.get_extra("counter")
+            .expect("failed to get extra")
+            .is_none()
+    );
+
+    db.set_extra("counter", &7u32.to_le_bytes())
+        .expect("failed to set extra");
+    db.set_extra("fingerprint", b"abc")
+        .expect("failed to set extra");
+    assert_eq!(
+        db.get_extra("counter").expect("failed to get extra"),
+        Some(7u32.to_le_bytes().to_vec())
+    );
+
+    let extras = db.extras().expect("failed to get extras");
+    assert_eq!(
+        extras,
+        vec![
+            ("counter".to_string(), 7u32.to_le_bytes().to_vec()),
+            ("fingerprint".to_string(), b"abc".to_vec())
+        ]
+    );
+
+    assert!(db.remove_extra("counter").expect("failed to remove extra"));
+    assert!(!db.remove_extra("counter").expect("failed to remove extra"));
+    assert_eq!(db.extras().expect("failed to get extras").len(), 1);
+}
+
+#[test]
+fn test_minidb_record_meta() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .record_timestamps(true)
+        .build()
+        .expect("failed to build store");
+
+    assert!(
+        db.record_meta::<Restaurant>("missing")
+            .expect("failed to get record meta")
+            .is_none()
+    );
+
+    let mut r = Restaurant { id: String::new() };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    let first = db
+        .record_meta::<Restaurant>(&r.id)
+        .expect("failed to get record meta")
+        .expect("record meta is missing");
+    assert!(first.size > 0);
+    assert!(first.created.is_some());
+    assert_eq!(first.created, first.modified);
+
+    std::thread::sleep(Duration::from_millis(5));
+    db.update(&r).expect("failed to update restaurant");
+    let second = db
+        .record_meta::<Restaurant>(&r.id)
+        .expect("failed to get record meta")
+        .expect("record meta is missing");
+    assert_eq!(second.created, first.created);
+    assert!(second.modified > first.modified);
+
+    db.remove::<Restaurant>(&r.id)
+        .expect("failed to remove restaurant");
+    assert!(
+        db.record_meta::<Restaurant>(&r.id)
+            .expect("failed to get record meta")
+            .is_none()
+    );
+}
+
+#[test]
+fn test_minidb_record_meta_untracked_remove() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let build = |record_timestamps| {
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .record_timestamps(record_timestamps)
+            .build()
+            .expect("failed to build store")
+    };
+
+    let db = build(true);
+    let mut r = Restaurant {
+        id: "r1".to_string(),
+    };
+    db.insert(&mut r).expect("failed to insert restaurant");
+    drop(db);
+
+    // the old timestamps go with the record even while they aren't tracked
+    let db = build(false);
+    db.remove::<Restaurant>(&r.id)
+        .expect("failed to remove restaurant");
+    db.insert(&mut r).expect("failed to insert restaurant");
+    drop(db);
+
+    let db = build(true);
+    let meta = db
+        .record_meta::<Restaurant>(&r.id)
+        .expect("failed to get record meta")
+        .expect("record meta is missing");
+    assert!(meta.created.is_none());
+}
+
+#[test]
+fn test_minidb_sync() {
+    let laptop_file = NamedTempFile::new().expect("failed to create temp file");
+    let desktop_file = NamedTempFile::new().expect("failed to create temp file");
+    let open = |path| {
+        MiniDB::builder(path)
+            .table::<MenuItem>()
+            .record_timestamps(true)
+            .build()
+            .expect("failed to build store")
+    };
+    let laptop = open(laptop_file.path());
+    let desktop = open(desktop_file.path());
+
+    let mut soup = MenuItem {
+        id: String::new(),
+        name: "Soup".to_string(),
+    };
+    laptop.insert(&mut soup).expect("failed to insert item");
+    let report = desktop
+        .sync_from::<MenuItem>(&laptop)
+        .expect("failed to sync");
+    assert_eq!(report.pulled, 1);
+    assert_eq!(
+        desktop.record_meta::<MenuItem>(&soup.id).unwrap(),
+        laptop.record_meta::<MenuItem>(&soup.id).unwrap()
+    );
+
+    soup.name = "Tomato Soup".to_string();
+    laptop.update(&soup).expect("failed to update item");
+    std::thread::sleep(Duration::from_millis(5));
+    soup.name = "Onion Soup".to_string();
+    desktop.update(&soup).expect("failed to update item");
+
+    let report = laptop
+        .sync_from::<MenuItem>(&desktop)
+        .expect("failed to sync");
+    assert_eq!(report.replaced, 1);
+    let report = desktop
+        .sync_from::<MenuItem>(&laptop)
+        .expect("failed to sync");
+    assert_eq!(report, minidb::SyncReport::default());
+    for db in [&laptop, &desktop] {
+        let item: MenuItem = db.get(&soup.id).unwrap().expect("item is missing");
+        assert_eq!(item.name, "Onion Soup");
+    }
+
+    soup.name = "Leek Soup".to_string();
+    laptop.update(&soup).expect("failed to update item");
+    let report = laptop
+        .sync_from_with::<MenuItem, _>(&desktop, |conflict| {
+            assert_eq!(conflict.local.name, "Leek Soup");
+            assert!(conflict.local_modified > conflict.remote_modified);
+            minidb::SyncResolution::Remote
+        })
+        .expect("failed to sync");
+    assert_eq!(report.replaced, 1);
+    let item: MenuItem = laptop.get(&soup.id).unwrap().expect("item is missing");
+    assert_eq!(item.name, "Onion Soup");
+}
+
+#[test]
+fn test_minidb_export_record() {
+    let source_file = NamedTempFile::new().expect("failed to create temp file");
+    let target_file = NamedTempFile::new().expect("failed to create temp file");
+    let source = MiniDB::builder(source_file.path())
+        .table::<MenuItem>()
+        .build()
+        .expect("failed to build store");
+    let target = MiniDB::builder(target_file.path())
+        .table::<MenuItem>()
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut soup = MenuItem {
+        id: String::new(),
+        name: "Soup".to_string(),
+    };
+    source.insert(&mut soup).expect("failed to insert item");
+
+    let mut blob = Vec::new();
+    assert!(
+        !source
+            .export_record::<MenuItem, _>("missing", &mut blob)
+            .expect("failed to export record")
+    );
+    assert!(blob.is_empty());
+    assert!(
+        source
+            .export_record::<MenuItem, _>(&soup.id, &mut blob)
+            .expect("failed to export record")
+    );
+
+    let imported: MenuItem = target
+        .import_record(blob.as_slice())
+        .expect("failed to import record");
+    assert_eq!(imported.id, soup.id);
+    let stored: MenuItem = target.get(&soup.id).unwrap().expect("item is missing");
+    assert_eq!(stored.name, "Soup");
+
+    assert!(matches!(
+        target.import_record::<Restaurant, _>(blob.as_slice()),
+        Err(minidb::Error::InvalidExport(_))
+    ));
+    assert!(
+        target
+            .import_record::<MenuItem, _>(&b"garbage"[..])
+            .is_err()
+    );
+
+    soup.name = "Onion Soup".to_string();
+    source.update(&soup).expect("failed to update item");
+    blob.clear();
+    source
+        .export_record::<MenuItem, _>(&soup.id, &mut blob)
+        .expect("failed to export record");
+
+    assert!(
+        target
+            .import_record_with::<MenuItem, _>(blob.as_slice(), ImportPolicy::Skip)
+            .expect("failed to import record")
+            .is_none()
+    );
+    assert!(matches!(
+        target.import_record_with::<MenuItem, _>(blob.as_slice(), ImportPolicy::Fail),
+        Err(minidb::Error::RecordExists { .. })
+    ));
+    let stored: MenuItem = target.get(&soup.id).unwrap().expect("item is missing");
+    assert_eq!(stored.name, "Soup");
+
+    let separator = " / ".to_string();
+    let merged = target
+        .import_record_with::<MenuItem, _>(
+            blob.as_slice(),
+            ImportPolicy::merge(move |existing: MenuItem, imported: MenuItem| MenuItem {
+                id: String::new(),
+                name: format!("{}{separator}{}", existing.name, imported.name),
+            }),
+        )
+        .expect("failed to import record")
+        .expect("record was skipped");
+    assert_eq!(merged.id, soup.id);
+    let stored: MenuItem = target.get(&soup.id).unwrap().expect("item is missing");
+    assert_eq!(stored.name, "Soup / Onion Soup");
+
+    target
+        .import_record_with::<MenuItem, _>(blob.as_slice(), ImportPolicy::Overwrite)
+        .expect("failed to import record");
+    let stored: MenuItem = target.get(&soup.id).unwrap().expect("item is missing");
+    assert_eq!(stored.name, "Onion Soup");
+}
+
+#[test]
+fn test_minidb_claim() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    assert!(
+        db.claim::<Restaurant>("missing", Duration::from_secs(30))
+            .expect("failed to claim")
+            .is_none()
+    );
+
+    let mut first = Restaurant { id: String::new() };
+    let mut second = Restaurant { id: String::new() };
+    db.insert(&mut first).expect("failed to insert restaurant");
+    db.insert(&mut second).expect("failed to insert restaurant");
+
+    let lease = db
+        .claim::<Restaurant>(&first.id, Duration::from_secs(30))
+        .expect("failed to claim")
+        .expect("restaurant should be claimable");
+    assert_eq!(lease.id(), first.id);
+    assert!(db.is_claimed::<Restaurant>(&first.id).unwrap());
+    assert!(
+        db.claim::<Restaurant>(&first.id, Duration::from_secs(30))
+            .expect("failed to claim")
+            .is_none()
+    );
+
+    let (next, next_lease) = db
+        .claim_next::<Restaurant>(Duration::from_millis(10))
+        .expect("failed to claim")
+        .expect("a restaurant should be claimable");
+    assert_eq!(next.id, second.id);
+    assert!(
+        db.claim_next::<Restaurant>(Duration::from_secs(30))
+            .expect("failed to claim")
+            .is_none()
+    );
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!db.is_claimed::<Restaurant>(&second.id).unwrap());
+    let reclaimed = db
+        .claim::<Restaurant>(&second.id, Duration::from_secs(30))
+        .expect("failed to claim")
+        .expect("expired lease should be claimable");
+    assert!(!db.release(&next_lease).expect("failed to release"));
+    assert!(db.release(&reclaimed).expect("failed to release"));
+
+    assert!(db.release(&lease).expect("failed to release"));
+    assert!(!db.release(&lease).expect("failed to release"));
+    assert!(!db.is_claimed::<Restaurant>(&first.id).unwrap());
+
+    // removing a record drops its lease, so a new record under the same ID isn't claimed
+    let lease = db
+        .claim::<Restaurant>(&first.id, Duration::from_secs(30))
+        .expect("failed to claim")
+        .expect("restaurant should be claimable");
+    db.remove::<Restaurant>(&first.id)
+        .expect("failed to remove restaurant");
+    db.insert_with_id(&mut Restaurant { id: String::new() }, &first.id)
+        .expect("failed to insert restaurant");
+    assert!(!db.is_claimed::<Restaurant>(&first.id).unwrap());
+    assert!(!db.release(&lease).expect("failed to release"));
+}
+
+#[test]
+fn test_minidb_find() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut items = menu_items();
+    db.insert_many(&mut items)
+        .expect("failed to insert menu items");
+
+    let mut pricey: Vec<String> = db
+        .find::<MenuItemV1, _>(|item| item.price > 10)
+        .expect("failed to find menu items")
+        .into_iter()
+        .map(|item| item.name)
+        .collect();
+    pricey.sort();
+    assert_eq!(pricey, vec!["Pizza", "Steak"]);
+
+    assert!(
+        db.find::<MenuItemV1, _>(|item| item.price > 100)
+            .expect("failed to find menu items")
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_minidb_count() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut items = menu_items();
+    db.insert_many(&mut items)
+        .expect("failed to insert menu items");
+
+    assert_eq!(db.count::<MenuItemV1>().expect("failed to count"), 3);
+    assert_eq!(
+        db.count_where::<MenuItemV1, _>(|item| item.price > 10)
+            .expect("failed to count"),
+        2
+    );
+}
+
+#[test]
+fn test_minidb_all_sorted() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut items = menu_items();
+    db.insert_many(&mut items)
+        .expect("failed to insert menu items");
+
+    let by_price: Vec<u32> = db
+        .all_sorted::<MenuItemV1, _, _>(|item| item.price)
+        .expect("failed to sort menu items")
+        .into_iter()
+        .map(|item| item.price)
+        .collect();
+    assert_eq!(by_price, vec![6, 12, 25]);
+}
+
+#[test]
+fn test_minidb_aggregate() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut items = menu_items();
+    db.insert_many(&mut items)
+        .expect("failed to insert menu items");
+
+    let prices = db
+        .aggregate::<MenuItemV1, _, _>(|item| item.price)
+        .expect("failed to aggregate prices");
+    assert_eq!(prices.count(), 3);
+    assert_eq!(prices.sum(), 43);
+    assert_eq!(prices.min(), Some(6));
+    assert_eq!(prices.max(), Some(25));
+    assert!((prices.avg().unwrap() - 43.0 / 3.0).abs() < f64::EPSILON);
+
+    let pricey = db
+        .query::<MenuItemV1>()
+        .filter(minidb::Filter::new(|item: &MenuItemV1| item.price > 100))
+        .aggregate(|item| item.price)
+        .expect("failed to aggregate prices");
+    assert_eq!(pricey.count(), 0);
+    assert_eq!(pricey.sum(), 0);
+    assert_eq!(pricey.max(), None);
+    assert_eq!(pricey.avg(), None);
+
+    let saturated = db
+        .aggregate::<MenuItemV1, _, _>(|item| u64::from(item.price) * (u64::MAX / 30))
+        .expect("failed to aggregate prices");
+    assert_eq!(saturated.sum(), u64::MAX);
+    assert!(saturated.avg().unwrap() > u64::MAX as f64 / 3.0);
+}
+
+#[test]
+fn test_minidb_group_by() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut items = menu_items();
+    db.insert_many(&mut items)
+        .expect("failed to insert menu items");
+
+    let by_range = db
+        .query::<MenuItemV1>()
+        .group_by(|item| item.price > 10)
+        .count()
+        .expect("failed to group menu items");
+    assert_eq!(
+        by_range.into_iter().collect::<Vec<_>>(),
+        [(false, 1), (true, 2)]
+    );
+
+    let totals = db
+        .query::<MenuItemV1>()
+        .group_by(|item| item.price > 10)
+        .aggregate(|item| item.price)
+        .expect("failed to group menu items");
+    assert_eq!(totals[&true].sum(), 37);
+    assert_eq!(totals[&false].max(), Some(6));
+}
+
+#[test]
+fn test_minidb_delete_where() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut items = menu_items();
+    db.insert_many(&mut items)
+        .expect("failed to insert menu items");
+
+    let removed = db
+        .delete_where::<MenuItemV1, _>(|item| item.price > 10)
+        .expect("failed to delete menu items");
+    assert_eq!(removed, 2);
+
+    let left: Vec<MenuItemV1> = db.all().expect("failed to get menu items");
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].name, "Soup");
+
+    assert_eq!(
+        db.delete_where::<MenuItemV1, _>(|item| item.price > 10)
+            .expect("failed to delete menu items"),
+        0
+    );
+}
+
+#[test]
+fn test_minidb_update_where() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut items = menu_items();
+    db.insert_many(&mut items)
+        .expect("failed to insert menu items");
+
+    let updated = db
+        .update_where::<MenuItemV1, _, _>(
+            |item| item.price > 10,
+            |item| {
+                item.price -= 2;
+                item.id = "ignored".to_string();
+            },
+        )
+        .expect("failed to update menu items");
+    assert_eq!(updated, 2);
+
+    let mut prices: Vec<u32> = db
+        .all::<MenuItemV1>()
+        .expect("failed to get menu items")
+        .into_iter()
+        .map(|item| item.price)
+        .collect();
+    prices.sort_unstable();
+    assert_eq!(prices, vec![6, 10, 23]);
+    assert!(db.get::<MenuItemV1>("ignored").unwrap().is_none());
+}
+
+#[test]
+fn test_minidb_all_lenient() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    let mut good = MenuItemV1 {
+        id: String::new(),
+        name: "Pizza".to_string(),
+        price: 12,
+    };
+    db.insert(&mut good).expect("failed to insert menu item");
+    let mut truncated = MenuItem {
+        id: String::new(),
+        name: "Soup".to_string(),
+    };
+    db.insert(&mut truncated)
+        .expect("failed to insert menu item");
+
+    assert!(db.all::<MenuItemV1>().is_err());
+
+    let report = db
+        .all_lenient::<MenuItemV1>()
+        .expect("failed to scan menu items");
+    assert!(!report.is_clean());
+    assert_eq!(report.items.len(), 1);
+    assert_eq!(report.items[0].id, good.id);
+    assert_eq!(report.corrupted.len(), 1);
+    assert_eq!(report.corrupted[0].0, truncated.id);
+}
+
+#[test]
+fn test_minidb_page() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    for id in ["a", "b", "c", "d", "e"] {
+        db.insert_with_id(&mut Restaurant { id: String::new() }, id)
+            .expect("failed to insert restaurant");
+    }
+
+    let mut cursor = None;
+    let mut pages = Vec::new();
+    loop {
+        let page = db
+            .page::<Restaurant>(cursor.as_deref(), 2)
+            .expect("failed to get page");
+        pages.push(page.items.into_iter().map(|r| r.id).collect::<Vec<_>>());
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(pages, vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+
+    let page = db.page_at::<Restaurant>(1, 3).expect("failed to get page");
+    let ids: Vec<String> = page.items.into_iter().map(|r| r.id).collect();
+    assert_eq!(ids, vec!["b", "c", "d"]);
+    assert_eq!(page.next_cursor.as_deref(), Some("d"));
+
+    let page = db.page_at::<Restaurant>(3, 2).expect("failed to get page");
+    assert_eq!(page.items.len(), 2);
+    assert!(page.next_cursor.is_none());
+}
+
+#[derive(Default)]
+struct MapCache {
+    entries: std::sync::Mutex<HashMap<(String, String, String), Vec<u8>>>,
+    hits: std::sync::atomic::AtomicUsize,
+}
+
+impl minidb::CacheLayer for MapCache {
+    fn get(&self, namespace: &str, table: &str, id: &str) -> Option<Vec<u8>> {
+        let bytes = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(&(namespace.to_string(), table.to_string(), id.to_string()))
+            .cloned();
+        if bytes.is_some() {
+            self.hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+        bytes
+    }
+
+    fn put(&self, namespace: &str, table: &str, id: &str, bytes: &[u8]) {
+        self.entries.lock().unwrap().insert(
+            (namespace.to_string(), table.to_string(), id.to_string()),
+            bytes.to_vec(),
+        );
+    }
+
+    fn invalidate(&self, namespace: &str, table: &str, id: &str) {
+        self.entries.lock().unwrap().remove(&(
+            namespace.to_string(),
+            table.to_string(),
+            id.to_string(),
+        ));
+    }
+}
+
+#[test]
+fn test_minidb_cache_layer() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let cache = std::sync::Arc::new(MapCache::default());
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItem>()
+        .cache_layer(cache.clone())
+        .build()
+        .expect("failed to build store");
+
+    let mut soup = MenuItem {
+        id: String::new(),
+        name: "Soup".to_string(),
+    };
+    db.insert(&mut soup).expect("failed to insert item");
+    assert_eq!(cache.entries.lock().unwrap().len(), 1);
+
+    let item: MenuItem = db.get(&soup.id).unwrap().expect("item is missing");
+    assert_eq!(item.name, "Soup");
+    assert_eq!(cache.hits.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+    let failed = db.transaction(|txn| {
+        soup.name = "Stew".to_string();
+        txn.update(&soup)?;
+        Err::<(), _>(minidb::Error::EmptyID)
+    });
+    assert!(failed.is_err());
+    let item: MenuItem = db.get(&soup.id).unwrap().expect("item is missing");
+    assert_eq!(item.name, "Soup");
+
+    db.remove::<MenuItem>(&soup.id)
+        .expect("failed to remove item");
+    assert!(cache.entries.lock().unwrap().is_empty());
+    assert!(db.get::<MenuItem>(&soup.id).unwrap().is_none());
+
+    // another database sharing the cache doesn't see the records of the first one
+    let other_file = NamedTempFile::new().expect("failed to create temp file");
+    let other = MiniDB::builder(other_file.path())
+        .table::<MenuItem>()
+        .cache_layer(cache.clone())
+        .build()
+        .expect("failed to build store");
+    db.insert(&mut soup).expect("failed to insert item");
+    assert!(other.get::<MenuItem>(&soup.id).unwrap().is_none());
+
+    // reads don't fill the cache
+    cache.entries.lock().unwrap().clear();
+    assert!(db.get::<MenuItem>(&soup.id).unwrap().is_some());
+    assert!(cache.entries.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_minidb_outbox() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    assert!(db.outbox().poll(10).expect("failed to poll").is_empty());
+
+    let mut restaurant = Restaurant { id: String::new() };
+    let failed: Result<(), minidb::Error> = db.transaction(|txn| {
+        txn.insert(&mut restaurant)?;
+        txn.publish("restaurants", "opened")?;
+        Err(minidb::Error::EmptyID)
+    });
+    assert!(failed.is_err());
+    assert!(db.outbox().poll(10).expect("failed to poll").is_empty());
+
+    db.transaction(|txn| {
+        txn.insert(&mut restaurant)?;
+        txn.publish("restaurants", "opened")?;
+        txn.publish("audit", &(1u8, restaurant.id.clone()))
+    })
+    .expect("failed to publish");
+
+    let events = db.outbox().poll(1).expect("failed to poll");
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].topic, "restaurants");
+    assert_eq!(events[0].decode::<String>().unwrap(), "opened");
+
+    assert!(db.outbox().ack(events[0].seq).expect("failed to ack"));
+    assert!(!db.outbox().ack(events[0].seq).expect("failed to ack"));
+
+    let events = db.outbox().poll(10).expect("failed to poll");
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].topic, "audit");
+    assert_eq!(
+        events[0].decode::<(u8, String)>().unwrap(),
+        (1, restaurant.id.clone())
+    );
+
+    db.outbox().ack(events[0].seq).expect("failed to ack");
+    let seq = db
+        .transaction(|txn| txn.publish("restaurants", "closed"))
+        .expect("failed to publish");
+    assert!(seq > events[0].seq);
+}
+
+#[test]
+fn test_minidb_snapshot() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItem>()
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut restaurant = Restaurant { id: String::new() };
+    db.insert(&mut restaurant)
+        .expect("failed to insert restaurant");
+    for name in ["Pizza", "Soup"] {
+        let mut item = MenuItem {
+            id: String::new(),
+            name: name.to_string(),
+        };
+        db.insert(&mut item).expect("failed to insert menu item");
+    }
+
+    let mut bundle = Vec::new();
+    let written = db
+        .snapshot()
+        .table::<MenuItem>()
+        .table::<Restaurant>()
+        .write_ndjson(&mut bundle)
+        .expect("failed to write snapshot");
+    assert_eq!(written, 3);
+
+    let lines: Vec<serde_json::Value> = String::from_utf8(bundle)
+        .expect("snapshot isn't UTF-8")
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("line isn't JSON"))
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["table"], "menu");
+    assert!(["Pizza", "Soup"].contains(&lines[1]["record"]["name"].as_str().unwrap()));
+    assert_eq!(lines[2]["table"], "restaurants");
+    assert_eq!(lines[2]["record"]["id"], restaurant.id.as_str());
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn test_minidb_bench_profile() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItem>()
+        .build()
+        .expect("failed to build store");
+
+    let sample = MenuItem {
+        id: String::new(),
+        name: "Pizza".to_string(),
+    };
+    let profile = db.bench_profile(&sample, 5).expect("failed to profile");
+    assert_eq!(profile.iterations, 5);
+    assert!(profile.commit > Duration::ZERO);
+    assert_eq!(db.count::<MenuItem>().expect("failed to count"), 0);
+}
+
+#[test]
+fn test_minidb_get_with_repair() {
+    let primary_file = NamedTempFile::new().expect("failed to create temp file");
+    let replica_file = NamedTempFile::new().expect("failed to create temp file");
+    let primary = MiniDB::builder(primary_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+    let replica = MiniDB::builder(replica_file.path())
+        .table::<MenuItemV1>()
+        .build()
+        .expect("failed to build store");
+
+    // a record written by an older model can't be read as a `MenuItemV1` anymore
+    let mut damaged = MenuItem {
+        id: String::new(),
+        name: "Soup".to_string(),
+    };
+    primary
+        .transaction(|txn| txn.insert_with_id(&mut damaged, "soup"))
+        .expect("failed to insert menu item");
+    replica
+        .transaction(|txn| {
+            for (id, price) in [("soup", 6), ("pizza", 12)] {
+                let mut item = MenuItemV1 {
+                    id: String::new(),
+                    name: id.to_string(),
+                    price,
+                };
+                txn.insert_with_id(&mut item, id)?;
+            }
+            Ok(())
+        })
+        .expect("failed to insert menu items");
+
+    assert!(primary.get::<MenuItemV1>("soup").is_err());
+    let soup = primary
+        .get_with_repair::<MenuItemV1>("soup", &replica)
+        .expect("failed to repair menu item")
+        .expect("replica has the menu item");
+    assert_eq!(soup.price, 6);
+    assert_eq!(primary.get::<MenuItemV1>("soup").unwrap().unwrap().price, 6);
+
+    let pizza = primary
+        .get_with_repair::<MenuItemV1>("pizza", &replica)
+        .expect("failed to repair menu item");
+    assert_eq!(pizza.map(|item| item.price), Some(12));
+    assert_eq!(primary.count::<MenuItemV1>().unwrap(), 2);
+
+    assert!(
+        primary
+            .get_with_repair::<MenuItemV1>("steak", &replica)
+            .expect("failed to repair menu item")
+            .is_none()
+    );
+}
+
+#[test]
+fn test_minidb_freeze() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItem>()
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut restaurant = Restaurant { id: String::new() };
+    db.insert(&mut restaurant)
+        .expect("failed to insert restaurant");
+
+    assert!(
+        !db.is_frozen::<Restaurant>()
+            .expect("failed to check freeze")
+    );
+    db.freeze::<Restaurant>().expect("failed to freeze");
+    assert!(
+        db.is_frozen::<Restaurant>()
+            .expect("failed to check freeze")
+    );
+    assert!(!db.is_frozen::<MenuItem>().expect("failed to check freeze"));
+
+    let mut other = Restaurant { id: String::new() };
+    assert!(matches!(
+        db.insert(&mut other),
+        Err(minidb::Error::TableFrozen(name)) if name == "restaurants"
+    ));
+    assert!(matches!(
+        db.remove::<Restaurant>(&restaurant.id),
+        Err(minidb::Error::TableFrozen(_))
+    ));
+    assert!(
+        db.get::<Restaurant>(&restaurant.id)
+            .expect("failed to get")
+            .is_some()
+    );
+
+    let mut item = MenuItem {
+        id: String::new(),
+        name: "Soup".to_string(),
+    };
+    db.insert(&mut item).expect("failed to insert menu item");
+
+    drop(db);
+    let db = MiniDB::builder(temp_file.path())
+        .table::<MenuItem>()
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to reopen store");
+    assert!(
+        db.is_frozen::<Restaurant>()
+            .expect("failed to check freeze")
+    );
+
+    assert!(db.unfreeze::<Restaurant>().expect("failed to unfreeze"));
+    assert!(!db.unfreeze::<Restaurant>().expect("failed to unfreeze"));
+    db.insert(&mut other)
+        .expect("failed to insert after unfreeze");
+    assert_eq!(db.count::<Restaurant>().expect("failed to count"), 2);
+}
+
+#[test]
+fn test_minidb_pin() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut restaurant = Restaurant { id: String::new() };
+    db.insert(&mut restaurant)
+        .expect("failed to insert restaurant");
+
+    assert!(!db.pin::<Restaurant>("missing").expect("failed to pin"));
+    assert!(db.pin::<Restaurant>(&restaurant.id).expect("failed to pin"));
+    assert!(
+        db.is_pinned::<Restaurant>(&restaurant.id)
+            .expect("failed to check pin")
+    );
+
+    assert!(matches!(
+        db.remove::<Restaurant>(&restaurant.id),
+        Err(minidb::Error::RecordPinned { id, .. }) if id == restaurant.id
+    ));
+    assert!(matches!(
+        db.delete_where::<Restaurant, _>(|_| true),
+        Err(minidb::Error::RecordPinned { .. })
+    ));
+    db.update(&restaurant)
+        .expect("failed to update pinned record");
+    assert_eq!(db.count::<Restaurant>().expect("failed to count"), 1);
+
+    assert!(
+        db.unpin::<Restaurant>(&restaurant.id)
+            .expect("failed to unpin")
+    );
+    assert!(
+        !db.unpin::<Restaurant>(&restaurant.id)
+            .expect("failed to unpin")
+    );
+    assert!(
+        db.remove::<Restaurant>(&restaurant.id)
+            .expect("failed to remove")
+            .is_some()
+    );
+}
+
+#[test]
+fn test_minidb_pin_remove_unpinned() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to build store");
+
+    let mut restaurant = Restaurant { id: String::new() };
+    db.insert(&mut restaurant)
+        .expect("failed to insert restaurant");
+    db.remove::<Restaurant>(&restaurant.id)
+        .expect("failed to remove restaurant");
+    drop(db);
+
+    // removing a record in a database that never pinned or claimed one doesn't create their tables
+    let raw = redb::Database::open(temp_file.path()).expect("failed to open raw database");
+    let txn = raw.begin_read().expect("failed to begin read");
+    let tables: Vec<String> = txn
+        .list_tables()
+        .expect("failed to list tables")
+        .map(|table| table.name().to_string())
+        .collect();
+    assert!(!tables.iter().any(|name| name == "pins" || name == "leases"));
+}
+
+#[test]
+fn test_minidb_env_overrides() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let vars = |pairs: &[(&str, &str)]| {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+            .collect();
+        move |name: &str| map.get(name).cloned()
+    };
+
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Thumbnail>()
+        .max_record_size(1024 * 1024)
+        .with_overrides_from(vars(&[
+            ("MINIDB_MAX_RECORD_SIZE", "1024"),
+            ("MINIDB_LOCK_TIMEOUT", "0.5"),
+        ]))
+        .build()
+        .expect("failed to build store");
+
+    let mut big = Thumbnail {
+        id: String::new(),
+        data: vec![1; 2048],
+    };
+    assert!(matches!(
+        db.insert(&mut big),
+        Err(minidb::Error::RecordTooLarge { max: 1024, .. })
+    ));
+    drop(db);
+
+    assert!(matches!(
+        MiniDB::builder(temp_file.path())
+            .with_overrides_from(vars(&[("MINIDB_LOCK_BACKEND", "flock")]))
+            .build(),
+        Err(minidb::Error::InvalidEnvVar { name, value }) if name == "MINIDB_LOCK_BACKEND" && value == "flock"
+    ));
+
+    // without the opt-in the limit set on the builder applies
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Thumbnail>()
+        .max_record_size(1024 * 1024)
+        .build()
+        .expect("failed to build store");
+    db.insert(&mut big).expect("failed to insert thumbnail");
+}
+
+#[test]
+fn test_minidb_relocate() {
+    let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let old_path = temp_dir.path().join("old.redb");
+    let new_path = temp_dir.path().join("new.redb");
+    let taken_path = temp_dir.path().join("taken.redb");
+    std::fs::write(&taken_path, b"").expect("failed to create file");
+
+    let mut db = MiniDB::builder(&old_path)
+        .table::<Restaurant>()
+        .lock_backend(LockBackend::PidFile)
+        .build()
+        .expect("failed to build store");
+    let mut restaurant = Restaurant { id: String::new() };
+    db.insert(&mut restaurant)
+        .expect("failed to insert restaurant");
+
+    assert!(db.relocate(&taken_path).is_err());
+    assert!(old_path.exists());
+    assert!(
+        db.get::<Restaurant>(&restaurant.id)
+            .expect("failed to get")
+            .is_some()
+    );
+
+    // a write that panicked earlier still gets checked on the next open
+    std::fs::write(temp_dir.path().join("old.redb.recover"), b"1").expect("failed to write marker");
+    db.relocate(&new_path).expect("failed to relocate");
+    assert!(!old_path.exists());
+    assert!(!temp_dir.path().join("old.redb.lock").exists());
+    assert!(temp_dir.path().join("new.redb.lock").exists());
+    assert!(!temp_dir.path().join("old.redb.recover").exists());
+    assert!(temp_dir.path().join("new.redb.recover").exists());
+    assert!(
+        db.get::<Restaurant>(&restaurant.id)
+            .expect("failed to get")
+            .is_some()
+    );
+
+    let mut other = Restaurant { id: String::new() };
+    db.insert(&mut other)
+        .expect("failed to insert after relocating");
+    drop(db);
+
+    let db = MiniDB::builder(&new_path)
+        .table::<Restaurant>()
+        .build()
+        .expect("failed to reopen store");
+    assert_eq!(db.count::<Restaurant>().expect("failed to count"), 2);
+}
+
+#[test]
+fn test_minidb_sortable_ids() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    let db = MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .sortable_ids(true)
+        .build()
+        .expect("failed to build store");
+
+    assert!(
+        db.first::<Restaurant>()
+            .expect("failed to get first")
+            .is_none()
+    );
+    assert!(
+        db.last::<Restaurant>()
+            .expect("failed to get last")
+            .is_none()
+    );
+
+    let mut ids = Vec::new();
+    for _ in 0..50 {
+        let mut restaurant = Restaurant { id: String::new() };
+        db.insert(&mut restaurant)
+            .expect("failed to insert restaurant");
+        ids.push(restaurant.id);
+    }
+    let mut batch: Vec<Restaurant> = (0..50).map(|_| Restaurant { id: String::new() }).collect();
+    db.insert_many(&mut batch)
+        .expect("failed to insert restaurants");
+    ids.extend(batch.into_iter().map(|restaurant| restaurant.id));
+
+    assert!(ids.is_sorted(), "IDs aren't in insertion order");
+    let first = db
+        .first::<Restaurant>()
+        .expect("failed to get first")
+        .expect("table is empty");
+    let last = db
+        .last::<Restaurant>()
+        .expect("failed to get last")
+        .expect("table is empty");
+    assert_eq!(first.id, ids[0]);
+    assert_eq!(last.id, ids[99]);
+    drop(db);
+
+    // random IDs would sort in between the time-ordered ones
+    assert!(matches!(
+        MiniDB::builder(temp_file.path())
+            .table::<Restaurant>()
+            .build(),
+        Err(minidb::Error::OptionMismatch { option, stored: true }) if option == "sortable_ids"
+    ));
+    MiniDB::builder(temp_file.path())
+        .table::<Restaurant>()
+        .sortable_ids(true)
+        .build()
+        .expect("failed to reopen store");
+}
@@ -0,0 +1,161 @@
+// This Source Code Form is subject to the terms of the
+// Mozilla Public License, v. 2.0. If a copy of the MPL was not distributed
+// with this file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{path::Path, process::Command, time::Duration};
+
+use minidb::{MiniDB, Table, Transaction};
+use rand::{RngExt, seq::IndexedRandom};
+use redb::TableDefinition;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+/// The environment variable that turns the test binary into a child process working on the given database
+const CHILD_DB_VAR: &str = "MINIDB_MULTIPROCESS_DB";
+
+const CHILDREN: usize = 4;
+const ITERATIONS: u64 = 25;
+
+#[derive(Serialize, Deserialize)]
+struct Counter {
+    id: String,
+    value: u64,
+}
+
+impl Table for Counter {
+    const TABLE: TableDefinition<'_, &'static str, &[u8]> = TableDefinition::new("counters");
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    id: String,
+    child: u32,
+    payload: Vec<u8>,
+}
+
+impl Table for Entry {
+    const TABLE: TableDefinition<'_, &'static str, &[u8]> = TableDefinition::new("entries");
+
+    fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+}
+
+/// Opens the shared database, waiting for the other processes to close it
+fn open(path: &Path) -> MiniDB {
+    MiniDB::builder(path)
+        .table::<Counter>()
+        .table::<Entry>()
+        .lock_timeout(Duration::from_secs(60))
+        .build()
+        .expect("failed to open shared database")
+}
+
+/// Adds `delta` to a counter inside the transaction, creating it if needed
+fn bump(txn: &Transaction, id: &str, delta: i64) -> Result<(), minidb::Error> {
+    txn.get_or_insert_with(id, || Counter {
+        id: String::new(),
+        value: 0,
+    })?;
+    txn.update_where::<Counter, _, _>(
+        |counter| counter.id == id,
+        |counter| counter.value = counter.value.saturating_add_signed(delta),
+    )?;
+    Ok(())
+}
+
+fn counter(db: &MiniDB, id: &str) -> u64 {
+    db.get::<Counter>(id)
+        .expect("failed to get counter")
+        .map_or(0, |counter| counter.value)
+}
+
+#[test]
+fn test_multiprocess_crud() {
+    let temp_file = NamedTempFile::new().expect("failed to create temp file");
+    drop(open(temp_file.path()));
+
+    let exe = std::env::current_exe().expect("failed to find the test binary");
+    let children: Vec<_> = (0..CHILDREN)
+        .map(|_| {
+            Command::new(&exe)
+                .args(["multiprocess_child", "--exact", "--ignored", "--nocapture"])
+                .env(CHILD_DB_VAR, temp_file.path())
+                .spawn()
+                .expect("failed to spawn child process")
+        })
+        .collect();
+
+    for mut child in children {
+        let status = child.wait().expect("failed to wait for child process");
+        assert!(status.success(), "child process failed: {status}");
+    }
+
+    let mut db = open(temp_file.path());
+    assert!(db.check_integrity().expect("failed to check integrity"));
+    assert_eq!(counter(&db, "ops"), CHILDREN as u64 * ITERATIONS);
+
+    let entries = db.all::<Entry>().expect("entries are corrupted");
+    assert_eq!(entries.len() as u64, counter(&db, "entries"));
+}
+
+/// One of the processes spawned by [`test_multiprocess_crud`], does nothing when run on its own
+#[test]
+#[ignore = "spawned by test_multiprocess_crud"]
+fn multiprocess_child() {
+    let Some(path) = std::env::var_os(CHILD_DB_VAR) else {
+        return;
+    };
+    let path = Path::new(&path);
+    let me = std::process::id();
+    let mut rng = rand::rng();
+
+    for _ in 0..ITERATIONS {
+        let db = open(path);
+
+        // the database stays open until the end of the iteration, so no other process changes our entries meanwhile
+        let mine: Vec<String> = db
+            .find::<Entry, _>(|entry| entry.child == me)
+            .expect("failed to find entries")
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect();
+        let victim = if rng.random_bool(0.3) {
+            mine.choose(&mut rng).cloned()
+        } else {
+            None
+        };
+
+        db.transaction(|txn| {
+            bump(txn, "ops", 1)?;
+
+            if let Some(id) = &victim {
+                txn.remove::<Entry>(id)?;
+                bump(txn, "entries", -1)
+            } else {
+                let mut entry = Entry {
+                    id: String::new(),
+                    child: me,
+                    payload: (0..rng.random_range(0..256))
+                        .map(|_| rng.random())
+                        .collect(),
+                };
+                txn.insert(&mut entry)?;
+                bump(txn, "entries", 1)
+            }
+        })
+        .expect("failed to write");
+    }
+}